@@ -0,0 +1,16 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lua_bytecode::{Bytecode, ParseOptions, lua51::LuaBytecode};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(mut bytecode) =
+        <Bytecode as LuaBytecode>::from_with_options(data, ParseOptions::hardened())
+    else {
+        return;
+    };
+
+    if let Ok(data) = bytecode.write() {
+        let _ = <Bytecode as LuaBytecode>::from_with_options(&data, ParseOptions::hardened());
+    }
+});
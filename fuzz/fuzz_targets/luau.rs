@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lua_bytecode::{ParseOptions, luau::LuaBytecode};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(bytecode) = LuaBytecode::from_with_options(data, ParseOptions::hardened()) else {
+        return;
+    };
+
+    if let Ok(data) = bytecode.write() {
+        let _ = LuaBytecode::from_with_options(&data, ParseOptions::hardened());
+    }
+});
@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+
+use crate::{Constant, Error, builder::Label};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Token {
+    Word(String),
+    String(Vec<u8>),
+}
+
+impl Token {
+    pub(crate) fn word(&self) -> Option<&str> {
+        match self {
+            Token::Word(word) => Some(word),
+            Token::String(_) => None,
+        }
+    }
+}
+
+pub(crate) fn error(line: usize, message: impl Into<String>) -> Error {
+    Error::Assembly {
+        line,
+        message: message.into(),
+    }
+}
+
+/// Turns an operand the builder refused into an error at `line`.
+pub(crate) fn operand_error(line: usize) -> impl Fn(Error) -> Error {
+    move |err| match err {
+        Error::OperandOutOfRange { operand, .. } => {
+            error(line, format!("{operand} operand is out of range"))
+        }
+        err => err,
+    }
+}
+
+/// Splits a line into words and string literals, `;` and `--` start a comment.
+pub(crate) fn tokenize(line: usize, text: &str) -> Result<Vec<Token>, Error> {
+    let bytes = text.as_bytes();
+    let is_separator = |byte: u8| matches!(byte, b' ' | b'\t' | b'\r' | b',');
+
+    let mut tokens = Vec::new();
+    let mut position = 0;
+    while position < bytes.len() {
+        match bytes[position] {
+            byte if is_separator(byte) => position += 1,
+            b';' => break,
+            b'-' if bytes.get(position + 1) == Some(&b'-') => break,
+
+            quote @ (b'"' | b'\'') => {
+                let (string, end) = parse_string(line, bytes, position + 1, quote)?;
+                tokens.push(Token::String(string));
+                position = end;
+            }
+
+            _ => {
+                let start = position;
+                while position < bytes.len()
+                    && !is_separator(bytes[position])
+                    && !matches!(bytes[position], b';' | b'"' | b'\'')
+                {
+                    position += 1;
+                }
+
+                tokens.push(Token::Word(text[start..position].to_string()));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+// returns the string and the position after its closing quote
+fn parse_string(
+    line: usize,
+    bytes: &[u8],
+    mut position: usize,
+    quote: u8,
+) -> Result<(Vec<u8>, usize), Error> {
+    let mut string = Vec::new();
+    loop {
+        let Some(&byte) = bytes.get(position) else {
+            return Err(error(line, "unterminated string"));
+        };
+        position += 1;
+
+        if byte == quote {
+            return Ok((string, position));
+        } else if byte != b'\\' {
+            string.push(byte);
+            continue;
+        }
+
+        let Some(&escape) = bytes.get(position) else {
+            return Err(error(line, "unterminated string"));
+        };
+        position += 1;
+
+        match escape {
+            b'a' => string.push(0x07),
+            b'b' => string.push(0x08),
+            b'f' => string.push(0x0c),
+            b'n' => string.push(b'\n'),
+            b'r' => string.push(b'\r'),
+            b't' => string.push(b'\t'),
+            b'v' => string.push(0x0b),
+            b'x' => {
+                let digits = bytes.get(position..position + 2).unwrap_or_default();
+                let value = std::str::from_utf8(digits)
+                    .ok()
+                    .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                    .ok_or_else(|| error(line, "invalid \\x escape"))?;
+
+                string.push(value);
+                position += 2;
+            }
+            b'0'..=b'9' => {
+                let start = position - 1;
+                while position < bytes.len()
+                    && position - start < 3
+                    && bytes[position].is_ascii_digit()
+                {
+                    position += 1;
+                }
+
+                let digits = std::str::from_utf8(&bytes[start..position]).unwrap();
+                let value = digits
+                    .parse::<u8>()
+                    .map_err(|_| error(line, "decimal escape is too large"))?;
+                string.push(value);
+            }
+            escape => string.push(escape),
+        }
+    }
+}
+
+/// Decimal or `0x` hexadecimal integers, optionally negative.
+pub(crate) fn parse_int(word: &str) -> Option<i64> {
+    let (negative, digits) = match word.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, word),
+    };
+
+    let value = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => i64::from_str_radix(hex, 16).ok()?,
+        None if !digits.is_empty() && digits.bytes().all(|byte| byte.is_ascii_digit()) => {
+            digits.parse::<i64>().ok()?
+        }
+        None => return None,
+    };
+
+    Some(if negative { -value } else { value })
+}
+
+pub(crate) fn parse_number(word: &str) -> Option<f64> {
+    match word {
+        "inf" => Some(f64::INFINITY),
+        "-inf" => Some(f64::NEG_INFINITY),
+        "nan" => Some(f64::NAN),
+        _ => parse_int(word)
+            .map(|value| value as f64)
+            .or_else(|| word.parse::<f64>().ok()),
+    }
+}
+
+/// `nil`, `true`, `false` or a number.
+pub(crate) fn literal(word: &str) -> Option<Constant> {
+    match word {
+        "nil" => Some(Constant::Nil),
+        "true" => Some(Constant::Bool(true)),
+        "false" => Some(Constant::Bool(false)),
+        _ => parse_number(word).map(Constant::Number),
+    }
+}
+
+/// Labels by name, created on first use and bound where they are defined.
+#[derive(Default)]
+pub(crate) struct NamedLabels {
+    labels: HashMap<String, Label>,
+    // pc of every defined label
+    defined: HashMap<String, u32>,
+    // the first line using each label
+    uses: Vec<(usize, String)>,
+}
+
+impl NamedLabels {
+    /// The label to bind at `pc`.
+    pub(crate) fn define(
+        &mut self,
+        line: usize,
+        name: &str,
+        pc: u32,
+        create: impl FnOnce() -> Label,
+    ) -> Result<Label, Error> {
+        if self.defined.insert(name.to_string(), pc).is_some() {
+            return Err(error(line, format!("label `{name}` is already defined")));
+        }
+
+        Ok(*self.labels.entry(name.to_string()).or_insert_with(create))
+    }
+
+    pub(crate) fn reference(
+        &mut self,
+        line: usize,
+        name: &str,
+        create: impl FnOnce() -> Label,
+    ) -> Label {
+        if !self.labels.contains_key(name) {
+            self.uses.push((line, name.to_string()));
+        }
+
+        *self.labels.entry(name.to_string()).or_insert_with(create)
+    }
+
+    /// Fails on the first label that is used but never defined.
+    pub(crate) fn check(&self) -> Result<(), Error> {
+        match self
+            .uses
+            .iter()
+            .find(|(_, name)| !self.defined.contains_key(name))
+        {
+            Some((line, name)) => Err(error(*line, format!("unknown label `{name}`"))),
+            None => Ok(()),
+        }
+    }
+
+    /// The pc of a defined label, or a pc written as a number.
+    pub(crate) fn position(&self, line: usize, name: &str) -> Result<u32, Error> {
+        match parse_int(name) {
+            Some(pc) if pc >= 0 => Ok(pc as u32),
+            _ => self
+                .defined
+                .get(name)
+                .copied()
+                .ok_or_else(|| error(line, format!("unknown label `{name}`"))),
+        }
+    }
+}
@@ -1,26 +1,95 @@
 use std::io::{Read, Write};
 
+use crate::Error;
+#[cfg(any(feature = "lua51", feature = "lua52"))]
+use crate::{Header, ParseOptions, RawLuaString};
+
+pub trait Primitive: Copy {
+    const SIZE: usize;
+
+    fn decode(bytes: &[u8], big_endian: bool) -> Self;
+    fn encode(self, bytes: &mut [u8], big_endian: bool);
+}
+
+macro_rules! primitive {
+    ($($kind:ty),*) => {
+        $(
+            impl Primitive for $kind {
+                const SIZE: usize = std::mem::size_of::<$kind>();
+
+                fn decode(bytes: &[u8], big_endian: bool) -> Self {
+                    let mut array = [0; std::mem::size_of::<$kind>()];
+                    array.copy_from_slice(bytes);
+
+                    if big_endian {
+                        <$kind>::from_be_bytes(array)
+                    } else {
+                        <$kind>::from_le_bytes(array)
+                    }
+                }
+
+                fn encode(self, bytes: &mut [u8], big_endian: bool) {
+                    if big_endian {
+                        bytes.copy_from_slice(&self.to_be_bytes());
+                    } else {
+                        bytes.copy_from_slice(&self.to_le_bytes());
+                    }
+                }
+            }
+        )*
+    };
+}
+
+primitive!(u8, i8, u16, i16, u32, i32, u64, i64, f32, f64);
+
+impl Primitive for bool {
+    const SIZE: usize = 1;
+
+    fn decode(bytes: &[u8], _: bool) -> Self {
+        bytes[0] != 0
+    }
+
+    fn encode(self, bytes: &mut [u8], _: bool) {
+        bytes[0] = self as u8;
+    }
+}
+
 pub struct Buffer {
     cursor: std::io::Cursor<Vec<u8>>,
+    big_endian: bool,
 }
 
 impl Buffer {
     pub fn new(data: Vec<u8>) -> Self {
         Self {
             cursor: std::io::Cursor::new(data),
+            big_endian: false,
         }
     }
 
-    pub fn read<T: Copy>(&mut self) -> T {
-        let size = std::mem::size_of::<T>();
+    pub fn set_big_endian(&mut self, big_endian: bool) {
+        self.big_endian = big_endian;
+    }
 
-        let buffer: Vec<u8> = vec![0; size];
-        let mut buffer_slice = buffer.into_boxed_slice();
+    pub fn read<T: Primitive>(&mut self) -> Result<T, Error> {
+        let mut bytes = [0; 8];
+        let bytes = &mut bytes[..T::SIZE];
+        self.read_exact(bytes)?;
 
-        self.cursor.read(&mut buffer_slice).unwrap();
+        Ok(T::decode(bytes, self.big_endian))
+    }
 
-        let value: &mut T = unsafe { &mut *(buffer_slice.as_mut_ptr() as *mut T) };
-        *value
+    pub fn read_bytes(&mut self, length: u64) -> Result<Vec<u8>, Error> {
+        if length > self.remaining() {
+            return Err(Error::UnexpectedEof {
+                offset: self.position(),
+            });
+        }
+
+        let mut bytes = vec![0; length as usize];
+        self.read_exact(&mut bytes)?;
+
+        Ok(bytes)
     }
 
     pub fn read_all(&mut self) -> Vec<u8> {
@@ -30,11 +99,16 @@ impl Buffer {
         buffer
     }
 
-    pub fn write<T: Copy>(&mut self, value: T) {
-        let size = std::mem::size_of::<T>();
-        let buffer = unsafe { std::slice::from_raw_parts_mut(&value as *const T as *mut u8, size) };
+    pub fn write<T: Primitive>(&mut self, value: T) {
+        let mut bytes = [0; 8];
+        let bytes = &mut bytes[..T::SIZE];
+        value.encode(bytes, self.big_endian);
+
+        self.write_bytes(bytes);
+    }
 
-        self.cursor.write_all(&buffer).unwrap();
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.cursor.write_all(bytes).unwrap();
     }
 
     pub fn position(&self) -> u64 {
@@ -47,6 +121,239 @@ impl Buffer {
 
     pub fn advance(&mut self, amount: u64) {
         let position = self.position();
-        self.set_position(position + amount);
+        self.set_position(position.saturating_add(amount));
+    }
+
+    pub fn remaining(&self) -> u64 {
+        (self.cursor.get_ref().len() as u64).saturating_sub(self.position())
+    }
+
+    fn read_exact(&mut self, bytes: &mut [u8]) -> Result<(), Error> {
+        let offset = self.position();
+        if (bytes.len() as u64) > self.remaining() {
+            return Err(Error::UnexpectedEof { offset });
+        }
+
+        self.cursor
+            .read_exact(bytes)
+            .map_err(|_| Error::UnexpectedEof { offset })
+    }
+}
+
+/// Ints, sizes and numbers whose width and encoding come from a lua 5.1/5.2 header.
+#[cfg(any(feature = "lua51", feature = "lua52"))]
+pub trait LuaPrimitive {
+    fn read_int(&mut self, header: &Header) -> Result<u32, Error>;
+    fn read_size_t(&mut self, header: &Header) -> Result<u64, Error>;
+    fn read_number(&mut self, header: &Header) -> Result<f64, Error>;
+    /// Reads a number of an integral header without going through `f64`.
+    fn read_integer(&mut self, header: &Header) -> Result<i64, Error>;
+    fn read_string(&mut self, header: &Header, max_length: u64) -> Result<RawLuaString, Error>;
+
+    fn write_int(&mut self, header: &Header, value: u32) -> Result<(), Error>;
+    fn write_size_t(&mut self, header: &Header, value: u64) -> Result<(), Error>;
+    /// Fails with `LossyConstant` when the header's number format can not hold `value`.
+    fn write_number(
+        &mut self,
+        header: &Header,
+        value: f64,
+        proto: u32,
+        index: u32,
+    ) -> Result<(), Error>;
+    /// Fails with `LossyConstant` when the header's number format can not hold `value`.
+    fn write_integer(
+        &mut self,
+        header: &Header,
+        value: i64,
+        proto: u32,
+        index: u32,
+    ) -> Result<(), Error>;
+    fn write_string(&mut self, header: &Header, string: &[u8]) -> Result<(), Error>;
+}
+
+#[cfg(any(feature = "lua51", feature = "lua52"))]
+impl LuaPrimitive for Buffer {
+    fn read_int(&mut self, header: &Header) -> Result<u32, Error> {
+        let offset = self.position();
+        let value = self.read_unsigned(header.int_size)?;
+
+        u32::try_from(value).map_err(|_| Error::Malformed {
+            offset,
+            reason: "int does not fit in 32 bits",
+        })
+    }
+
+    fn read_size_t(&mut self, header: &Header) -> Result<u64, Error> {
+        self.read_unsigned(header.size_t_size)
+    }
+
+    fn read_number(&mut self, header: &Header) -> Result<f64, Error> {
+        if header.is_number_integral {
+            return Ok(self.read_integer(header)? as f64);
+        }
+
+        self.read_float(header.number_size)
+    }
+
+    fn read_integer(&mut self, header: &Header) -> Result<i64, Error> {
+        self.read_signed(header.number_size)
+    }
+
+    fn read_string(&mut self, header: &Header, max_length: u64) -> Result<RawLuaString, Error> {
+        let offset = self.position();
+        let length = self.read_size_t(header)?;
+        ParseOptions::check(length, max_length, offset, "string length")?;
+
+        self.read_bytes(length)
+    }
+
+    fn write_int(&mut self, header: &Header, value: u32) -> Result<(), Error> {
+        self.write_unsigned(
+            value as u64,
+            header.int_size,
+            "value does not fit the int size",
+        )
+    }
+
+    fn write_size_t(&mut self, header: &Header, value: u64) -> Result<(), Error> {
+        self.write_unsigned(
+            value,
+            header.size_t_size,
+            "value does not fit the size_t size",
+        )
+    }
+
+    fn write_number(
+        &mut self,
+        header: &Header,
+        value: f64,
+        proto: u32,
+        index: u32,
+    ) -> Result<(), Error> {
+        let lossy = Error::LossyConstant { proto, index };
+        if header.is_number_integral {
+            // i64::MAX rounds up to 2^63, which no integer holds
+            if value.fract() != 0.0 || !(i64::MIN as f64..i64::MAX as f64).contains(&value) {
+                return Err(lossy);
+            }
+
+            return self.write_integer(header, value as i64, proto, index);
+        }
+
+        self.write_float(value, header.number_size, lossy)
+    }
+
+    fn write_integer(
+        &mut self,
+        header: &Header,
+        value: i64,
+        proto: u32,
+        index: u32,
+    ) -> Result<(), Error> {
+        let lossy = Error::LossyConstant { proto, index };
+        if !header.is_number_integral {
+            if value as f64 as i128 != value as i128 {
+                return Err(lossy);
+            }
+
+            return self.write_number(header, value as f64, proto, index);
+        }
+
+        self.write_signed(value, header.number_size, lossy)
+    }
+
+    fn write_string(&mut self, header: &Header, string: &[u8]) -> Result<(), Error> {
+        self.write_size_t(header, string.len() as u64)?;
+        self.write_bytes(string);
+
+        Ok(())
+    }
+}
+
+/// Values whose width comes from a header, in the byte order set on the buffer.
+#[cfg(any(
+    feature = "lua51",
+    feature = "lua52",
+    feature = "lua53",
+    feature = "lua54"
+))]
+impl Buffer {
+    pub(crate) fn read_unsigned(&mut self, size: u8) -> Result<u64, Error> {
+        let bytes = self.read_bytes(size as u64)?;
+        let fold = |value: u64, byte: &u8| value << 8 | *byte as u64;
+
+        if self.big_endian {
+            Ok(bytes.iter().fold(0, fold))
+        } else {
+            Ok(bytes.iter().rev().fold(0, fold))
+        }
+    }
+
+    pub(crate) fn read_signed(&mut self, size: u8) -> Result<i64, Error> {
+        let shift = 64 - size as u32 * 8;
+        Ok(((self.read_unsigned(size)? << shift) as i64) >> shift)
+    }
+
+    pub(crate) fn read_float(&mut self, size: u8) -> Result<f64, Error> {
+        match size {
+            4 => Ok(self.read::<f32>()? as f64),
+            _ => self.read::<f64>(),
+        }
+    }
+
+    pub(crate) fn write_unsigned(
+        &mut self,
+        value: u64,
+        size: u8,
+        reason: &'static str,
+    ) -> Result<(), Error> {
+        if size < 8 && value >> (size as u32 * 8) != 0 {
+            return Err(Error::Malformed {
+                offset: self.position(),
+                reason,
+            });
+        }
+
+        self.write_sized(value, size);
+        Ok(())
+    }
+
+    /// Fails with `lossy` when `value` needs more than `size` bytes.
+    pub(crate) fn write_signed(&mut self, value: i64, size: u8, lossy: Error) -> Result<(), Error> {
+        let bits = size as u32 * 8;
+        if bits < 64 && !(-(1 << (bits - 1))..1 << (bits - 1)).contains(&value) {
+            return Err(lossy);
+        }
+
+        self.write_sized(value as u64, size);
+        Ok(())
+    }
+
+    /// Fails with `lossy` when a 4-byte float can not hold `value`.
+    pub(crate) fn write_float(&mut self, value: f64, size: u8, lossy: Error) -> Result<(), Error> {
+        match size {
+            4 => {
+                let narrow = value as f32;
+                if narrow as f64 != value && !value.is_nan() {
+                    return Err(lossy);
+                }
+
+                self.write::<f32>(narrow);
+            }
+
+            _ => self.write::<f64>(value),
+        }
+
+        Ok(())
+    }
+
+    fn write_sized(&mut self, value: u64, size: u8) {
+        let bytes = if self.big_endian {
+            value.to_be_bytes()[8 - size as usize..].to_vec()
+        } else {
+            value.to_le_bytes()[..size as usize].to_vec()
+        };
+
+        self.write_bytes(&bytes);
     }
 }
@@ -0,0 +1,94 @@
+use crate::{Constant, Error};
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// every builder numbers its labels apart so a foreign label is caught
+static NEXT_BUILDER: AtomicU64 = AtomicU64::new(0);
+
+/// A jump target of a `ProtoBuilder`, it can be used before it is bound.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Label {
+    builder: u64,
+    index: u32,
+}
+
+pub(crate) struct Labels {
+    builder: u64,
+    positions: Vec<Option<u32>>,
+}
+
+impl Default for Labels {
+    fn default() -> Self {
+        Self {
+            builder: NEXT_BUILDER.fetch_add(1, Ordering::Relaxed),
+            positions: Vec::new(),
+        }
+    }
+}
+
+impl Labels {
+    pub(crate) fn create(&mut self) -> Label {
+        self.positions.push(None);
+        Label {
+            builder: self.builder,
+            index: self.positions.len() as u32 - 1,
+        }
+    }
+
+    pub(crate) fn bind(&mut self, label: Label, pc: u32) -> Result<(), Error> {
+        let index = self.index(label)?;
+        self.positions[index] = Some(pc);
+        Ok(())
+    }
+
+    pub(crate) fn position(&self, label: Label) -> Result<u32, Error> {
+        self.positions[self.index(label)?].ok_or(Error::UnboundLabel { label: label.index })
+    }
+
+    fn index(&self, label: Label) -> Result<usize, Error> {
+        match label.builder == self.builder && (label.index as usize) < self.positions.len() {
+            true => Ok(label.index as usize),
+            false => Err(Error::UnknownLabel { label: label.index }),
+        }
+    }
+}
+
+/// Index of `value` in `constants`, it is appended when no equal constant exists.
+pub(crate) fn intern(constants: &mut Vec<Constant>, value: Constant) -> u32 {
+    match constants
+        .iter()
+        .position(|constant| same_constant(constant, &value))
+    {
+        Some(index) => index as u32,
+        None => {
+            constants.push(value);
+            constants.len() as u32 - 1
+        }
+    }
+}
+
+// numbers are compared bitwise so 0 and -0 stay apart, tables are never shared
+pub(crate) fn same_constant(left: &Constant, right: &Constant) -> bool {
+    match (left, right) {
+        (Constant::Nil, Constant::Nil) => true,
+        (Constant::Bool(left), Constant::Bool(right)) => left == right,
+        #[cfg(any(feature = "lua51", feature = "luau"))]
+        (Constant::RawBool(left), Constant::RawBool(right)) => left == right,
+        (Constant::Number(left), Constant::Number(right)) => left.to_bits() == right.to_bits(),
+        #[cfg(feature = "lua51")]
+        (Constant::Integer(left), Constant::Integer(right)) => left == right,
+        (Constant::String(left), Constant::String(right)) => left == right,
+
+        #[cfg(feature = "luau")]
+        (Constant::Vector(x, y, z, w), Constant::Vector(x2, y2, z2, w2)) => {
+            [x, y, z, w].map(|value| value.to_bits())
+                == [x2, y2, z2, w2].map(|value| value.to_bits())
+        }
+        #[cfg(feature = "luau")]
+        (Constant::Closure(left), Constant::Closure(right)) => left == right,
+        #[cfg(feature = "luau")]
+        (Constant::Import(left), Constant::Import(right)) => left == right,
+
+        _ => false,
+    }
+}
@@ -1,14 +1,68 @@
 use crate::RawLuaString;
 
-#[cfg(feature = "lua51")]
+#[cfg(any(feature = "lua51", feature = "lua52", feature = "lua53"))]
 pub const LUA_CONSTANT_NIL: u8 = 0;
-#[cfg(feature = "lua51")]
+#[cfg(any(feature = "lua51", feature = "lua52", feature = "lua53"))]
 pub const LUA_CONSTANT_BOOLEAN: u8 = 1;
-#[cfg(feature = "lua51")]
+#[cfg(any(feature = "lua51", feature = "lua52", feature = "lua53"))]
 pub const LUA_CONSTANT_NUMBER: u8 = 3;
-#[cfg(feature = "lua51")]
+#[cfg(any(feature = "lua51", feature = "lua52", feature = "lua53"))]
 pub const LUA_CONSTANT_STRING: u8 = 4;
 
+#[cfg(feature = "lua53")]
+pub const LUA53_CONSTANT_NIL: u8 = 0x00;
+#[cfg(feature = "lua53")]
+pub const LUA53_CONSTANT_BOOLEAN: u8 = 0x01;
+#[cfg(feature = "lua53")]
+pub const LUA53_CONSTANT_FLOAT: u8 = 0x03;
+#[cfg(feature = "lua53")]
+pub const LUA53_CONSTANT_INTEGER: u8 = 0x13;
+#[cfg(feature = "lua53")]
+pub const LUA53_CONSTANT_SHORT_STRING: u8 = 0x04;
+#[cfg(feature = "lua53")]
+pub const LUA53_CONSTANT_LONG_STRING: u8 = 0x14;
+
+#[cfg(feature = "lua54")]
+pub const LUA54_CONSTANT_NIL: u8 = 0x00;
+#[cfg(feature = "lua54")]
+pub const LUA54_CONSTANT_FALSE: u8 = 0x01;
+#[cfg(feature = "lua54")]
+pub const LUA54_CONSTANT_TRUE: u8 = 0x11;
+#[cfg(feature = "lua54")]
+pub const LUA54_CONSTANT_INTEGER: u8 = 0x03;
+#[cfg(feature = "lua54")]
+pub const LUA54_CONSTANT_FLOAT: u8 = 0x13;
+#[cfg(feature = "lua54")]
+pub const LUA54_CONSTANT_SHORT_STRING: u8 = 0x04;
+#[cfg(feature = "lua54")]
+pub const LUA54_CONSTANT_LONG_STRING: u8 = 0x14;
+
+#[cfg(feature = "luajit")]
+pub const LUAJIT_KGC_CHILD: u8 = 0;
+#[cfg(feature = "luajit")]
+pub const LUAJIT_KGC_TABLE: u8 = 1;
+#[cfg(feature = "luajit")]
+pub const LUAJIT_KGC_I64: u8 = 2;
+#[cfg(feature = "luajit")]
+pub const LUAJIT_KGC_U64: u8 = 3;
+#[cfg(feature = "luajit")]
+pub const LUAJIT_KGC_COMPLEX: u8 = 4;
+#[cfg(feature = "luajit")]
+pub const LUAJIT_KGC_STRING: u8 = 5;
+
+#[cfg(feature = "luajit")]
+pub const LUAJIT_KTAB_NIL: u8 = 0;
+#[cfg(feature = "luajit")]
+pub const LUAJIT_KTAB_FALSE: u8 = 1;
+#[cfg(feature = "luajit")]
+pub const LUAJIT_KTAB_TRUE: u8 = 2;
+#[cfg(feature = "luajit")]
+pub const LUAJIT_KTAB_INTEGER: u8 = 3;
+#[cfg(feature = "luajit")]
+pub const LUAJIT_KTAB_NUMBER: u8 = 4;
+#[cfg(feature = "luajit")]
+pub const LUAJIT_KTAB_STRING: u8 = 5;
+
 #[cfg(feature = "luau")]
 pub const LUAU_CONSTANT_NIL: u8 = 0;
 #[cfg(feature = "luau")]
@@ -31,29 +85,73 @@ pub enum Constant {
     #[default]
     Nil,
     Bool(bool),
+    /// A boolean stored as a byte other than 0 or 1, any byte but 0 is true.
+    #[cfg(any(
+        feature = "lua51",
+        feature = "lua52",
+        feature = "lua53",
+        feature = "luau"
+    ))]
+    RawBool(u8),
     Number(f64),
     String(RawLuaString),
 
+    /// Lua 5.1 keeps the numbers of integral headers here.
+    #[cfg(any(
+        feature = "lua51",
+        feature = "lua53",
+        feature = "lua54",
+        feature = "luajit"
+    ))]
+    Integer(i64),
+
     #[cfg(feature = "luau")]
     Vector(f32, f32, f32, f32),
-    #[cfg(feature = "luau")]
+    #[cfg(any(feature = "luau", feature = "luajit"))]
     Closure(u32),
     #[cfg(feature = "luau")]
     Import(i32),
     #[cfg(feature = "luau")]
     Table(u32, Vec<u32>),
+
+    #[cfg(feature = "luajit")]
+    TemplateTable {
+        array: Vec<Constant>,
+        hash: Vec<(Constant, Constant)>,
+    },
+    #[cfg(feature = "luajit")]
+    Int64(i64),
+    #[cfg(feature = "luajit")]
+    UInt64(u64),
+    #[cfg(feature = "luajit")]
+    Complex(f64, f64),
 }
 
 // TODO: hacky code, maybe use traits?
 impl Constant {
+    /// `Bool` for 0 and 1, any other byte is kept so it is written back unchanged.
+    #[cfg(any(
+        feature = "lua51",
+        feature = "lua52",
+        feature = "lua53",
+        feature = "luau"
+    ))]
+    pub(crate) fn from_bool_byte(byte: u8) -> Constant {
+        match byte {
+            0 | 1 => Constant::Bool(byte == 1),
+            byte => Constant::RawBool(byte),
+        }
+    }
+
     #[cfg(feature = "lua51")]
     pub fn kind(&self) -> u8 {
         match self {
             Constant::Nil => LUA_CONSTANT_NIL,
-            Constant::Bool(_) => LUA_CONSTANT_BOOLEAN,
-            Constant::Number(_) => LUA_CONSTANT_NUMBER,
+            Constant::Bool(_) | Constant::RawBool(_) => LUA_CONSTANT_BOOLEAN,
+            Constant::Number(_) | Constant::Integer(_) => LUA_CONSTANT_NUMBER,
             Constant::String(_) => LUA_CONSTANT_STRING,
 
+            #[allow(unreachable_patterns)]
             _ => unreachable!(),
         }
     }
@@ -62,13 +160,16 @@ impl Constant {
     pub fn kind_luau(&self) -> u8 {
         match self {
             Constant::Nil => LUAU_CONSTANT_NIL,
-            Constant::Bool(_) => LUAU_CONSTANT_BOOLEAN,
+            Constant::Bool(_) | Constant::RawBool(_) => LUAU_CONSTANT_BOOLEAN,
             Constant::Number(_) => LUAU_CONSTANT_NUMBER,
             Constant::String(_) => LUAU_CONSTANT_STRING,
             Constant::Vector(_, _, _, _) => LUAU_CONSTANT_VECTOR,
             Constant::Closure(_) => LUAU_CONSTANT_CLOSURE,
             Constant::Import(_) => LUAU_CONSTANT_IMPORT,
             Constant::Table(_, _) => LUAU_CONSTANT_TABLE,
+
+            #[allow(unreachable_patterns)]
+            _ => unreachable!(),
         }
     }
 }
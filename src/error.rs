@@ -0,0 +1,191 @@
+use crate::{Format, RawLuaString};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    UnknownFormat,
+    UnsupportedFormat(Format),
+
+    UnexpectedEof {
+        offset: u64,
+    },
+    BadMagic {
+        offset: u64,
+        magic: u32,
+    },
+    UnsupportedVersion {
+        offset: u64,
+        version: u8,
+    },
+    UnknownConstantKind {
+        offset: u64,
+        proto: u32,
+        index: u32,
+        tag: u8,
+    },
+    InvalidStringRef {
+        offset: u64,
+        reference: u32,
+    },
+    InvalidOpcode {
+        proto: u32,
+        pc: u32,
+        opcode: u8,
+    },
+    TruncatedInstruction {
+        proto: u32,
+        pc: u32,
+    },
+    InvalidProtoRef {
+        proto: u32,
+    },
+    Malformed {
+        offset: u64,
+        reason: &'static str,
+    },
+    LimitExceeded {
+        offset: u64,
+        limit: &'static str,
+    },
+
+    // write errors
+    MissingString {
+        string: RawLuaString,
+    },
+    UnsupportedConstant {
+        proto: u32,
+        index: u32,
+    },
+    ValueOutOfRange {
+        proto: u32,
+        value: &'static str,
+    },
+    LossyConstant {
+        proto: u32,
+        index: u32,
+    },
+    // every value a retarget found out of range
+    Retarget(Vec<Error>),
+
+    // luau chunks that carry a compile error instead of bytecode
+    CompileError(String),
+
+    // proto builder errors
+    UnboundLabel {
+        label: u32,
+    },
+    UnknownLabel {
+        label: u32,
+    },
+    JumpOutOfRange {
+        pc: u32,
+    },
+    OperandOutOfRange {
+        pc: u32,
+        operand: &'static str,
+    },
+    StackOverflow {
+        size: u32,
+    },
+
+    // assembler errors, lines start at 1
+    Assembly {
+        line: usize,
+        message: String,
+    },
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::UnknownFormat => write!(f, "unknown bytecode format"),
+            Error::UnsupportedFormat(format) => {
+                write!(
+                    f,
+                    "{format:?} bytecode is not supported by the enabled features"
+                )
+            }
+            Error::UnexpectedEof { offset } => {
+                write!(f, "unexpected end of input at offset {offset}")
+            }
+            Error::BadMagic { offset, magic } => {
+                write!(f, "bad magic {magic:#010x} at offset {offset}")
+            }
+            Error::UnsupportedVersion { offset, version } => {
+                write!(f, "unsupported version {version:#04x} at offset {offset}")
+            }
+            Error::UnknownConstantKind {
+                offset,
+                proto,
+                index,
+                tag,
+            } => write!(
+                f,
+                "unknown constant kind {tag} for constant {index} of proto {proto} at offset {offset}"
+            ),
+            Error::InvalidStringRef { offset, reference } => {
+                write!(f, "invalid string reference {reference} at offset {offset}")
+            }
+            Error::InvalidOpcode { proto, pc, opcode } => {
+                write!(f, "invalid opcode {opcode} at proto {proto}, pc {pc}")
+            }
+            Error::TruncatedInstruction { proto, pc } => {
+                write!(
+                    f,
+                    "missing extra word of instruction at proto {proto}, pc {pc}"
+                )
+            }
+            Error::InvalidProtoRef { proto } => write!(f, "invalid proto reference {proto}"),
+            Error::Malformed { offset, reason } => {
+                write!(f, "malformed bytecode at offset {offset}: {reason}")
+            }
+            Error::LimitExceeded { offset, limit } => {
+                write!(f, "{limit} limit exceeded at offset {offset}")
+            }
+            Error::MissingString { string } => write!(
+                f,
+                "string {:?} is missing from the string table",
+                String::from_utf8_lossy(string)
+            ),
+            Error::UnsupportedConstant { proto, index } => write!(
+                f,
+                "constant {index} of proto {proto} can not be encoded in this format"
+            ),
+            Error::ValueOutOfRange { proto, value } => {
+                write!(f, "{value} of proto {proto} does not fit the target header")
+            }
+            Error::LossyConstant { proto, index } => {
+                write!(f, "constant {index} of proto {proto} would lose precision")
+            }
+            Error::Retarget(problems) => {
+                write!(f, "{} values do not fit the target header", problems.len())?;
+                for problem in problems {
+                    write!(f, "\n  {problem}")?;
+                }
+                Ok(())
+            }
+            Error::CompileError(message) => write!(f, "error message in bytecode: {message}"),
+            Error::UnboundLabel { label } => write!(f, "label {label} is used but never bound"),
+            Error::UnknownLabel { label } => {
+                write!(f, "label {label} was not created by this builder")
+            }
+            Error::JumpOutOfRange { pc } => write!(f, "jump at pc {pc} is out of range"),
+            Error::OperandOutOfRange { pc, operand } => {
+                write!(
+                    f,
+                    "operand {operand} of the instruction at pc {pc} is out of range"
+                )
+            }
+            Error::StackOverflow { size } => {
+                write!(
+                    f,
+                    "a stack of {size} slots is larger than the format allows"
+                )
+            }
+            Error::Assembly { line, message } => {
+                write!(f, "assembly error at line {line}: {message}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -2,19 +2,44 @@
 
 use constant::Constant;
 
+#[cfg(any(feature = "lua51", feature = "luau"))]
+mod asm;
 mod buffer;
+#[cfg(any(feature = "lua51", feature = "luau"))]
+mod builder;
 pub mod constant;
+mod error;
+#[cfg(any(feature = "lua51", feature = "luau"))]
+mod number;
 pub mod opcode;
+pub mod tree;
+
+pub use error::Error;
+pub use tree::ProtoTree;
 
 #[cfg(feature = "lua51")]
 pub mod lua51;
+#[cfg(feature = "lua52")]
+pub mod lua52;
+#[cfg(feature = "lua53")]
+pub mod lua53;
+#[cfg(feature = "lua54")]
+pub mod lua54;
+#[cfg(feature = "luajit")]
+pub mod luajit;
 #[cfg(feature = "luau")]
 pub mod luau;
 
-#[cfg(feature = "lua51")]
+#[cfg(any(
+    feature = "lua51",
+    feature = "lua52",
+    feature = "lua53",
+    feature = "lua54"
+))]
 pub const LUA_MAGIC: u32 = 0x61754c1b;
 
-enum Format {
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
     Lua51,
     Lua52,
     Lua53,
@@ -23,9 +48,152 @@ enum Format {
     Luau,
 }
 
+#[derive(Clone, Debug)]
+pub enum Chunk {
+    #[cfg(feature = "lua51")]
+    Lua51(Bytecode),
+    #[cfg(feature = "lua52")]
+    Lua52(Bytecode),
+    #[cfg(feature = "lua53")]
+    Lua53(Bytecode),
+    #[cfg(feature = "lua54")]
+    Lua54(Bytecode),
+    #[cfg(feature = "luajit")]
+    LuaJit(Bytecode),
+    #[cfg(feature = "luau")]
+    Luau(luau::LuaBytecode),
+}
+
+/// Sniffs the chunk header, luau chunks are recognized by their version and types version bytes.
+///
+/// A leading 0 is a luau compile error, parsing it reports the compiler message.
+pub fn detect_format(data: &[u8]) -> Option<Format> {
+    match data {
+        [0x1b, b'L', b'u', b'a', version, ..] => match version {
+            0x51 => Some(Format::Lua51),
+            0x52 => Some(Format::Lua52),
+            0x53 => Some(Format::Lua53),
+            0x54 => Some(Format::Lua54),
+            _ => None,
+        },
+
+        [0x1b, b'L', b'J', ..] => Some(Format::LuaJit),
+
+        [0 | 3, ..] => Some(Format::Luau),
+        [4..=6, types_version, ..] if *types_version <= 3 => Some(Format::Luau),
+
+        _ => None,
+    }
+}
+
+pub fn parse_any(data: &[u8]) -> Result<Chunk, Error> {
+    parse_any_with_options(data, ParseOptions::default())
+}
+
+pub fn parse_any_with_options(data: &[u8], options: ParseOptions) -> Result<Chunk, Error> {
+    let format = detect_format(data).ok_or(Error::UnknownFormat)?;
+
+    match format {
+        #[cfg(feature = "lua51")]
+        Format::Lua51 => {
+            let bytecode = <Bytecode as lua51::LuaBytecode>::from_with_options(data, options)?;
+            Ok(Chunk::Lua51(bytecode))
+        }
+
+        #[cfg(feature = "lua52")]
+        Format::Lua52 => {
+            let bytecode = <Bytecode as lua52::LuaBytecode>::from_with_options(data, options)?;
+            Ok(Chunk::Lua52(bytecode))
+        }
+
+        #[cfg(feature = "lua53")]
+        Format::Lua53 => {
+            let bytecode = <Bytecode as lua53::LuaBytecode>::from_with_options(data, options)?;
+            Ok(Chunk::Lua53(bytecode))
+        }
+
+        #[cfg(feature = "lua54")]
+        Format::Lua54 => {
+            let bytecode = <Bytecode as lua54::LuaBytecode>::from_with_options(data, options)?;
+            Ok(Chunk::Lua54(bytecode))
+        }
+
+        #[cfg(feature = "luajit")]
+        Format::LuaJit => {
+            let bytecode = <Bytecode as luajit::LuaBytecode>::from_with_options(data, options)?;
+            Ok(Chunk::LuaJit(bytecode))
+        }
+
+        #[cfg(feature = "luau")]
+        Format::Luau => Ok(Chunk::Luau(luau::LuaBytecode::from_with_options(
+            data, options,
+        )?)),
+
+        #[allow(unreachable_patterns)]
+        format => {
+            let _ = options;
+            Err(Error::UnsupportedFormat(format))
+        }
+    }
+}
+
 type RawLuaString = Vec<u8>;
 
-#[cfg(feature = "lua51")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParseOptions {
+    pub max_protos: u32,
+    pub max_instructions: u32,
+    pub max_string_length: u64,
+    pub max_depth: u32,
+}
+
+impl ParseOptions {
+    /// Limits for bytecode from untrusted sources.
+    pub fn hardened() -> Self {
+        Self {
+            max_protos: 1 << 16,
+            max_instructions: 1 << 20,
+            max_string_length: 1 << 24,
+            max_depth: 200,
+        }
+    }
+
+    pub(crate) fn check(
+        value: u64,
+        limit: u64,
+        offset: u64,
+        name: &'static str,
+    ) -> Result<(), Error> {
+        if value > limit {
+            return Err(Error::LimitExceeded {
+                offset,
+                limit: name,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        // only the nesting depth is limited, deeper protos overflow the stack
+        Self {
+            max_protos: u32::MAX,
+            max_instructions: u32::MAX,
+            max_string_length: u64::MAX,
+            max_depth: 200,
+        }
+    }
+}
+
+#[cfg(any(
+    feature = "lua51",
+    feature = "lua52",
+    feature = "lua53",
+    feature = "lua54",
+    feature = "luajit"
+))]
 #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
 pub struct Header {
     pub version: u8,
@@ -36,13 +204,81 @@ pub struct Header {
     pub int_size: u8,
     pub size_t_size: u8,
     pub instruction_size: u8,
+    #[cfg(any(feature = "lua53", feature = "lua54"))]
+    pub integer_size: u8,
     pub number_size: u8,
 
     pub is_number_integral: bool,
     pub luajit_flags: u8,
 }
 
-#[cfg(feature = "lua51")]
+#[cfg(any(
+    feature = "lua51",
+    feature = "lua52",
+    feature = "lua53",
+    feature = "lua54"
+))]
+impl Header {
+    /// Rejects sizes the reader can not decode, `sizes` pairs the size bytes from `offset` on
+    /// with the sizes accepted for each.
+    pub(crate) fn check_supported_sizes(
+        offset: u64,
+        sizes: &[(u8, &[u8], &'static str)],
+    ) -> Result<(), Error> {
+        for (position, (size, supported, reason)) in sizes.iter().enumerate() {
+            if !supported.contains(size) {
+                return Err(Error::Malformed {
+                    offset: offset + position as u64,
+                    reason,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(any(feature = "lua51", feature = "lua52"))]
+impl Header {
+    /// Reads the endianness byte, 1 marks a little-endian chunk.
+    pub(crate) fn read_endianness(buffer: &mut buffer::Buffer) -> Result<bool, Error> {
+        let offset = buffer.position();
+        match buffer.read::<u8>()? {
+            0 => Ok(true),
+            1 => Ok(false),
+            _ => Err(Error::Malformed {
+                offset,
+                reason: "unknown endianness",
+            }),
+        }
+    }
+
+    /// Rejects sizes the reader can not decode, `offset` points at the int size byte.
+    pub(crate) fn check_sizes(&self, offset: u64) -> Result<(), Error> {
+        let number_sizes: &[u8] = match self.is_number_integral {
+            true => &[1, 2, 4, 8],
+            false => &[4, 8],
+        };
+
+        Header::check_supported_sizes(
+            offset,
+            &[
+                (self.int_size, &[1, 2, 4, 8], "unsupported int size"),
+                (self.size_t_size, &[1, 2, 4, 8], "unsupported size_t size"),
+                (self.instruction_size, &[4], "unsupported instruction size"),
+                (self.number_size, number_sizes, "unsupported number size"),
+            ],
+        )
+    }
+}
+
+#[cfg(any(
+    feature = "lua51",
+    feature = "lua52",
+    feature = "lua53",
+    feature = "lua54",
+    feature = "luajit"
+))]
 #[derive(Clone, Debug, Default)]
 pub struct Bytecode {
     pub header: Header,
@@ -50,9 +286,69 @@ pub struct Bytecode {
     pub main_proto_id: u32,
 }
 
+#[cfg(any(
+    feature = "lua51",
+    feature = "lua52",
+    feature = "lua53",
+    feature = "lua54",
+    feature = "luajit"
+))]
+impl ProtoTree for Bytecode {
+    fn protos(&self) -> &[Proto] {
+        &self.protos
+    }
+
+    fn main_proto_id(&self) -> u32 {
+        self.main_proto_id
+    }
+}
+
+#[cfg(any(
+    feature = "lua52",
+    feature = "lua53",
+    feature = "lua54",
+    feature = "luajit"
+))]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct UpvalueDescriptor {
+    pub in_stack: bool,
+    pub index: u8,
+
+    /// LuaJIT marks upvalues that are never assigned as `Const`.
+    #[cfg(any(feature = "lua54", feature = "luajit"))]
+    pub kind: UpvalueKind,
+}
+
+#[cfg(any(feature = "lua54", feature = "luajit"))]
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum UpvalueKind {
+    #[default]
+    Regular,
+    Const,
+    ToBeClosed,
+    CompileTimeConstant,
+}
+
+#[cfg(any(feature = "lua54", feature = "luajit"))]
+impl TryFrom<u8> for UpvalueKind {
+    type Error = u8;
+
+    fn try_from(kind: u8) -> Result<Self, Self::Error> {
+        match kind {
+            0 => Ok(UpvalueKind::Regular),
+            1 => Ok(UpvalueKind::Const),
+            2 => Ok(UpvalueKind::ToBeClosed),
+            3 => Ok(UpvalueKind::CompileTimeConstant),
+            kind => Err(kind),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct LocalVariable {
-    name: RawLuaString,
+    /// `None` for a NULL name, which lua 5.3 and 5.4 keep apart from an empty one.
+    name: Option<RawLuaString>,
     start_pc: u32,
     end_pc: u32,
 
@@ -69,9 +365,14 @@ pub struct Proto {
     pub parameter_count: u8,
     pub upvalue_count: u8,
     pub is_vararg: bool,
+    /// Raw luau vararg byte, the VM treats any nonzero value as vararg.
+    #[cfg(feature = "luau")]
+    pub vararg_byte: u8,
 
+    /// Raw proto flags, lua 5.1 keeps its `VARARG_*` bits here.
     pub flags: u8,
-    pub type_info: Vec<u8>,
+    #[cfg(feature = "luau")]
+    pub type_info: Option<luau::LuauTypeInfo>,
 
     pub line_defined: u32,
     pub last_line_defined: u32,
@@ -79,11 +380,26 @@ pub struct Proto {
     pub name: Option<RawLuaString>,
     pub line_info: Vec<u32>,
     pub absolute_line_info: Vec<i32>,
+    #[cfg(feature = "lua54")]
+    pub absolute_line_pcs: Vec<u32>,
     pub linegaplog2: u8,
+    /// Luau chunks can carry an empty debug info section.
+    #[cfg(feature = "luau")]
+    pub has_debug_info: bool,
 
     pub protos: Vec<u32>,
     pub locals: Vec<LocalVariable>,
-    pub upvalues: Vec<RawLuaString>,
+    /// `None` for a NULL name, which lua 5.3 and 5.4 keep apart from an empty one.
+    pub upvalues: Vec<Option<RawLuaString>>,
+    #[cfg(any(
+        feature = "lua52",
+        feature = "lua53",
+        feature = "lua54",
+        feature = "luajit"
+    ))]
+    pub upvalue_descriptors: Vec<UpvalueDescriptor>,
     pub constants: Vec<Constant>,
+    #[cfg(feature = "luajit")]
+    pub number_constants: Vec<Constant>,
     pub instructions: Vec<opcode::Instruction>,
 }
@@ -1,216 +1,514 @@
-use crate::{opcode::Instruction, *};
-use buffer::Buffer;
+use crate::{
+    opcode::{Instruction, LuaOpMode, LuaOpcode, Opcode},
+    *,
+};
+use buffer::{Buffer, LuaPrimitive};
 
-pub trait LuaBytecode {
-    fn from(data: &[u8]) -> Result<Bytecode, String>;
-    fn parse_header(&self, buffer: &mut Buffer) -> Header;
-    fn parse_proto(&mut self, buffer: &mut Buffer) -> Proto;
+pub mod asm;
+pub mod builder;
+pub mod disasm;
+
+pub const VARARG_HASARG: u8 = 1;
+pub const VARARG_ISVARARG: u8 = 2;
+pub const VARARG_NEEDSARG: u8 = 4;
 
-    fn write(&mut self) -> Vec<u8>;
-    fn write_proto(&self, index: u32, buffer: &mut Buffer);
+pub trait LuaBytecode {
+    fn from(data: &[u8]) -> Result<Bytecode, Error>;
+    fn from_with_options(data: &[u8], options: ParseOptions) -> Result<Bytecode, Error>;
+    fn parse_header(&self, buffer: &mut Buffer) -> Result<Header, Error>;
+    fn parse_proto(
+        &mut self,
+        buffer: &mut Buffer,
+        options: &ParseOptions,
+        depth: u32,
+    ) -> Result<Proto, Error>;
+
+    fn write(&mut self) -> Result<Vec<u8>, Error>;
+    fn write_proto(&self, index: u32, buffer: &mut Buffer) -> Result<(), Error>;
+
+    /// Re-encodes the chunk for another platform, the header is only replaced when every value fits.
+    ///
+    /// Fails with `Error::Retarget` listing every value that does not fit.
+    fn retarget(&mut self, header: Header) -> Result<Vec<u8>, Error>;
 }
 
 impl LuaBytecode for Bytecode {
-    fn from(data: &[u8]) -> Result<Bytecode, String> {
+    fn from(data: &[u8]) -> Result<Bytecode, Error> {
+        Self::from_with_options(data, ParseOptions::default())
+    }
+
+    fn from_with_options(data: &[u8], options: ParseOptions) -> Result<Bytecode, Error> {
         let mut bytecode = Bytecode::default();
         let mut buffer = Buffer::new(data.to_vec());
 
-        bytecode.header = bytecode.parse_header(&mut buffer);
-        let main_proto = bytecode.parse_proto(&mut buffer);
-        bytecode.protos.push(main_proto);
+        bytecode.header = bytecode.parse_header(&mut buffer)?;
+        buffer.set_big_endian(bytecode.header.is_big_endian);
+
+        // protos are stored in pre-order, the slot is reserved before parsing
+        bytecode.protos.push(Proto::default());
+        bytecode.protos[0] = bytecode.parse_proto(&mut buffer, &options, 0)?;
+        bytecode.main_proto_id = 0;
 
         Ok(bytecode)
     }
 
-    fn parse_header(&self, buffer: &mut Buffer) -> Header {
-        let magic = buffer.read::<u32>();
-        assert_eq!(magic, LUA_MAGIC);
+    fn parse_header(&self, buffer: &mut Buffer) -> Result<Header, Error> {
+        let offset = buffer.position();
+        let magic = buffer.read::<u32>()?;
+        if magic != LUA_MAGIC {
+            return Err(Error::BadMagic { offset, magic });
+        }
 
-        let version = buffer.read::<u8>();
-        assert_eq!(version, 0x51);
+        let offset = buffer.position();
+        let version = buffer.read::<u8>()?;
+        if version != 0x51 {
+            return Err(Error::UnsupportedVersion { offset, version });
+        }
 
-        Header {
+        let header = Header {
             version,
-            format: buffer.read::<u8>(),
-            is_big_endian: buffer.read::<bool>(),
-            int_size: buffer.read::<u8>(),
-            size_t_size: buffer.read::<u8>(),
-            instruction_size: buffer.read::<u8>(),
-            number_size: buffer.read::<u8>(),
-            is_number_integral: buffer.read::<bool>(),
-            luajit_flags: 0,
-        }
+            format: buffer.read::<u8>()?,
+            is_big_endian: Header::read_endianness(buffer)?,
+            int_size: buffer.read::<u8>()?,
+            size_t_size: buffer.read::<u8>()?,
+            instruction_size: buffer.read::<u8>()?,
+            number_size: buffer.read::<u8>()?,
+            is_number_integral: buffer.read::<bool>()?,
+            ..Default::default()
+        };
+
+        // the sizes follow the version, format and endianness bytes
+        header.check_sizes(offset + 3)?;
+        Ok(header)
     }
 
-    fn parse_proto(&mut self, buffer: &mut Buffer) -> Proto {
-        let mut proto = Proto::default();
-
-        proto.name = Some(buffer.read_string());
-        proto.line_defined = buffer.read::<u32>();
-        proto.last_line_defined = buffer.read::<u32>();
-        proto.upvalue_count = buffer.read::<u8>();
-        proto.parameter_count = buffer.read::<u8>();
-        proto.is_vararg = buffer.read::<bool>();
-        proto.max_stack_size = buffer.read::<u8>();
+    fn parse_proto(
+        &mut self,
+        buffer: &mut Buffer,
+        options: &ParseOptions,
+        depth: u32,
+    ) -> Result<Proto, Error> {
+        let offset = buffer.position();
+        ParseOptions::check(
+            depth as u64,
+            options.max_depth as u64,
+            offset,
+            "nesting depth",
+        )?;
+
+        // the caller reserved the last slot for this proto
+        let proto_id = self.protos.len() as u32 - 1;
+
+        let header = self.header;
+        let max_length = options.max_string_length;
+        let mut proto = Proto {
+            name: Some(buffer.read_string(&header, max_length)?),
+            line_defined: buffer.read_int(&header)?,
+            last_line_defined: buffer.read_int(&header)?,
+            upvalue_count: buffer.read::<u8>()?,
+            parameter_count: buffer.read::<u8>()?,
+            flags: buffer.read::<u8>()?,
+            max_stack_size: buffer.read::<u8>()?,
+            ..Default::default()
+        };
+        proto.is_vararg = proto.flags & VARARG_ISVARARG != 0;
+
+        let offset = buffer.position();
+        let instruction_count = buffer.read_int(&header)?;
+        ParseOptions::check(
+            instruction_count as u64,
+            options.max_instructions as u64,
+            offset,
+            "instruction count",
+        )?;
 
-        let instruction_count = buffer.read::<u32>();
         for _ in 0..instruction_count {
-            let instruction = buffer.read::<u32>();
-            let instruction = Instruction::from_bytes(&instruction.to_le_bytes());
-            proto.instructions.push(instruction);
+            proto.instructions.push(Instruction(buffer.read::<u32>()?));
         }
 
-        let constant_count = buffer.read::<u32>();
-        for _ in 0..constant_count {
-            let kind = buffer.read::<u8>();
+        validate_instructions(proto_id, &proto.instructions)?;
+
+        let constant_count = buffer.read_int(&header)?;
+        for index in 0..constant_count {
+            let offset = buffer.position();
+            let kind = buffer.read::<u8>()?;
 
             let constant = match kind {
                 constant::LUA_CONSTANT_NIL => Constant::Nil,
-                constant::LUA_CONSTANT_BOOLEAN => Constant::Bool(buffer.read::<u8>() > 0),
-                constant::LUA_CONSTANT_NUMBER => Constant::Number(buffer.read::<f64>()),
-                constant::LUA_CONSTANT_STRING => Constant::String(buffer.read_string()),
+                constant::LUA_CONSTANT_BOOLEAN => Constant::from_bool_byte(buffer.read::<u8>()?),
+                // integral numbers stay exact past 2^53
+                constant::LUA_CONSTANT_NUMBER if header.is_number_integral => {
+                    Constant::Integer(buffer.read_integer(&header)?)
+                }
+                constant::LUA_CONSTANT_NUMBER => Constant::Number(buffer.read_number(&header)?),
+                constant::LUA_CONSTANT_STRING => {
+                    Constant::String(buffer.read_string(&header, max_length)?)
+                }
 
-                _ => {
-                    unreachable!();
+                tag => {
+                    return Err(Error::UnknownConstantKind {
+                        offset,
+                        proto: proto_id,
+                        index,
+                        tag,
+                    });
                 }
             };
 
             proto.constants.push(constant);
         }
 
-        let proto_count = buffer.read::<u32>();
+        let offset = buffer.position();
+        let proto_count = buffer.read_int(&header)?;
+        ParseOptions::check(
+            self.protos.len() as u64 + proto_count as u64,
+            options.max_protos as u64,
+            offset,
+            "proto count",
+        )?;
+
         for _ in 0..proto_count {
-            proto.protos.push(self.protos.len() as u32); // proto_id
-            let child_proto = self.parse_proto(buffer);
-            self.protos.push(child_proto);
+            let child_id = self.protos.len();
+            proto.protos.push(child_id as u32);
+
+            self.protos.push(Proto::default());
+            self.protos[child_id] = self.parse_proto(buffer, options, depth + 1)?;
         }
 
-        let line_info_count = buffer.read::<u32>();
+        let line_info_count = buffer.read_int(&header)?;
         for _ in 0..line_info_count {
-            proto.line_info.push(buffer.read::<u32>());
+            proto.line_info.push(buffer.read_int(&header)?);
         }
 
-        let local_count = buffer.read::<u32>();
+        let local_count = buffer.read_int(&header)?;
         for _ in 0..local_count {
             proto.locals.push(LocalVariable {
-                name: buffer.read_string(),
-                start_pc: buffer.read::<u32>(),
-                end_pc: buffer.read::<u32>(),
+                name: Some(buffer.read_string(&header, max_length)?),
+                start_pc: buffer.read_int(&header)?,
+                end_pc: buffer.read_int(&header)?,
                 #[cfg(feature = "luau")]
                 register: 0,
             })
         }
 
-        let upvalue_count = buffer.read::<u32>();
+        let upvalue_count = buffer.read_int(&header)?;
         for _ in 0..upvalue_count {
-            proto.upvalues.push(buffer.read_string());
+            proto
+                .upvalues
+                .push(Some(buffer.read_string(&header, max_length)?));
         }
 
-        proto
+        Ok(proto)
     }
 
-    fn write(&mut self) -> Vec<u8> {
+    fn write(&mut self) -> Result<Vec<u8>, Error> {
         let mut buffer = Buffer::new(Vec::new());
 
         buffer.write::<u32>(LUA_MAGIC);
         buffer.write::<u8>(self.header.version);
         buffer.write::<u8>(self.header.format);
-        buffer.write::<bool>(self.header.is_big_endian);
+        buffer.write::<bool>(!self.header.is_big_endian);
+
+        self.header.check_sizes(buffer.position())?;
         buffer.write::<u8>(self.header.int_size);
         buffer.write::<u8>(self.header.size_t_size);
         buffer.write::<u8>(self.header.instruction_size);
         buffer.write::<u8>(self.header.number_size);
         buffer.write::<bool>(self.header.is_number_integral);
+        buffer.set_big_endian(self.header.is_big_endian);
 
-        self.write_proto(self.main_proto_id, &mut buffer);
+        self.write_proto(self.main_proto_id, &mut buffer)?;
 
         buffer.set_position(0);
-        buffer.read_all()
+        Ok(buffer.read_all())
     }
 
-    fn write_proto(&self, index: u32, buffer: &mut Buffer) {
-        let proto = &self.protos[index as usize];
+    fn write_proto(&self, index: u32, buffer: &mut Buffer) -> Result<(), Error> {
+        let proto = self
+            .protos
+            .get(index as usize)
+            .ok_or(Error::InvalidProtoRef { proto: index })?;
+        let header = &self.header;
 
-        buffer.write_string(proto.name.clone().unwrap());
-        buffer.write::<u32>(proto.line_defined);
-        buffer.write::<u32>(proto.last_line_defined);
+        buffer.write_string(header, proto.name.as_deref().unwrap_or_default())?;
+        buffer.write_int(header, proto.line_defined)?;
+        buffer.write_int(header, proto.last_line_defined)?;
         buffer.write::<u8>(proto.upvalue_count);
         buffer.write::<u8>(proto.parameter_count);
-        buffer.write::<bool>(proto.is_vararg);
+        // the compat bits are kept as parsed, only VARARG_ISVARARG follows is_vararg
+        buffer.write::<u8>(match proto.is_vararg {
+            true => proto.flags | VARARG_ISVARARG,
+            false => proto.flags & !VARARG_ISVARARG,
+        });
         buffer.write::<u8>(proto.max_stack_size);
 
-        buffer.write::<u32>(proto.instructions.len() as u32);
+        buffer.write_int(header, proto.instructions.len() as u32)?;
         for instruction in proto.instructions.iter() {
-            buffer.write::<i32>(instruction.0 as i32);
+            buffer.write::<u32>(instruction.0);
         }
 
-        buffer.write::<u32>(proto.constants.len() as u32);
-        for constant in proto.constants.iter() {
-            buffer.write::<u8>(constant.kind());
-
+        buffer.write_int(header, proto.constants.len() as u32)?;
+        for (constant_index, constant) in proto.constants.iter().enumerate() {
             match constant {
-                Constant::Nil => (),
+                Constant::Nil => {
+                    buffer.write::<u8>(constant::LUA_CONSTANT_NIL);
+                }
+
                 Constant::Bool(value) => {
+                    buffer.write::<u8>(constant::LUA_CONSTANT_BOOLEAN);
                     buffer.write(*value);
                 }
 
+                Constant::RawBool(byte) => {
+                    buffer.write::<u8>(constant::LUA_CONSTANT_BOOLEAN);
+                    buffer.write(*byte);
+                }
+
                 Constant::Number(value) => {
-                    buffer.write(*value);
+                    buffer.write::<u8>(constant::LUA_CONSTANT_NUMBER);
+                    buffer.write_number(header, *value, index, constant_index as u32)?;
+                }
+
+                Constant::Integer(value) => {
+                    buffer.write::<u8>(constant::LUA_CONSTANT_NUMBER);
+                    buffer.write_integer(header, *value, index, constant_index as u32)?;
                 }
 
                 Constant::String(value) => {
-                    buffer.write_string(value.clone());
+                    buffer.write::<u8>(constant::LUA_CONSTANT_STRING);
+                    buffer.write_string(header, value)?;
                 }
 
-                _ => unreachable!(),
+                #[allow(unreachable_patterns)]
+                _ => {
+                    return Err(Error::UnsupportedConstant {
+                        proto: index,
+                        index: constant_index as u32,
+                    });
+                }
             }
         }
 
-        buffer.write::<u32>(proto.protos.len() as u32);
+        buffer.write_int(header, proto.protos.len() as u32)?;
         for proto in proto.protos.iter() {
-            self.write_proto(*proto, buffer);
+            self.write_proto(*proto, buffer)?;
         }
 
-        buffer.write::<u32>(proto.line_info.len() as u32);
+        buffer.write_int(header, proto.line_info.len() as u32)?;
         for line in proto.line_info.iter() {
-            buffer.write::<u32>(*line);
+            buffer.write_int(header, *line)?;
         }
 
-        buffer.write::<u32>(proto.locals.len() as u32);
+        buffer.write_int(header, proto.locals.len() as u32)?;
         for local in proto.locals.iter() {
-            buffer.write_string(local.name.clone());
-            buffer.write::<u32>(local.start_pc);
-            buffer.write::<u32>(local.end_pc);
+            buffer.write_string(header, local.name.as_deref().unwrap_or_default())?;
+            buffer.write_int(header, local.start_pc)?;
+            buffer.write_int(header, local.end_pc)?;
         }
 
-        buffer.write::<u32>(proto.upvalues.len() as u32);
+        buffer.write_int(header, proto.upvalues.len() as u32)?;
         for upvalue in proto.upvalues.iter() {
-            buffer.write_string(upvalue.clone());
+            buffer.write_string(header, upvalue.as_deref().unwrap_or_default())?;
         }
+
+        Ok(())
     }
-}
 
-trait LuaString {
-    fn read_string(&mut self) -> RawLuaString;
-    fn write_string(&mut self, string: RawLuaString);
-}
+    fn retarget(&mut self, header: Header) -> Result<Vec<u8>, Error> {
+        if header.version != 0x51 {
+            return Err(Error::UnsupportedVersion {
+                offset: 4,
+                version: header.version,
+            });
+        }
 
-impl LuaString for Buffer {
-    fn read_string(&mut self) -> RawLuaString {
-        let mut bytes = Vec::new();
+        header.check_sizes(7)?;
 
-        let length = self.read::<u64>();
-        for _ in 0..length {
-            bytes.push(self.read::<u8>());
+        let mut problems = Vec::new();
+        for (id, proto) in self.protos.iter().enumerate() {
+            check_proto(&header, id as u32, proto, &mut problems);
         }
+        if !problems.is_empty() {
+            return Err(Error::Retarget(problems));
+        }
+
+        let previous = std::mem::replace(&mut self.header, header);
+        let data = self.write();
+        if data.is_err() {
+            self.header = previous;
+        }
+        data
+    }
+}
+
+fn check_proto(header: &Header, id: u32, proto: &Proto, problems: &mut Vec<Error>) {
+    let fits = |value: u64, size: u8| size >= 8 || value >> (size as u32 * 8) == 0;
+    let mut check_int = |value: u32, name: &'static str| {
+        if !fits(value as u64, header.int_size) {
+            problems.push(Error::ValueOutOfRange {
+                proto: id,
+                value: name,
+            });
+        }
+    };
+
+    check_int(proto.line_defined, "line_defined");
+    check_int(proto.last_line_defined, "last_line_defined");
+    check_int(proto.instructions.len() as u32, "instruction count");
+    check_int(proto.constants.len() as u32, "constant count");
+    check_int(proto.protos.len() as u32, "proto count");
+    check_int(proto.line_info.len() as u32, "line info count");
+    check_int(proto.locals.len() as u32, "local count");
+    check_int(proto.upvalues.len() as u32, "upvalue count");
+
+    for line in proto.line_info.iter() {
+        check_int(*line, "line info");
+    }
 
-        bytes
+    for local in proto.locals.iter() {
+        check_int(local.start_pc, "local start_pc");
+        check_int(local.end_pc, "local end_pc");
     }
 
-    fn write_string(&mut self, string: RawLuaString) {
-        self.write::<u64>(string.len() as u64);
-        for byte in string {
-            self.write::<u8>(byte);
+    let strings = proto.name.iter().map(Vec::as_slice);
+    let strings = strings
+        .chain(
+            proto
+                .locals
+                .iter()
+                .filter_map(|local| local.name.as_deref()),
+        )
+        .chain(proto.upvalues.iter().filter_map(Option::as_deref))
+        .chain(
+            proto
+                .constants
+                .iter()
+                .filter_map(|constant| match constant {
+                    Constant::String(value) => Some(value.as_slice()),
+                    _ => None,
+                }),
+        );
+    for string in strings {
+        if !fits(string.len() as u64, header.size_t_size) {
+            problems.push(Error::ValueOutOfRange {
+                proto: id,
+                value: "string length",
+            });
         }
     }
+
+    // encoding into a scratch buffer catches both range and precision loss
+    for (index, constant) in proto.constants.iter().enumerate() {
+        let mut scratch = Buffer::new(Vec::new());
+        let result = match constant {
+            Constant::Number(value) => scratch.write_number(header, *value, id, index as u32),
+            Constant::Integer(value) => scratch.write_integer(header, *value, id, index as u32),
+            _ => Ok(()),
+        };
+
+        if let Err(err) = result {
+            problems.push(err);
+        }
+    }
+}
+
+fn validate_instructions(proto: u32, instructions: &[Instruction]) -> Result<(), Error> {
+    decode_instructions(proto, instructions).try_for_each(|item| item.map(|_| ()))
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Operands {
+    ABC { a: u32, b: u32, c: u32 },
+    ABx { a: u32, bx: u32 },
+    AsBx { a: u32, sbx: i32 },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DecodedInstruction {
+    pub opcode: LuaOpcode,
+    pub operands: Operands,
+    /// The block number that follows `SETLIST` when C is 0.
+    pub extra: Option<u32>,
+}
+
+/// Walks the instructions of a proto, yielding `(pc, instruction)` and skipping extra words.
+pub fn decode_instructions(proto: u32, instructions: &[Instruction]) -> DecodedInstructions<'_> {
+    DecodedInstructions {
+        proto,
+        instructions,
+        pc: 0,
+    }
+}
+
+pub struct DecodedInstructions<'a> {
+    proto: u32,
+    instructions: &'a [Instruction],
+    pc: usize,
+}
+
+impl Iterator for DecodedInstructions<'_> {
+    type Item = Result<(u32, DecodedInstruction), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pc = self.pc;
+        let instruction = self.instructions.get(pc)?.lua51();
+
+        // errors end the walk
+        self.pc = self.instructions.len();
+
+        let opcode = match instruction.try_opcode() {
+            Ok(Opcode::LuaOpcode(opcode)) => opcode,
+            #[allow(unreachable_patterns)]
+            Ok(_) => unreachable!(),
+
+            Err(opcode) => {
+                return Some(Err(Error::InvalidOpcode {
+                    proto: self.proto,
+                    pc: pc as u32,
+                    opcode,
+                }));
+            }
+        };
+
+        let a = instruction.a();
+        let operands = match opcode.mode() {
+            LuaOpMode::IABx => Operands::ABx {
+                a,
+                bx: instruction.bx(),
+            },
+            LuaOpMode::IAsBx => Operands::AsBx {
+                a,
+                sbx: instruction.sbx(),
+            },
+            _ => Operands::ABC {
+                a,
+                b: instruction.b(),
+                c: instruction.c(),
+            },
+        };
+
+        // SETLIST with C = 0 stores its count in the next word
+        let extra = match opcode {
+            LuaOpcode::SetList if instruction.c() == 0 => match self.instructions.get(pc + 1) {
+                Some(extra) => Some(extra.0),
+                None => {
+                    return Some(Err(Error::TruncatedInstruction {
+                        proto: self.proto,
+                        pc: pc as u32,
+                    }));
+                }
+            },
+            _ => None,
+        };
+
+        self.pc = pc + 1 + extra.is_some() as usize;
+        Some(Ok((
+            pc as u32,
+            DecodedInstruction {
+                opcode,
+                operands,
+                extra,
+            },
+        )))
+    }
 }
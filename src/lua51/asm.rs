@@ -0,0 +1,559 @@
+//! A line based assembler for lua 5.1 chunks.
+//!
+//! ```text
+//! .header endian=little int=4 size_t=8 number=8
+//! .source "@example.lua"
+//! .const greeting "hello"
+//!
+//!         GETGLOBAL 0 "print"
+//!         LOADK     1 $greeting
+//! loop:   CALL      0 2 1
+//!         JMP       loop
+//!
+//! .function callback
+//!         .params 1
+//!         RETURN 0 1
+//! .end
+//! ```
+//!
+//! Operands follow the `luac -l` order, so instruction lines of a listing can be pasted as is.
+//! Constants are written as `Kn`, `$name` of an earlier `.const`, a string literal, `#literal` or a negative `luac -l` index,
+//! jumps take a label or a raw offset and `CLOSURE` takes a child function name or index.
+//! Strings get their terminating zero appended.
+
+use std::collections::HashMap;
+
+use crate::{
+    Bytecode, Constant, Error, Header, LocalVariable, Proto,
+    asm::{NamedLabels, Token, error, literal, operand_error, parse_int, tokenize},
+    lua51::{LuaBytecode, VARARG_ISVARARG, builder::ProtoBuilder},
+    opcode::{BIT_RK, LuaOpArgMode, LuaOpMode, LuaOpcode, MAX_ARG_SBX, MAX_INDEX_RK},
+};
+
+/// Assembles `source` into a chunk with the main function at proto 0.
+pub fn assemble(source: &str) -> Result<Bytecode, Error> {
+    let mut assembler = Assembler {
+        header: Header {
+            version: 0x51,
+            int_size: 4,
+            size_t_size: 8,
+            instruction_size: 4,
+            number_size: 8,
+            ..Default::default()
+        },
+        protos: vec![Proto::default()],
+        functions: vec![Function::new(0)],
+    };
+
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let tokens = tokenize(line, text)?;
+        assembler.line(line, &tokens)?;
+    }
+
+    if let [_, .., function] = assembler.functions.as_slice() {
+        return Err(error(function.line, "missing .end for this .function"));
+    }
+
+    let main = assembler.functions.pop().unwrap();
+    assembler.protos[0] = main.finish()?;
+
+    Ok(Bytecode {
+        header: assembler.header,
+        protos: assembler.protos,
+        main_proto_id: 0,
+    })
+}
+
+/// Assembles `source` and encodes it with `LuaBytecode::write`.
+pub fn assemble_bytes(source: &str) -> Result<Vec<u8>, Error> {
+    let mut bytecode = assemble(source)?;
+    LuaBytecode::write(&mut bytecode)
+}
+
+struct Assembler {
+    header: Header,
+    protos: Vec<Proto>,
+    // the innermost function being assembled is last
+    functions: Vec<Function>,
+}
+
+impl Assembler {
+    fn line(&mut self, line: usize, tokens: &[Token]) -> Result<(), Error> {
+        let mut tokens = tokens;
+
+        // pc and line columns of a `luac -l` listing
+        if let [Token::Word(pc), Token::Word(column), rest @ ..] = tokens
+            && parse_int(pc).is_some()
+            && column.starts_with('[')
+            && column.ends_with(']')
+        {
+            if let Some(source_line) = parse_int(&column[1..column.len() - 1]) {
+                self.function().set_line(line, source_line)?;
+            }
+            tokens = rest;
+        }
+
+        if let Some(Token::Word(word)) = tokens.first()
+            && let Some(label) = word.strip_suffix(':')
+        {
+            self.function().define_label(line, label)?;
+            tokens = &tokens[1..];
+        }
+
+        match tokens.first() {
+            None => Ok(()),
+            Some(Token::Word(word)) if word.starts_with('.') => {
+                self.directive(line, &word[1..], &tokens[1..])
+            }
+            Some(Token::Word(word)) => self.function().instruction(line, word, &tokens[1..]),
+            Some(Token::String(_)) => Err(error(line, "expected an instruction or directive")),
+        }
+    }
+
+    fn directive(&mut self, line: usize, name: &str, arguments: &[Token]) -> Result<(), Error> {
+        match name {
+            "header" => self.header_directive(line, arguments),
+
+            "function" => {
+                let name = match arguments {
+                    [] => None,
+                    [Token::Word(name)] => Some(name.clone()),
+                    _ => return Err(error(line, "expected a function name")),
+                };
+
+                let id = self.protos.len() as u32;
+                self.protos.push(Proto::default());
+
+                let parent = self.function();
+                parent.proto.protos.push(id);
+                parent.children.push(name);
+
+                self.functions.push(Function::new(line));
+                Ok(())
+            }
+
+            "end" => {
+                if self.functions.len() < 2 {
+                    return Err(error(line, ".end without .function"));
+                }
+
+                let function = self.functions.pop().unwrap();
+                let parent = self.function();
+                let id = parent.proto.protos[parent.children.len() - 1];
+                self.protos[id as usize] = function.finish()?;
+                Ok(())
+            }
+
+            _ => self.function().directive(line, name, arguments),
+        }
+    }
+
+    fn header_directive(&mut self, line: usize, arguments: &[Token]) -> Result<(), Error> {
+        for argument in arguments {
+            let Some((key, value)) = argument.word().and_then(|word| word.split_once('=')) else {
+                return Err(error(line, "expected key=value"));
+            };
+
+            let size = || match parse_int(value) {
+                Some(size @ 0..=255) => Ok(size as u8),
+                _ => Err(error(line, format!("invalid size `{value}`"))),
+            };
+
+            match key {
+                "endian" => {
+                    self.header.is_big_endian = match value {
+                        "little" => false,
+                        "big" => true,
+                        _ => return Err(error(line, "endian must be little or big")),
+                    }
+                }
+                "int" => self.header.int_size = size()?,
+                "size_t" => self.header.size_t_size = size()?,
+                "instruction" => self.header.instruction_size = size()?,
+                "number" => self.header.number_size = size()?,
+                "integral" => {
+                    self.header.is_number_integral = match value {
+                        "true" => true,
+                        "false" => false,
+                        _ => return Err(error(line, "integral must be true or false")),
+                    }
+                }
+                _ => return Err(error(line, format!("unknown header field `{key}`"))),
+            }
+        }
+
+        self.header
+            .check_sizes(0)
+            .map_err(|_| error(line, "unsupported header sizes"))
+    }
+
+    fn function(&mut self) -> &mut Function {
+        self.functions.last_mut().unwrap()
+    }
+}
+
+struct Function {
+    // the line of the `.function` directive
+    line: usize,
+    // everything but the code, which the builder owns
+    proto: Proto,
+    builder: ProtoBuilder,
+    // the source line of every emitted word
+    lines: Vec<usize>,
+    stack_size: Option<u8>,
+
+    labels: NamedLabels,
+    constants: HashMap<String, u32>,
+    children: Vec<Option<String>>,
+    closures: Vec<(usize, u32, String)>,
+    // constant and child indices written as numbers, checked once the counts are known
+    constant_indices: Vec<(usize, u32)>,
+    child_indices: Vec<(usize, u32)>,
+    locals: Vec<(usize, Vec<u8>, String, String)>,
+}
+
+impl Function {
+    fn new(line: usize) -> Self {
+        Function {
+            line,
+            proto: Proto::default(),
+            builder: ProtoBuilder::new(),
+            lines: Vec::new(),
+            stack_size: None,
+            labels: NamedLabels::default(),
+            constants: HashMap::new(),
+            children: Vec::new(),
+            closures: Vec::new(),
+            constant_indices: Vec::new(),
+            child_indices: Vec::new(),
+            locals: Vec::new(),
+        }
+    }
+
+    fn define_label(&mut self, line: usize, name: &str) -> Result<(), Error> {
+        let builder = &mut self.builder;
+        let label = self
+            .labels
+            .define(line, name, builder.pc(), || builder.label())?;
+        builder.bind(label)
+    }
+
+    fn set_line(&mut self, line: usize, source_line: i64) -> Result<(), Error> {
+        let source_line = u32::try_from(source_line).map_err(|_| error(line, "invalid line"))?;
+        self.builder.set_line(source_line);
+        Ok(())
+    }
+
+    fn directive(&mut self, line: usize, name: &str, arguments: &[Token]) -> Result<(), Error> {
+        let int = |token: Option<&Token>| {
+            token
+                .and_then(Token::word)
+                .and_then(parse_int)
+                .ok_or_else(|| error(line, format!(".{name} expects an integer")))
+        };
+        let byte = |token: Option<&Token>| {
+            u8::try_from(int(token)?).map_err(|_| error(line, format!(".{name} is out of range")))
+        };
+
+        match name {
+            "source" => match arguments {
+                [Token::String(source)] => self.proto.name = Some(with_terminator(source)),
+                _ => return Err(error(line, ".source expects a string")),
+            },
+
+            "linedefined" => {
+                self.proto.line_defined = int(arguments.first())? as u32;
+                self.proto.last_line_defined = int(arguments.get(1))? as u32;
+            }
+
+            "params" => self.proto.parameter_count = byte(arguments.first())?,
+            "stack" => self.stack_size = Some(byte(arguments.first())?),
+            "upvalues" => self.proto.upvalue_count = byte(arguments.first())?,
+
+            "vararg" => {
+                self.proto.is_vararg = true;
+                self.proto.flags = match arguments.first() {
+                    Some(_) => byte(arguments.first())? | VARARG_ISVARARG,
+                    None => VARARG_ISVARARG,
+                };
+            }
+
+            "upvalue" => match arguments {
+                [Token::Word(upvalue)] => {
+                    self.proto
+                        .upvalues
+                        .push(Some(with_terminator(upvalue.as_bytes())));
+                    self.proto.upvalue_count = self
+                        .proto
+                        .upvalue_count
+                        .max(self.proto.upvalues.len() as u8);
+                }
+                _ => return Err(error(line, ".upvalue expects a name")),
+            },
+
+            "local" => match arguments {
+                [Token::Word(local), Token::Word(start), Token::Word(end)] => self.locals.push((
+                    line,
+                    with_terminator(local.as_bytes()),
+                    start.clone(),
+                    end.clone(),
+                )),
+                _ => return Err(error(line, ".local expects a name, a start and an end")),
+            },
+
+            "const" => match arguments {
+                [Token::Word(constant), value] => {
+                    let value = match value {
+                        Token::String(string) => Constant::String(with_terminator(string)),
+                        Token::Word(word) => literal(word.strip_prefix('#').unwrap_or(word))
+                            .ok_or_else(|| error(line, format!("invalid constant `{word}`")))?,
+                    };
+
+                    let index = self.builder.constant(value);
+                    self.constants.insert(constant.clone(), index);
+                }
+                _ => return Err(error(line, ".const expects a name and a value")),
+            },
+
+            "line" => {
+                let source_line = int(arguments.first())?;
+                self.set_line(line, source_line)?;
+            }
+
+            "word" => {
+                let word = int(arguments.first())?;
+                let word = u32::try_from(word).map_err(|_| error(line, ".word is out of range"))?;
+                self.builder.emit_word(word);
+                self.lines.push(line);
+            }
+
+            _ => return Err(error(line, format!("unknown directive `.{name}`"))),
+        }
+
+        Ok(())
+    }
+
+    fn instruction(
+        &mut self,
+        line: usize,
+        mnemonic: &str,
+        operands: &[Token],
+    ) -> Result<(), Error> {
+        let opcode = (0..=u8::MAX)
+            .filter_map(|op| LuaOpcode::try_from(op).ok())
+            .find(|opcode| opcode.name().eq_ignore_ascii_case(mnemonic))
+            .ok_or_else(|| error(line, format!("unknown opcode `{mnemonic}`")))?;
+
+        let info = opcode.info();
+        let expected = match info.mode {
+            LuaOpMode::IABx | LuaOpMode::IAsBx if opcode == LuaOpcode::Jmp => 1,
+            LuaOpMode::IABx | LuaOpMode::IAsBx => 2,
+            _ => 1 + (info.b != LuaOpArgMode::N) as usize + (info.c != LuaOpArgMode::N) as usize,
+        };
+
+        if operands.len() != expected {
+            return Err(error(
+                line,
+                format!("{} expects {expected} operands", opcode.name()),
+            ));
+        }
+
+        let mut operands = operands.iter();
+        let a = match opcode {
+            LuaOpcode::Jmp => 0,
+            _ => register(line, operands.next().unwrap())?,
+        };
+
+        match info.mode {
+            LuaOpMode::IABx => {
+                let operand = operands.next().unwrap();
+                let bx = match info.b {
+                    LuaOpArgMode::K => self.constant(line, operand)?,
+                    // CLOSURE takes a child function
+                    _ => match operand.word().and_then(parse_int) {
+                        Some(index) => {
+                            let index = index as u32;
+                            self.child_indices.push((line, index));
+                            index
+                        }
+                        None => {
+                            let name = operand.word().unwrap_or_default().to_string();
+                            self.closures.push((line, self.builder.pc(), name));
+                            0
+                        }
+                    },
+                };
+
+                self.builder
+                    .emit_abx(opcode, a, bx)
+                    .map_err(operand_error(line))?;
+            }
+
+            LuaOpMode::IAsBx => {
+                let operand = operands.next().unwrap();
+                match operand.word().and_then(parse_int) {
+                    Some(offset) if offset.abs() <= MAX_ARG_SBX as i64 => {
+                        self.builder
+                            .emit_asbx(opcode, a, offset as i32)
+                            .map_err(operand_error(line))?;
+                    }
+                    Some(_) => return Err(error(line, "jump offset is out of range")),
+                    None => {
+                        let name = operand
+                            .word()
+                            .ok_or_else(|| error(line, "expected a label"))?;
+                        let builder = &mut self.builder;
+                        let label = self.labels.reference(line, name, || builder.label());
+                        builder
+                            .emit_jump(opcode, a, label)
+                            .map_err(operand_error(line))?;
+                    }
+                }
+            }
+
+            _ => {
+                let mut argument = |mode| match mode {
+                    LuaOpArgMode::N => Ok(0),
+                    mode => self.argument(line, operands.next().unwrap(), mode),
+                };
+                let b = argument(info.b)?;
+                let c = argument(info.c)?;
+                self.builder
+                    .emit_abc(opcode, a, b, c)
+                    .map_err(operand_error(line))?;
+            }
+        }
+
+        self.lines.push(line);
+        Ok(())
+    }
+
+    fn argument(&mut self, line: usize, operand: &Token, mode: LuaOpArgMode) -> Result<u32, Error> {
+        let word = operand.word().unwrap_or_default();
+        let is_register = parse_int(word.strip_prefix(['R', 'r']).unwrap_or(word))
+            .is_some_and(|value| value >= 0);
+
+        match mode {
+            LuaOpArgMode::K if !is_register => {
+                let index = self.constant(line, operand)?;
+                if index > MAX_INDEX_RK {
+                    return Err(error(line, "constant index does not fit an RK operand"));
+                }
+                Ok(index | BIT_RK)
+            }
+
+            LuaOpArgMode::R | LuaOpArgMode::K => register(line, operand),
+
+            // plain counts and flags are not registers
+            _ => match parse_int(word) {
+                Some(value @ 0..=511) => Ok(value as u32),
+                _ => Err(error(line, format!("invalid operand `{word}`"))),
+            },
+        }
+    }
+
+    fn constant(&mut self, line: usize, operand: &Token) -> Result<u32, Error> {
+        let word = match operand {
+            Token::String(string) => {
+                return Ok(self
+                    .builder
+                    .constant(Constant::String(with_terminator(string))));
+            }
+            Token::Word(word) => word.as_str(),
+        };
+
+        let index = if let Some(name) = word.strip_prefix('$') {
+            self.constants.get(name).copied()
+        } else if let Some(literal) = word.strip_prefix('#').and_then(literal) {
+            Some(self.builder.constant(literal))
+        } else {
+            let index = match word.strip_prefix(['K', 'k']).and_then(parse_int) {
+                Some(index) => u32::try_from(index).ok(),
+                // `luac -l` prints constants as -1 - index
+                None => parse_int(word)
+                    .filter(|index| *index < 0)
+                    .map(|index| (-1 - index) as u32),
+            };
+            if let Some(index) = index {
+                self.constant_indices.push((line, index));
+            }
+            index
+        };
+
+        index.ok_or_else(|| error(line, format!("invalid constant `{word}`")))
+    }
+
+    fn finish(self) -> Result<Proto, Error> {
+        self.labels.check()?;
+
+        let lines = self.lines;
+        let code = self.builder.finish().map_err(|err| match err {
+            Error::JumpOutOfRange { pc } => {
+                error(lines[pc as usize], "jump offset is out of range")
+            }
+            err => err,
+        })?;
+
+        let mut proto = Proto {
+            instructions: code.instructions,
+            constants: code.constants,
+            line_info: code.line_info,
+            max_stack_size: self.stack_size.unwrap_or(code.max_stack_size),
+            ..self.proto
+        };
+
+        if let Some((line, index)) = self
+            .constant_indices
+            .iter()
+            .find(|(_, index)| *index as usize >= proto.constants.len())
+        {
+            return Err(error(*line, format!("constant {index} does not exist")));
+        }
+
+        if let Some((line, index)) = self
+            .child_indices
+            .iter()
+            .find(|(_, index)| *index as usize >= self.children.len())
+        {
+            return Err(error(*line, format!("function {index} does not exist")));
+        }
+
+        for (line, pc, name) in self.closures {
+            let index = self
+                .children
+                .iter()
+                .position(|child| child.as_deref() == Some(&name))
+                .ok_or_else(|| error(line, format!("unknown function `{name}`")))?;
+            let mut instruction = proto.instructions[pc as usize].lua51();
+            instruction.set_bx(index as u32);
+            proto.instructions[pc as usize] = instruction.into();
+        }
+
+        for (line, name, start, end) in self.locals {
+            proto.locals.push(LocalVariable {
+                name: Some(name),
+                start_pc: self.labels.position(line, &start)?,
+                end_pc: self.labels.position(line, &end)?,
+                #[cfg(feature = "luau")]
+                register: 0,
+            });
+        }
+
+        Ok(proto)
+    }
+}
+
+fn register(line: usize, operand: &Token) -> Result<u32, Error> {
+    let word = operand.word().unwrap_or_default();
+    match parse_int(word.strip_prefix(['R', 'r']).unwrap_or(word)) {
+        Some(register @ 0..=255) => Ok(register as u32),
+        _ => Err(error(line, format!("invalid register `{word}`"))),
+    }
+}
+
+fn with_terminator(string: &[u8]) -> Vec<u8> {
+    let mut string = string.to_vec();
+    string.push(0);
+    string
+}
@@ -0,0 +1,185 @@
+use crate::{
+    Constant, Error, Proto,
+    builder::{Labels, intern},
+    opcode::{
+        Instruction, LuaInstruction, LuaOpArgMode, LuaOpcode, MAX_ARG_A, MAX_ARG_B, MAX_ARG_BX,
+        MAX_ARG_C, MAX_ARG_SBX, Opcode, is_k,
+    },
+};
+
+// MAXSTACK of lua 5.1, the verifier rejects larger stacks
+const MAX_STACK_SIZE: u32 = 250;
+
+pub use crate::builder::Label;
+
+/// Emits the instructions of one lua 5.1 proto, jumps are patched and the stack is sized on `finish`.
+///
+/// Constants are stored as in `Proto`, strings keep their terminating zero.
+#[derive(Default)]
+pub struct ProtoBuilder {
+    proto: Proto,
+    labels: Labels,
+    jumps: Vec<(u32, Label)>,
+
+    line: Option<u32>,
+    lines: Vec<u32>,
+    // one past the highest register in use
+    registers: u32,
+}
+
+impl ProtoBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The pc of the next instruction.
+    pub fn pc(&self) -> u32 {
+        self.proto.instructions.len() as u32
+    }
+
+    /// Source line of the instructions emitted from now on, `line_info` stays empty without one.
+    pub fn set_line(&mut self, line: u32) {
+        self.line = Some(line);
+    }
+
+    pub fn constant(&mut self, value: Constant) -> u32 {
+        intern(&mut self.proto.constants, value)
+    }
+
+    pub fn label(&mut self) -> Label {
+        self.labels.create()
+    }
+
+    /// Points `label` at the next instruction, binding it again moves it.
+    ///
+    /// Fails on a label created by another builder.
+    pub fn bind(&mut self, label: Label) -> Result<(), Error> {
+        self.labels.bind(label, self.pc())
+    }
+
+    /// Makes the stack at least `registers` slots large, for ranges the builder can not see.
+    pub fn reserve(&mut self, registers: u32) {
+        self.registers = self.registers.max(registers);
+    }
+
+    /// Fails when an operand does not fit its field.
+    pub fn emit_abc(&mut self, opcode: LuaOpcode, a: u32, b: u32, c: u32) -> Result<u32, Error> {
+        self.check("A", a <= MAX_ARG_A)?;
+        self.check("B", b <= MAX_ARG_B)?;
+        self.check("C", c <= MAX_ARG_C)?;
+
+        self.use_registers(opcode, a, b, c);
+        let instruction = LuaInstruction::from_abc(Opcode::LuaOpcode(opcode), a, b, c);
+        Ok(self.emit(instruction.into()))
+    }
+
+    pub fn emit_abx(&mut self, opcode: LuaOpcode, a: u32, bx: u32) -> Result<u32, Error> {
+        self.check("A", a <= MAX_ARG_A)?;
+        self.check("Bx", bx <= MAX_ARG_BX)?;
+
+        self.use_registers(opcode, a, 0, 0);
+        let instruction = LuaInstruction::from_abx(Opcode::LuaOpcode(opcode), a, bx);
+        Ok(self.emit(instruction.into()))
+    }
+
+    pub fn emit_asbx(&mut self, opcode: LuaOpcode, a: u32, sbx: i32) -> Result<u32, Error> {
+        self.check("A", a <= MAX_ARG_A)?;
+        self.check("sBx", sbx.unsigned_abs() <= MAX_ARG_SBX as u32)?;
+
+        self.use_registers(opcode, a, 0, 0);
+
+        let mut instruction = LuaInstruction(opcode as u32);
+        instruction.set_a(a).set_sbx(sbx);
+        Ok(self.emit(instruction.into()))
+    }
+
+    /// Emits a `JMP`, `FORPREP` or `FORLOOP` whose offset is patched to `label` on `finish`.
+    pub fn emit_jump(&mut self, opcode: LuaOpcode, a: u32, label: Label) -> Result<u32, Error> {
+        let pc = self.emit_asbx(opcode, a, 0)?;
+        self.jumps.push((pc, label));
+        Ok(pc)
+    }
+
+    /// Emits a raw word, like the count `SETLIST` reads when C is zero.
+    pub fn emit_word(&mut self, word: u32) -> u32 {
+        self.emit(Instruction(word))
+    }
+
+    fn check(&self, operand: &'static str, fits: bool) -> Result<(), Error> {
+        match fits {
+            true => Ok(()),
+            false => Err(Error::OperandOutOfRange {
+                pc: self.pc(),
+                operand,
+            }),
+        }
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> u32 {
+        let pc = self.pc();
+        self.proto.instructions.push(instruction);
+        self.lines.push(self.line.unwrap_or_default());
+        pc
+    }
+
+    // the operands are checked first, so the sums below stay far from overflowing
+    fn use_registers(&mut self, opcode: LuaOpcode, a: u32, b: u32, c: u32) {
+        let info = opcode.info();
+
+        // the comparisons keep a flag in A
+        if !matches!(
+            opcode,
+            LuaOpcode::Jmp | LuaOpcode::Eq | LuaOpcode::Lt | LuaOpcode::Le
+        ) {
+            self.reserve(a + 1);
+        }
+
+        for (mode, operand) in [(info.b, b), (info.c, c)] {
+            match mode {
+                LuaOpArgMode::R => self.reserve(operand + 1),
+                LuaOpArgMode::K if !is_k(operand) => self.reserve(operand + 1),
+                _ => (),
+            }
+        }
+
+        let range = match opcode {
+            LuaOpcode::Call | LuaOpcode::TailCall => (a + b).max((a + c).saturating_sub(1)),
+            LuaOpcode::Return | LuaOpcode::Vararg => (a + b).saturating_sub(1),
+            LuaOpcode::SetList => a + b,
+            LuaOpcode::Self_ => a + 2,
+            LuaOpcode::ForPrep | LuaOpcode::ForLoop => a + 4,
+            LuaOpcode::TForLoop => a + 3 + c,
+            _ => 0,
+        };
+        self.reserve(range);
+    }
+
+    /// Patches the jumps and fills `line_info` and `max_stack_size`, which is at least 2 like luac makes it.
+    ///
+    /// Fails when the stack grows past the 250 slots lua 5.1 allows.
+    pub fn finish(mut self) -> Result<Proto, Error> {
+        for (pc, label) in self.jumps.iter() {
+            let offset = self.labels.position(*label)? as i64 - *pc as i64 - 1;
+            if offset.abs() > MAX_ARG_SBX as i64 {
+                return Err(Error::JumpOutOfRange { pc: *pc });
+            }
+
+            let mut instruction = self.proto.instructions[*pc as usize].lua51();
+            instruction.set_sbx(offset as i32);
+            self.proto.instructions[*pc as usize] = instruction.into();
+        }
+
+        if self.line.is_some() {
+            self.proto.line_info = self.lines;
+        }
+
+        if self.registers > MAX_STACK_SIZE {
+            return Err(Error::StackOverflow {
+                size: self.registers,
+            });
+        }
+
+        self.proto.max_stack_size = self.registers.max(2) as u8;
+        Ok(self.proto)
+    }
+}
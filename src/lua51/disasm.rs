@@ -0,0 +1,281 @@
+use std::fmt::Write;
+
+use crate::{
+    Bytecode, Constant, Error, Proto, ProtoTree, RawLuaString,
+    lua51::{DecodedInstruction, Operands, decode_instructions},
+    number::format_g,
+    opcode::{LuaOpArgMode, LuaOpcode, index_k, is_k},
+};
+
+/// Renders every proto of the chunk like `luac -l -l`, the main proto first and children after their parent.
+pub fn disassemble(bytecode: &Bytecode) -> Result<String, Error> {
+    let mut output = String::new();
+
+    // children without a source inherit the one of their parent
+    let mut sources = vec![None; bytecode.protos.len()];
+    for (id, proto) in bytecode.depth_first() {
+        let source = match proto.name.as_deref() {
+            Some(name) if !name.is_empty() => Some(name.to_vec()),
+            _ => sources[id as usize].clone(),
+        };
+
+        for child in proto.protos.iter() {
+            if let Some(slot) = sources.get_mut(*child as usize) {
+                *slot = source.clone();
+            }
+        }
+
+        let is_main = id == bytecode.main_proto_id;
+        disassemble_proto(&mut output, bytecode, id, proto, is_main, source)?;
+    }
+
+    Ok(output)
+}
+
+fn disassemble_proto(
+    output: &mut String,
+    bytecode: &Bytecode,
+    id: u32,
+    proto: &Proto,
+    is_main: bool,
+    source: Option<RawLuaString>,
+) -> Result<(), Error> {
+    let plural = |count: usize| if count == 1 { "" } else { "s" };
+
+    let source = match source.as_deref().map(text) {
+        Some([b'@' | b'=', name @ ..]) => String::from_utf8_lossy(name).into_owned(),
+        Some([0x1b, ..]) => "(bstring)".to_string(),
+        _ => "(string)".to_string(),
+    };
+
+    let count = proto.instructions.len();
+    let _ = writeln!(
+        output,
+        "\n{} <{source}:{},{}> ({count} instruction{}, {} bytes)",
+        if is_main { "main" } else { "function" },
+        proto.line_defined,
+        proto.last_line_defined,
+        plural(count),
+        count * bytecode.header.instruction_size as usize,
+    );
+
+    let parameters = proto.parameter_count as usize;
+    let _ = write!(
+        output,
+        "{parameters}{} param{}, {} slot{}, {} upvalue{}, ",
+        if proto.is_vararg { "+" } else { "" },
+        plural(parameters),
+        proto.max_stack_size,
+        plural(proto.max_stack_size as usize),
+        proto.upvalue_count,
+        plural(proto.upvalue_count as usize),
+    );
+    let _ = writeln!(
+        output,
+        "{} local{}, {} constant{}, {} function{}",
+        proto.locals.len(),
+        plural(proto.locals.len()),
+        proto.constants.len(),
+        plural(proto.constants.len()),
+        proto.protos.len(),
+        plural(proto.protos.len()),
+    );
+
+    for item in decode_instructions(id, &proto.instructions) {
+        let (pc, instruction) = item?;
+        write_instruction(output, proto, pc, &instruction);
+    }
+
+    let _ = writeln!(output, "constants ({}):", proto.constants.len());
+    for (index, constant) in proto.constants.iter().enumerate() {
+        let _ = writeln!(output, "\t{}\t{}", index + 1, constant_text(constant));
+    }
+
+    let _ = writeln!(output, "locals ({}):", proto.locals.len());
+    for (index, local) in proto.locals.iter().enumerate() {
+        let _ = writeln!(
+            output,
+            "\t{index}\t{}\t{}\t{}",
+            String::from_utf8_lossy(text(local.name.as_deref().unwrap_or_default())),
+            local.start_pc + 1,
+            local.end_pc + 1,
+        );
+    }
+
+    let _ = writeln!(output, "upvalues ({}):", proto.upvalues.len());
+    for (index, upvalue) in proto.upvalues.iter().enumerate() {
+        let _ = writeln!(
+            output,
+            "\t{index}\t{}",
+            String::from_utf8_lossy(text(upvalue.as_deref().unwrap_or_default()))
+        );
+    }
+
+    Ok(())
+}
+
+fn write_instruction(
+    output: &mut String,
+    proto: &Proto,
+    pc: u32,
+    instruction: &DecodedInstruction,
+) {
+    let _ = write!(output, "\t{}\t", pc + 1);
+    match proto.line_info.get(pc as usize) {
+        Some(line) if *line > 0 => {
+            let _ = write!(output, "[{line}]\t");
+        }
+        _ => output.push_str("[-]\t"),
+    }
+
+    let opcode = instruction.opcode;
+    let info = opcode.info();
+    let _ = write!(output, "{:<9}\t", opcode.name());
+
+    // constants are printed as negative numbers, -1 being the first
+    let rk = |x: u32| match is_k(x) {
+        true => -1 - index_k(x) as i64,
+        false => x as i64,
+    };
+
+    match instruction.operands {
+        Operands::ABC { a, b, c } => {
+            let _ = write!(output, "{a}");
+            if info.b != LuaOpArgMode::N {
+                let _ = write!(output, " {}", rk(b));
+            }
+            if info.c != LuaOpArgMode::N {
+                let _ = write!(output, " {}", rk(c));
+            }
+        }
+
+        Operands::ABx { a, bx } => match info.b {
+            LuaOpArgMode::K => {
+                let _ = write!(output, "{a} {}", -1 - bx as i64);
+            }
+            _ => {
+                let _ = write!(output, "{a} {bx}");
+            }
+        },
+
+        Operands::AsBx { a, sbx } => match opcode {
+            LuaOpcode::Jmp => {
+                let _ = write!(output, "{sbx}");
+            }
+            _ => {
+                let _ = write!(output, "{a} {sbx}");
+            }
+        },
+    }
+
+    let constant = |index: u32| match proto.constants.get(index as usize) {
+        Some(constant) => constant_text(constant),
+        None => "?".to_string(),
+    };
+    let rk_constant = |x: u32| match is_k(x) {
+        true => constant(index_k(x)),
+        false => "-".to_string(),
+    };
+
+    let comment = match (opcode, instruction.operands) {
+        (LuaOpcode::LoadK, Operands::ABx { bx, .. }) => Some(constant(bx)),
+
+        (LuaOpcode::GetUpval | LuaOpcode::SetUpval, Operands::ABC { b, .. }) => {
+            Some(match proto.upvalues.get(b as usize) {
+                Some(name) => {
+                    String::from_utf8_lossy(text(name.as_deref().unwrap_or_default())).into_owned()
+                }
+                None => "-".to_string(),
+            })
+        }
+
+        (LuaOpcode::GetGlobal | LuaOpcode::SetGlobal, Operands::ABx { bx, .. }) => {
+            Some(match proto.constants.get(bx as usize) {
+                Some(Constant::String(name)) => String::from_utf8_lossy(text(name)).into_owned(),
+                _ => constant(bx),
+            })
+        }
+
+        (LuaOpcode::GetTable | LuaOpcode::Self_, Operands::ABC { c, .. }) if is_k(c) => {
+            Some(rk_constant(c))
+        }
+
+        (
+            LuaOpcode::SetTable
+            | LuaOpcode::Add
+            | LuaOpcode::Sub
+            | LuaOpcode::Mul
+            | LuaOpcode::Div
+            | LuaOpcode::Mod
+            | LuaOpcode::Pow
+            | LuaOpcode::Eq
+            | LuaOpcode::Lt
+            | LuaOpcode::Le,
+            Operands::ABC { b, c, .. },
+        ) if is_k(b) || is_k(c) => Some(format!("{} {}", rk_constant(b), rk_constant(c))),
+
+        (LuaOpcode::Jmp | LuaOpcode::ForLoop | LuaOpcode::ForPrep, Operands::AsBx { sbx, .. }) => {
+            Some(format!("to {}", sbx as i64 + pc as i64 + 2))
+        }
+
+        (LuaOpcode::Closure, Operands::ABx { bx, .. }) => proto
+            .protos
+            .get(bx as usize)
+            .map(|child| format!("proto {child}")),
+
+        (LuaOpcode::SetList, Operands::ABC { c, .. }) => {
+            Some(instruction.extra.unwrap_or(c).to_string())
+        }
+
+        _ => None,
+    };
+
+    if let Some(comment) = comment {
+        let _ = write!(output, "\t; {comment}");
+    }
+    output.push('\n');
+}
+
+fn constant_text(constant: &Constant) -> String {
+    match constant {
+        Constant::Nil => "nil".to_string(),
+        Constant::Bool(value) => value.to_string(),
+        Constant::RawBool(byte) => (*byte != 0).to_string(),
+        // LUA_NUMBER_FMT
+        Constant::Number(value) => format_g(*value, 14),
+        Constant::Integer(value) => value.to_string(),
+        Constant::String(value) => quote(text(value)),
+
+        #[allow(unreachable_patterns)]
+        _ => "?".to_string(),
+    }
+}
+
+// strings are stored with their terminating zero
+fn text(string: &[u8]) -> &[u8] {
+    string.strip_suffix(b"\0").unwrap_or(string)
+}
+
+fn quote(string: &[u8]) -> String {
+    let mut output = String::from("\"");
+    for byte in string {
+        match byte {
+            b'"' => output.push_str("\\\""),
+            b'\\' => output.push_str("\\\\"),
+            0x07 => output.push_str("\\a"),
+            0x08 => output.push_str("\\b"),
+            0x0c => output.push_str("\\f"),
+            b'\n' => output.push_str("\\n"),
+            b'\r' => output.push_str("\\r"),
+            b'\t' => output.push_str("\\t"),
+            0x0b => output.push_str("\\v"),
+            byte if byte.is_ascii_graphic() || *byte == b' ' => output.push(*byte as char),
+            byte => {
+                let _ = write!(output, "\\{byte:03}");
+            }
+        }
+    }
+    output.push('"');
+
+    output
+}
@@ -0,0 +1,341 @@
+use crate::{opcode::Instruction, *};
+use buffer::{Buffer, LuaPrimitive};
+
+pub const LUAC_TAIL: &[u8; 6] = b"\x19\x93\r\n\x1a\n";
+
+pub trait LuaBytecode {
+    fn from(data: &[u8]) -> Result<Bytecode, Error>;
+    fn from_with_options(data: &[u8], options: ParseOptions) -> Result<Bytecode, Error>;
+    fn parse_header(&self, buffer: &mut Buffer) -> Result<Header, Error>;
+    fn parse_proto(
+        &mut self,
+        buffer: &mut Buffer,
+        options: &ParseOptions,
+        depth: u32,
+    ) -> Result<Proto, Error>;
+
+    fn write(&mut self) -> Result<Vec<u8>, Error>;
+    fn write_proto(&self, index: u32, buffer: &mut Buffer) -> Result<(), Error>;
+}
+
+impl LuaBytecode for Bytecode {
+    fn from(data: &[u8]) -> Result<Bytecode, Error> {
+        Self::from_with_options(data, ParseOptions::default())
+    }
+
+    fn from_with_options(data: &[u8], options: ParseOptions) -> Result<Bytecode, Error> {
+        let mut bytecode = Bytecode::default();
+        let mut buffer = Buffer::new(data.to_vec());
+
+        bytecode.header = bytecode.parse_header(&mut buffer)?;
+        buffer.set_big_endian(bytecode.header.is_big_endian);
+
+        // protos are stored in pre-order, the slot is reserved before parsing
+        bytecode.protos.push(Proto::default());
+        bytecode.protos[0] = bytecode.parse_proto(&mut buffer, &options, 0)?;
+        bytecode.main_proto_id = 0;
+
+        Ok(bytecode)
+    }
+
+    fn parse_header(&self, buffer: &mut Buffer) -> Result<Header, Error> {
+        let offset = buffer.position();
+        let magic = buffer.read::<u32>()?;
+        if magic != LUA_MAGIC {
+            return Err(Error::BadMagic { offset, magic });
+        }
+
+        let offset = buffer.position();
+        let version = buffer.read::<u8>()?;
+        if version != 0x52 {
+            return Err(Error::UnsupportedVersion { offset, version });
+        }
+
+        let header = Header {
+            version,
+            format: buffer.read::<u8>()?,
+            is_big_endian: Header::read_endianness(buffer)?,
+            int_size: buffer.read::<u8>()?,
+            size_t_size: buffer.read::<u8>()?,
+            instruction_size: buffer.read::<u8>()?,
+            number_size: buffer.read::<u8>()?,
+            is_number_integral: buffer.read::<bool>()?,
+            ..Default::default()
+        };
+
+        // the sizes follow the version, format and endianness bytes
+        header.check_sizes(offset + 3)?;
+
+        let offset = buffer.position();
+        if buffer.read_bytes(LUAC_TAIL.len() as u64)? != LUAC_TAIL {
+            return Err(Error::Malformed {
+                offset,
+                reason: "header tail does not match LUAC_TAIL",
+            });
+        }
+
+        Ok(header)
+    }
+
+    fn parse_proto(
+        &mut self,
+        buffer: &mut Buffer,
+        options: &ParseOptions,
+        depth: u32,
+    ) -> Result<Proto, Error> {
+        let offset = buffer.position();
+        ParseOptions::check(
+            depth as u64,
+            options.max_depth as u64,
+            offset,
+            "nesting depth",
+        )?;
+
+        // the caller reserved the last slot for this proto
+        let proto_id = self.protos.len() as u32 - 1;
+
+        let header = self.header;
+        let max_length = options.max_string_length;
+        let mut proto = Proto {
+            line_defined: buffer.read_int(&header)?,
+            last_line_defined: buffer.read_int(&header)?,
+            parameter_count: buffer.read::<u8>()?,
+            is_vararg: buffer.read::<bool>()?,
+            max_stack_size: buffer.read::<u8>()?,
+            ..Default::default()
+        };
+
+        let offset = buffer.position();
+        let instruction_count = buffer.read_int(&header)?;
+        ParseOptions::check(
+            instruction_count as u64,
+            options.max_instructions as u64,
+            offset,
+            "instruction count",
+        )?;
+
+        for _ in 0..instruction_count {
+            proto.instructions.push(Instruction(buffer.read::<u32>()?));
+        }
+
+        validate_instructions(proto_id, &proto.instructions)?;
+
+        let constant_count = buffer.read_int(&header)?;
+        for index in 0..constant_count {
+            let offset = buffer.position();
+            let kind = buffer.read::<u8>()?;
+
+            let constant = match kind {
+                constant::LUA_CONSTANT_NIL => Constant::Nil,
+                constant::LUA_CONSTANT_BOOLEAN => Constant::from_bool_byte(buffer.read::<u8>()?),
+                constant::LUA_CONSTANT_NUMBER => Constant::Number(buffer.read_number(&header)?),
+                constant::LUA_CONSTANT_STRING => {
+                    Constant::String(buffer.read_string(&header, max_length)?)
+                }
+
+                tag => {
+                    return Err(Error::UnknownConstantKind {
+                        offset,
+                        proto: proto_id,
+                        index,
+                        tag,
+                    });
+                }
+            };
+
+            proto.constants.push(constant);
+        }
+
+        let offset = buffer.position();
+        let proto_count = buffer.read_int(&header)?;
+        ParseOptions::check(
+            self.protos.len() as u64 + proto_count as u64,
+            options.max_protos as u64,
+            offset,
+            "proto count",
+        )?;
+
+        for _ in 0..proto_count {
+            let child_id = self.protos.len();
+            proto.protos.push(child_id as u32);
+
+            self.protos.push(Proto::default());
+            self.protos[child_id] = self.parse_proto(buffer, options, depth + 1)?;
+        }
+
+        let offset = buffer.position();
+        let upvalue_count = buffer.read_int(&header)?;
+        proto.upvalue_count = u8::try_from(upvalue_count).map_err(|_| Error::Malformed {
+            offset,
+            reason: "too many upvalues",
+        })?;
+
+        for _ in 0..upvalue_count {
+            proto.upvalue_descriptors.push(UpvalueDescriptor {
+                in_stack: buffer.read::<bool>()?,
+                index: buffer.read::<u8>()?,
+                #[cfg(any(feature = "lua54", feature = "luajit"))]
+                kind: UpvalueKind::Regular,
+            });
+        }
+
+        // debug information, the source name is only present in unstripped chunks
+        let source = buffer.read_string(&header, max_length)?;
+        proto.name = if source.is_empty() {
+            None
+        } else {
+            Some(source)
+        };
+
+        let line_info_count = buffer.read_int(&header)?;
+        for _ in 0..line_info_count {
+            proto.line_info.push(buffer.read_int(&header)?);
+        }
+
+        let local_count = buffer.read_int(&header)?;
+        for _ in 0..local_count {
+            proto.locals.push(LocalVariable {
+                name: Some(buffer.read_string(&header, max_length)?),
+                start_pc: buffer.read_int(&header)?,
+                end_pc: buffer.read_int(&header)?,
+                #[cfg(feature = "luau")]
+                register: 0,
+            })
+        }
+
+        let upvalue_count = buffer.read_int(&header)?;
+        for _ in 0..upvalue_count {
+            proto
+                .upvalues
+                .push(Some(buffer.read_string(&header, max_length)?));
+        }
+
+        Ok(proto)
+    }
+
+    fn write(&mut self) -> Result<Vec<u8>, Error> {
+        let mut buffer = Buffer::new(Vec::new());
+
+        buffer.write::<u32>(LUA_MAGIC);
+        buffer.write::<u8>(self.header.version);
+        buffer.write::<u8>(self.header.format);
+        buffer.write::<bool>(!self.header.is_big_endian);
+
+        self.header.check_sizes(buffer.position())?;
+        buffer.write::<u8>(self.header.int_size);
+        buffer.write::<u8>(self.header.size_t_size);
+        buffer.write::<u8>(self.header.instruction_size);
+        buffer.write::<u8>(self.header.number_size);
+        buffer.write::<bool>(self.header.is_number_integral);
+        buffer.set_big_endian(self.header.is_big_endian);
+        buffer.write_bytes(LUAC_TAIL);
+
+        self.write_proto(self.main_proto_id, &mut buffer)?;
+
+        buffer.set_position(0);
+        Ok(buffer.read_all())
+    }
+
+    fn write_proto(&self, index: u32, buffer: &mut Buffer) -> Result<(), Error> {
+        let proto = self
+            .protos
+            .get(index as usize)
+            .ok_or(Error::InvalidProtoRef { proto: index })?;
+        let header = &self.header;
+
+        buffer.write_int(header, proto.line_defined)?;
+        buffer.write_int(header, proto.last_line_defined)?;
+        buffer.write::<u8>(proto.parameter_count);
+        buffer.write::<bool>(proto.is_vararg);
+        buffer.write::<u8>(proto.max_stack_size);
+
+        buffer.write_int(header, proto.instructions.len() as u32)?;
+        for instruction in proto.instructions.iter() {
+            buffer.write::<u32>(instruction.0);
+        }
+
+        buffer.write_int(header, proto.constants.len() as u32)?;
+        for (constant_index, constant) in proto.constants.iter().enumerate() {
+            match constant {
+                Constant::Nil => {
+                    buffer.write::<u8>(constant::LUA_CONSTANT_NIL);
+                }
+
+                Constant::Bool(value) => {
+                    buffer.write::<u8>(constant::LUA_CONSTANT_BOOLEAN);
+                    buffer.write(*value);
+                }
+
+                Constant::RawBool(byte) => {
+                    buffer.write::<u8>(constant::LUA_CONSTANT_BOOLEAN);
+                    buffer.write(*byte);
+                }
+
+                Constant::Number(value) => {
+                    buffer.write::<u8>(constant::LUA_CONSTANT_NUMBER);
+                    buffer.write_number(header, *value, index, constant_index as u32)?;
+                }
+
+                Constant::String(value) => {
+                    buffer.write::<u8>(constant::LUA_CONSTANT_STRING);
+                    buffer.write_string(header, value)?;
+                }
+
+                #[allow(unreachable_patterns)]
+                _ => {
+                    return Err(Error::UnsupportedConstant {
+                        proto: index,
+                        index: constant_index as u32,
+                    });
+                }
+            }
+        }
+
+        buffer.write_int(header, proto.protos.len() as u32)?;
+        for proto in proto.protos.iter() {
+            self.write_proto(*proto, buffer)?;
+        }
+
+        buffer.write_int(header, proto.upvalue_descriptors.len() as u32)?;
+        for upvalue in proto.upvalue_descriptors.iter() {
+            buffer.write::<bool>(upvalue.in_stack);
+            buffer.write::<u8>(upvalue.index);
+        }
+
+        buffer.write_string(header, proto.name.as_deref().unwrap_or_default())?;
+
+        buffer.write_int(header, proto.line_info.len() as u32)?;
+        for line in proto.line_info.iter() {
+            buffer.write_int(header, *line)?;
+        }
+
+        buffer.write_int(header, proto.locals.len() as u32)?;
+        for local in proto.locals.iter() {
+            buffer.write_string(header, local.name.as_deref().unwrap_or_default())?;
+            buffer.write_int(header, local.start_pc)?;
+            buffer.write_int(header, local.end_pc)?;
+        }
+
+        buffer.write_int(header, proto.upvalues.len() as u32)?;
+        for upvalue in proto.upvalues.iter() {
+            buffer.write_string(header, upvalue.as_deref().unwrap_or_default())?;
+        }
+
+        Ok(())
+    }
+}
+
+// unlike 5.1, the extra SETLIST and LOADKX operand is a full EXTRAARG instruction
+fn validate_instructions(proto: u32, instructions: &[Instruction]) -> Result<(), Error> {
+    for (pc, instruction) in instructions.iter().enumerate() {
+        if let Err(opcode) = instruction.lua52().try_opcode() {
+            return Err(Error::InvalidOpcode {
+                proto,
+                pc: pc as u32,
+                opcode,
+            });
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,484 @@
+use crate::{opcode::Instruction, *};
+use buffer::Buffer;
+
+pub const LUAC_DATA: &[u8; 6] = b"\x19\x93\r\n\x1a\n";
+pub const LUAC_INT: i64 = 0x5678;
+pub const LUAC_NUM: f64 = 370.5;
+
+// strings up to this length are dumped with the short string tag
+const LUAI_MAXSHORTLEN: usize = 40;
+
+// LUA_32BITS builds dump 4-byte integers and floats
+fn check_sizes(header: &Header, offset: u64) -> Result<(), Error> {
+    Header::check_supported_sizes(
+        offset,
+        &[
+            (header.int_size, &[1, 2, 4, 8], "unsupported int size"),
+            (header.size_t_size, &[1, 2, 4, 8], "unsupported size_t size"),
+            (
+                header.instruction_size,
+                &[4],
+                "unsupported instruction size",
+            ),
+            (header.integer_size, &[4, 8], "unsupported integer size"),
+            (header.number_size, &[4, 8], "unsupported number size"),
+        ],
+    )
+}
+
+pub trait LuaBytecode {
+    fn from(data: &[u8]) -> Result<Bytecode, Error>;
+    fn from_with_options(data: &[u8], options: ParseOptions) -> Result<Bytecode, Error>;
+    fn parse_header(&self, buffer: &mut Buffer) -> Result<Header, Error>;
+    fn parse_proto(
+        &mut self,
+        buffer: &mut Buffer,
+        options: &ParseOptions,
+        depth: u32,
+    ) -> Result<Proto, Error>;
+
+    fn write(&mut self) -> Result<Vec<u8>, Error>;
+    fn write_proto(&self, index: u32, buffer: &mut Buffer) -> Result<(), Error>;
+}
+
+impl LuaBytecode for Bytecode {
+    fn from(data: &[u8]) -> Result<Bytecode, Error> {
+        Self::from_with_options(data, ParseOptions::default())
+    }
+
+    fn from_with_options(data: &[u8], options: ParseOptions) -> Result<Bytecode, Error> {
+        let mut bytecode = Bytecode::default();
+        let mut buffer = Buffer::new(data.to_vec());
+
+        bytecode.header = bytecode.parse_header(&mut buffer)?;
+
+        // upvalue count of the main closure, the proto repeats it
+        buffer.read::<u8>()?;
+
+        bytecode.protos.push(Proto::default());
+        bytecode.protos[0] = bytecode.parse_proto(&mut buffer, &options, 0)?;
+        bytecode.main_proto_id = 0;
+
+        Ok(bytecode)
+    }
+
+    fn parse_header(&self, buffer: &mut Buffer) -> Result<Header, Error> {
+        let offset = buffer.position();
+        let magic = buffer.read::<u32>()?;
+        if magic != LUA_MAGIC {
+            return Err(Error::BadMagic { offset, magic });
+        }
+
+        let offset = buffer.position();
+        let version = buffer.read::<u8>()?;
+        if version != 0x53 {
+            return Err(Error::UnsupportedVersion { offset, version });
+        }
+
+        let format = buffer.read::<u8>()?;
+
+        let offset = buffer.position();
+        if buffer.read_bytes(LUAC_DATA.len() as u64)? != LUAC_DATA {
+            return Err(Error::Malformed {
+                offset,
+                reason: "header data does not match LUAC_DATA",
+            });
+        }
+
+        let offset = buffer.position();
+        let mut header = Header {
+            version,
+            format,
+            int_size: buffer.read::<u8>()?,
+            size_t_size: buffer.read::<u8>()?,
+            instruction_size: buffer.read::<u8>()?,
+            integer_size: buffer.read::<u8>()?,
+            number_size: buffer.read::<u8>()?,
+            ..Default::default()
+        };
+        check_sizes(&header, offset)?;
+
+        // there is no endianness byte, the byte order is the one LUAC_INT reads back in
+        let offset = buffer.position();
+        if buffer.read_signed(header.integer_size)? != LUAC_INT {
+            buffer.set_position(offset);
+            buffer.set_big_endian(true);
+            header.is_big_endian = true;
+
+            if buffer.read_signed(header.integer_size)? != LUAC_INT {
+                return Err(Error::Malformed {
+                    offset,
+                    reason: "integer check value does not match LUAC_INT",
+                });
+            }
+        }
+
+        let offset = buffer.position();
+        if buffer.read_float(header.number_size)? != LUAC_NUM {
+            return Err(Error::Malformed {
+                offset,
+                reason: "number check value does not match LUAC_NUM",
+            });
+        }
+
+        Ok(header)
+    }
+
+    fn parse_proto(
+        &mut self,
+        buffer: &mut Buffer,
+        options: &ParseOptions,
+        depth: u32,
+    ) -> Result<Proto, Error> {
+        let offset = buffer.position();
+        ParseOptions::check(
+            depth as u64,
+            options.max_depth as u64,
+            offset,
+            "nesting depth",
+        )?;
+
+        // the caller reserved the last slot for this proto
+        let proto_id = self.protos.len() as u32 - 1;
+
+        // a missing source means the proto shares the source of its parent
+        let header = self.header;
+        let max_length = options.max_string_length;
+        let mut proto = Proto {
+            name: buffer.read_string(&header, max_length)?,
+            line_defined: buffer.read_int(&header)?,
+            last_line_defined: buffer.read_int(&header)?,
+            parameter_count: buffer.read::<u8>()?,
+            is_vararg: buffer.read::<bool>()?,
+            max_stack_size: buffer.read::<u8>()?,
+            ..Default::default()
+        };
+
+        let offset = buffer.position();
+        let instruction_count = buffer.read_int(&header)?;
+        ParseOptions::check(
+            instruction_count as u64,
+            options.max_instructions as u64,
+            offset,
+            "instruction count",
+        )?;
+
+        for _ in 0..instruction_count {
+            let instruction = buffer.read::<u32>()?;
+            proto.instructions.push(Instruction(instruction));
+        }
+
+        validate_instructions(proto_id, &proto.instructions)?;
+
+        let constant_count = buffer.read_int(&header)?;
+        for index in 0..constant_count {
+            let offset = buffer.position();
+            let kind = buffer.read::<u8>()?;
+
+            let constant = match kind {
+                constant::LUA53_CONSTANT_NIL => Constant::Nil,
+                constant::LUA53_CONSTANT_BOOLEAN => Constant::from_bool_byte(buffer.read::<u8>()?),
+                constant::LUA53_CONSTANT_FLOAT => {
+                    Constant::Number(buffer.read_float(header.number_size)?)
+                }
+                constant::LUA53_CONSTANT_INTEGER => {
+                    Constant::Integer(buffer.read_signed(header.integer_size)?)
+                }
+                constant::LUA53_CONSTANT_SHORT_STRING | constant::LUA53_CONSTANT_LONG_STRING => {
+                    Constant::String(buffer.read_string(&header, max_length)?.unwrap_or_default())
+                }
+
+                tag => {
+                    return Err(Error::UnknownConstantKind {
+                        offset,
+                        proto: proto_id,
+                        index,
+                        tag,
+                    });
+                }
+            };
+
+            proto.constants.push(constant);
+        }
+
+        let offset = buffer.position();
+        let upvalue_count = buffer.read_int(&header)?;
+        proto.upvalue_count = u8::try_from(upvalue_count).map_err(|_| Error::Malformed {
+            offset,
+            reason: "too many upvalues",
+        })?;
+
+        for _ in 0..upvalue_count {
+            proto.upvalue_descriptors.push(UpvalueDescriptor {
+                in_stack: buffer.read::<bool>()?,
+                index: buffer.read::<u8>()?,
+                #[cfg(any(feature = "lua54", feature = "luajit"))]
+                kind: UpvalueKind::Regular,
+            });
+        }
+
+        let offset = buffer.position();
+        let proto_count = buffer.read_int(&header)?;
+        ParseOptions::check(
+            self.protos.len() as u64 + proto_count as u64,
+            options.max_protos as u64,
+            offset,
+            "proto count",
+        )?;
+
+        for _ in 0..proto_count {
+            let child_id = self.protos.len();
+            proto.protos.push(child_id as u32);
+
+            self.protos.push(Proto::default());
+            self.protos[child_id] = self.parse_proto(buffer, options, depth + 1)?;
+        }
+
+        let line_info_count = buffer.read_int(&header)?;
+        for _ in 0..line_info_count {
+            proto.line_info.push(buffer.read_int(&header)?);
+        }
+
+        let local_count = buffer.read_int(&header)?;
+        for _ in 0..local_count {
+            proto.locals.push(LocalVariable {
+                name: buffer.read_string(&header, max_length)?,
+                start_pc: buffer.read_int(&header)?,
+                end_pc: buffer.read_int(&header)?,
+                #[cfg(feature = "luau")]
+                register: 0,
+            })
+        }
+
+        let upvalue_count = buffer.read_int(&header)?;
+        for _ in 0..upvalue_count {
+            let name = buffer.read_string(&header, max_length)?;
+            proto.upvalues.push(name);
+        }
+
+        Ok(proto)
+    }
+
+    fn write(&mut self) -> Result<Vec<u8>, Error> {
+        let mut buffer = Buffer::new(Vec::new());
+
+        buffer.write::<u32>(LUA_MAGIC);
+        buffer.write::<u8>(self.header.version);
+        buffer.write::<u8>(self.header.format);
+        buffer.write_bytes(LUAC_DATA);
+        check_sizes(&self.header, buffer.position())?;
+        buffer.write::<u8>(self.header.int_size);
+        buffer.write::<u8>(self.header.size_t_size);
+        buffer.write::<u8>(self.header.instruction_size);
+        buffer.write::<u8>(self.header.integer_size);
+        buffer.write::<u8>(self.header.number_size);
+        // the check values fit every supported size
+        let unsupported = || Error::UnsupportedFormat(Format::Lua53);
+        buffer.set_big_endian(self.header.is_big_endian);
+        buffer.write_signed(LUAC_INT, self.header.integer_size, unsupported())?;
+        buffer.write_float(LUAC_NUM, self.header.number_size, unsupported())?;
+
+        let main_proto =
+            self.protos
+                .get(self.main_proto_id as usize)
+                .ok_or(Error::InvalidProtoRef {
+                    proto: self.main_proto_id,
+                })?;
+
+        buffer.write::<u8>(main_proto.upvalue_descriptors.len() as u8);
+        self.write_proto(self.main_proto_id, &mut buffer)?;
+
+        buffer.set_position(0);
+        Ok(buffer.read_all())
+    }
+
+    fn write_proto(&self, index: u32, buffer: &mut Buffer) -> Result<(), Error> {
+        let proto = self
+            .protos
+            .get(index as usize)
+            .ok_or(Error::InvalidProtoRef { proto: index })?;
+
+        let header = &self.header;
+        buffer.write_string(header, proto.name.as_deref())?;
+        buffer.write_int(header, proto.line_defined)?;
+        buffer.write_int(header, proto.last_line_defined)?;
+        buffer.write::<u8>(proto.parameter_count);
+        buffer.write::<bool>(proto.is_vararg);
+        buffer.write::<u8>(proto.max_stack_size);
+
+        buffer.write_int(header, proto.instructions.len() as u32)?;
+        for instruction in proto.instructions.iter() {
+            buffer.write::<u32>(instruction.0);
+        }
+
+        buffer.write_int(header, proto.constants.len() as u32)?;
+        let lossy = |constant_index: usize| Error::LossyConstant {
+            proto: index,
+            index: constant_index as u32,
+        };
+        for (constant_index, constant) in proto.constants.iter().enumerate() {
+            match constant {
+                Constant::Nil => {
+                    buffer.write::<u8>(constant::LUA53_CONSTANT_NIL);
+                }
+
+                Constant::Bool(value) => {
+                    buffer.write::<u8>(constant::LUA53_CONSTANT_BOOLEAN);
+                    buffer.write(*value);
+                }
+
+                Constant::RawBool(byte) => {
+                    buffer.write::<u8>(constant::LUA53_CONSTANT_BOOLEAN);
+                    buffer.write(*byte);
+                }
+
+                Constant::Number(value) => {
+                    buffer.write::<u8>(constant::LUA53_CONSTANT_FLOAT);
+                    buffer.write_float(*value, header.number_size, lossy(constant_index))?;
+                }
+
+                Constant::Integer(value) => {
+                    buffer.write::<u8>(constant::LUA53_CONSTANT_INTEGER);
+                    buffer.write_signed(*value, header.integer_size, lossy(constant_index))?;
+                }
+
+                Constant::String(value) => {
+                    if value.len() <= LUAI_MAXSHORTLEN {
+                        buffer.write::<u8>(constant::LUA53_CONSTANT_SHORT_STRING);
+                    } else {
+                        buffer.write::<u8>(constant::LUA53_CONSTANT_LONG_STRING);
+                    }
+
+                    buffer.write_string(header, Some(value))?;
+                }
+
+                #[allow(unreachable_patterns)]
+                _ => {
+                    return Err(Error::UnsupportedConstant {
+                        proto: index,
+                        index: constant_index as u32,
+                    });
+                }
+            }
+        }
+
+        buffer.write_int(header, proto.upvalue_descriptors.len() as u32)?;
+        for upvalue in proto.upvalue_descriptors.iter() {
+            buffer.write::<bool>(upvalue.in_stack);
+            buffer.write::<u8>(upvalue.index);
+        }
+
+        buffer.write_int(header, proto.protos.len() as u32)?;
+        for proto in proto.protos.iter() {
+            self.write_proto(*proto, buffer)?;
+        }
+
+        buffer.write_int(header, proto.line_info.len() as u32)?;
+        for line in proto.line_info.iter() {
+            buffer.write_int(header, *line)?;
+        }
+
+        buffer.write_int(header, proto.locals.len() as u32)?;
+        for local in proto.locals.iter() {
+            buffer.write_string(header, local.name.as_deref())?;
+            buffer.write_int(header, local.start_pc)?;
+            buffer.write_int(header, local.end_pc)?;
+        }
+
+        buffer.write_int(header, proto.upvalues.len() as u32)?;
+        for upvalue in proto.upvalues.iter() {
+            buffer.write_string(header, upvalue.as_deref())?;
+        }
+
+        Ok(())
+    }
+}
+
+fn validate_instructions(proto: u32, instructions: &[Instruction]) -> Result<(), Error> {
+    for (pc, instruction) in instructions.iter().enumerate() {
+        if let Err(opcode) = instruction.lua53().try_opcode() {
+            return Err(Error::InvalidOpcode {
+                proto,
+                pc: pc as u32,
+                opcode,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+// ints and size_t have the widths of the header, 5.3 strings carry no trailing NUL and
+// store length + 1 in a byte with an 0xff escape to a size_t, 0 means NULL
+trait SizedPrimitive {
+    fn read_int(&mut self, header: &Header) -> Result<u32, Error>;
+    fn read_string(
+        &mut self,
+        header: &Header,
+        max_length: u64,
+    ) -> Result<Option<RawLuaString>, Error>;
+
+    fn write_int(&mut self, header: &Header, value: u32) -> Result<(), Error>;
+    fn write_string(&mut self, header: &Header, string: Option<&[u8]>) -> Result<(), Error>;
+}
+
+impl SizedPrimitive for Buffer {
+    fn read_int(&mut self, header: &Header) -> Result<u32, Error> {
+        let offset = self.position();
+        let value = self.read_unsigned(header.int_size)?;
+
+        u32::try_from(value).map_err(|_| Error::Malformed {
+            offset,
+            reason: "int does not fit in 32 bits",
+        })
+    }
+
+    fn read_string(
+        &mut self,
+        header: &Header,
+        max_length: u64,
+    ) -> Result<Option<RawLuaString>, Error> {
+        let offset = self.position();
+        let size = match self.read::<u8>()? {
+            0xff => self.read_unsigned(header.size_t_size)?,
+            size => size as u64,
+        };
+
+        if size == 0 {
+            return Ok(None);
+        }
+
+        ParseOptions::check(size - 1, max_length, offset, "string length")?;
+        Ok(Some(self.read_bytes(size - 1)?))
+    }
+
+    fn write_int(&mut self, header: &Header, value: u32) -> Result<(), Error> {
+        self.write_unsigned(
+            value as u64,
+            header.int_size,
+            "value does not fit the int size",
+        )
+    }
+
+    fn write_string(&mut self, header: &Header, string: Option<&[u8]>) -> Result<(), Error> {
+        let Some(string) = string else {
+            self.write::<u8>(0);
+            return Ok(());
+        };
+
+        let size = string.len() as u64 + 1;
+        if size < 0xff {
+            self.write::<u8>(size as u8);
+        } else {
+            self.write::<u8>(0xff);
+            self.write_unsigned(
+                size,
+                header.size_t_size,
+                "value does not fit the size_t size",
+            )?;
+        }
+
+        self.write_bytes(string);
+        Ok(())
+    }
+}
@@ -0,0 +1,513 @@
+use crate::{opcode::Instruction, *};
+use buffer::Buffer;
+
+pub const LUAC_DATA: &[u8; 6] = b"\x19\x93\r\n\x1a\n";
+pub const LUAC_INT: i64 = 0x5678;
+pub const LUAC_NUM: f64 = 370.5;
+
+// strings up to this length are dumped with the short string tag
+const LUAI_MAXSHORTLEN: usize = 40;
+
+// LUA_32BITS builds dump 4-byte integers and floats
+fn check_sizes(header: &Header, offset: u64) -> Result<(), Error> {
+    Header::check_supported_sizes(
+        offset,
+        &[
+            (
+                header.instruction_size,
+                &[4],
+                "unsupported instruction size",
+            ),
+            (header.integer_size, &[4, 8], "unsupported integer size"),
+            (header.number_size, &[4, 8], "unsupported number size"),
+        ],
+    )
+}
+
+pub trait LuaBytecode {
+    fn from(data: &[u8]) -> Result<Bytecode, Error>;
+    fn from_with_options(data: &[u8], options: ParseOptions) -> Result<Bytecode, Error>;
+    fn parse_header(&self, buffer: &mut Buffer) -> Result<Header, Error>;
+    fn parse_proto(
+        &mut self,
+        buffer: &mut Buffer,
+        options: &ParseOptions,
+        depth: u32,
+    ) -> Result<Proto, Error>;
+
+    fn write(&mut self) -> Result<Vec<u8>, Error>;
+    fn write_proto(&self, index: u32, buffer: &mut Buffer) -> Result<(), Error>;
+}
+
+impl LuaBytecode for Bytecode {
+    fn from(data: &[u8]) -> Result<Bytecode, Error> {
+        Self::from_with_options(data, ParseOptions::default())
+    }
+
+    fn from_with_options(data: &[u8], options: ParseOptions) -> Result<Bytecode, Error> {
+        let mut bytecode = Bytecode::default();
+        let mut buffer = Buffer::new(data.to_vec());
+
+        bytecode.header = bytecode.parse_header(&mut buffer)?;
+
+        // upvalue count of the main closure, the proto repeats it
+        buffer.read::<u8>()?;
+
+        bytecode.protos.push(Proto::default());
+        bytecode.protos[0] = bytecode.parse_proto(&mut buffer, &options, 0)?;
+        bytecode.main_proto_id = 0;
+
+        Ok(bytecode)
+    }
+
+    fn parse_header(&self, buffer: &mut Buffer) -> Result<Header, Error> {
+        let offset = buffer.position();
+        let magic = buffer.read::<u32>()?;
+        if magic != LUA_MAGIC {
+            return Err(Error::BadMagic { offset, magic });
+        }
+
+        let offset = buffer.position();
+        let version = buffer.read::<u8>()?;
+        if version != 0x54 {
+            return Err(Error::UnsupportedVersion { offset, version });
+        }
+
+        let format = buffer.read::<u8>()?;
+
+        let offset = buffer.position();
+        if buffer.read_bytes(LUAC_DATA.len() as u64)? != LUAC_DATA {
+            return Err(Error::Malformed {
+                offset,
+                reason: "header data does not match LUAC_DATA",
+            });
+        }
+
+        let offset = buffer.position();
+        let mut header = Header {
+            version,
+            format,
+            instruction_size: buffer.read::<u8>()?,
+            integer_size: buffer.read::<u8>()?,
+            number_size: buffer.read::<u8>()?,
+            ..Default::default()
+        };
+        check_sizes(&header, offset)?;
+
+        // there is no endianness byte, the byte order is the one LUAC_INT reads back in
+        let offset = buffer.position();
+        if buffer.read_signed(header.integer_size)? != LUAC_INT {
+            buffer.set_position(offset);
+            buffer.set_big_endian(true);
+            header.is_big_endian = true;
+
+            if buffer.read_signed(header.integer_size)? != LUAC_INT {
+                return Err(Error::Malformed {
+                    offset,
+                    reason: "integer check value does not match LUAC_INT",
+                });
+            }
+        }
+
+        let offset = buffer.position();
+        if buffer.read_float(header.number_size)? != LUAC_NUM {
+            return Err(Error::Malformed {
+                offset,
+                reason: "number check value does not match LUAC_NUM",
+            });
+        }
+
+        Ok(header)
+    }
+
+    fn parse_proto(
+        &mut self,
+        buffer: &mut Buffer,
+        options: &ParseOptions,
+        depth: u32,
+    ) -> Result<Proto, Error> {
+        let offset = buffer.position();
+        ParseOptions::check(
+            depth as u64,
+            options.max_depth as u64,
+            offset,
+            "nesting depth",
+        )?;
+
+        // the caller reserved the last slot for this proto
+        let proto_id = self.protos.len() as u32 - 1;
+
+        // a missing source means the proto shares the source of its parent
+        let max_length = options.max_string_length;
+        let mut proto = Proto {
+            name: buffer.read_string(max_length)?,
+            line_defined: buffer.read_int()?,
+            last_line_defined: buffer.read_int()?,
+            parameter_count: buffer.read::<u8>()?,
+            is_vararg: buffer.read::<bool>()?,
+            max_stack_size: buffer.read::<u8>()?,
+            ..Default::default()
+        };
+
+        let offset = buffer.position();
+        let instruction_count = buffer.read_int()?;
+        ParseOptions::check(
+            instruction_count as u64,
+            options.max_instructions as u64,
+            offset,
+            "instruction count",
+        )?;
+
+        for _ in 0..instruction_count {
+            let instruction = buffer.read::<u32>()?;
+            proto.instructions.push(Instruction(instruction));
+        }
+
+        validate_instructions(proto_id, &proto.instructions)?;
+
+        let constant_count = buffer.read_int()?;
+        for index in 0..constant_count {
+            let offset = buffer.position();
+            let kind = buffer.read::<u8>()?;
+
+            let constant = match kind {
+                constant::LUA54_CONSTANT_NIL => Constant::Nil,
+                constant::LUA54_CONSTANT_FALSE => Constant::Bool(false),
+                constant::LUA54_CONSTANT_TRUE => Constant::Bool(true),
+                constant::LUA54_CONSTANT_INTEGER => {
+                    Constant::Integer(buffer.read_signed(self.header.integer_size)?)
+                }
+                constant::LUA54_CONSTANT_FLOAT => {
+                    Constant::Number(buffer.read_float(self.header.number_size)?)
+                }
+                constant::LUA54_CONSTANT_SHORT_STRING | constant::LUA54_CONSTANT_LONG_STRING => {
+                    Constant::String(buffer.read_string(max_length)?.unwrap_or_default())
+                }
+
+                tag => {
+                    return Err(Error::UnknownConstantKind {
+                        offset,
+                        proto: proto_id,
+                        index,
+                        tag,
+                    });
+                }
+            };
+
+            proto.constants.push(constant);
+        }
+
+        let offset = buffer.position();
+        let upvalue_count = buffer.read_int()?;
+        proto.upvalue_count = u8::try_from(upvalue_count).map_err(|_| Error::Malformed {
+            offset,
+            reason: "too many upvalues",
+        })?;
+
+        for _ in 0..upvalue_count {
+            let in_stack = buffer.read::<bool>()?;
+            let index = buffer.read::<u8>()?;
+
+            let offset = buffer.position();
+            let kind =
+                UpvalueKind::try_from(buffer.read::<u8>()?).map_err(|_| Error::Malformed {
+                    offset,
+                    reason: "unknown upvalue kind",
+                })?;
+
+            proto.upvalue_descriptors.push(UpvalueDescriptor {
+                in_stack,
+                index,
+                kind,
+            });
+        }
+
+        let offset = buffer.position();
+        let proto_count = buffer.read_int()?;
+        ParseOptions::check(
+            self.protos.len() as u64 + proto_count as u64,
+            options.max_protos as u64,
+            offset,
+            "proto count",
+        )?;
+
+        for _ in 0..proto_count {
+            let child_id = self.protos.len();
+            proto.protos.push(child_id as u32);
+
+            self.protos.push(Proto::default());
+            self.protos[child_id] = self.parse_proto(buffer, options, depth + 1)?;
+        }
+
+        // line deltas are signed bytes, kept as raw bytes like luau
+        let line_info_count = buffer.read_int()?;
+        for _ in 0..line_info_count {
+            proto.line_info.push(buffer.read::<u8>()? as u32);
+        }
+
+        let absolute_line_count = buffer.read_int()?;
+        for _ in 0..absolute_line_count {
+            proto.absolute_line_pcs.push(buffer.read_int()?);
+            proto.absolute_line_info.push(buffer.read_int()? as i32);
+        }
+
+        let local_count = buffer.read_int()?;
+        for _ in 0..local_count {
+            proto.locals.push(LocalVariable {
+                name: buffer.read_string(max_length)?,
+                start_pc: buffer.read_int()?,
+                end_pc: buffer.read_int()?,
+                #[cfg(feature = "luau")]
+                register: 0,
+            })
+        }
+
+        let upvalue_count = buffer.read_int()?;
+        for _ in 0..upvalue_count {
+            let name = buffer.read_string(max_length)?;
+            proto.upvalues.push(name);
+        }
+
+        Ok(proto)
+    }
+
+    fn write(&mut self) -> Result<Vec<u8>, Error> {
+        let mut buffer = Buffer::new(Vec::new());
+
+        buffer.write::<u32>(LUA_MAGIC);
+        buffer.write::<u8>(self.header.version);
+        buffer.write::<u8>(self.header.format);
+        buffer.write_bytes(LUAC_DATA);
+        check_sizes(&self.header, buffer.position())?;
+        buffer.write::<u8>(self.header.instruction_size);
+        buffer.write::<u8>(self.header.integer_size);
+        buffer.write::<u8>(self.header.number_size);
+        // the check values fit every supported size
+        let unsupported = || Error::UnsupportedFormat(Format::Lua54);
+        buffer.set_big_endian(self.header.is_big_endian);
+        buffer.write_signed(LUAC_INT, self.header.integer_size, unsupported())?;
+        buffer.write_float(LUAC_NUM, self.header.number_size, unsupported())?;
+
+        let main_proto =
+            self.protos
+                .get(self.main_proto_id as usize)
+                .ok_or(Error::InvalidProtoRef {
+                    proto: self.main_proto_id,
+                })?;
+
+        buffer.write::<u8>(main_proto.upvalue_descriptors.len() as u8);
+        self.write_proto(self.main_proto_id, &mut buffer)?;
+
+        buffer.set_position(0);
+        Ok(buffer.read_all())
+    }
+
+    fn write_proto(&self, index: u32, buffer: &mut Buffer) -> Result<(), Error> {
+        let proto = self
+            .protos
+            .get(index as usize)
+            .ok_or(Error::InvalidProtoRef { proto: index })?;
+
+        buffer.write_string(proto.name.as_deref());
+        buffer.write_int(proto.line_defined);
+        buffer.write_int(proto.last_line_defined);
+        buffer.write::<u8>(proto.parameter_count);
+        buffer.write::<bool>(proto.is_vararg);
+        buffer.write::<u8>(proto.max_stack_size);
+
+        buffer.write_int(proto.instructions.len() as u32);
+        for instruction in proto.instructions.iter() {
+            buffer.write::<u32>(instruction.0);
+        }
+
+        buffer.write_int(proto.constants.len() as u32);
+        let lossy = |constant_index: usize| Error::LossyConstant {
+            proto: index,
+            index: constant_index as u32,
+        };
+        for (constant_index, constant) in proto.constants.iter().enumerate() {
+            match constant {
+                Constant::Nil => {
+                    buffer.write::<u8>(constant::LUA54_CONSTANT_NIL);
+                }
+
+                Constant::Bool(false) => {
+                    buffer.write::<u8>(constant::LUA54_CONSTANT_FALSE);
+                }
+
+                Constant::Bool(true) => {
+                    buffer.write::<u8>(constant::LUA54_CONSTANT_TRUE);
+                }
+
+                Constant::Integer(value) => {
+                    buffer.write::<u8>(constant::LUA54_CONSTANT_INTEGER);
+                    let size = self.header.integer_size;
+                    buffer.write_signed(*value, size, lossy(constant_index))?;
+                }
+
+                Constant::Number(value) => {
+                    buffer.write::<u8>(constant::LUA54_CONSTANT_FLOAT);
+                    let size = self.header.number_size;
+                    buffer.write_float(*value, size, lossy(constant_index))?;
+                }
+
+                Constant::String(value) => {
+                    if value.len() <= LUAI_MAXSHORTLEN {
+                        buffer.write::<u8>(constant::LUA54_CONSTANT_SHORT_STRING);
+                    } else {
+                        buffer.write::<u8>(constant::LUA54_CONSTANT_LONG_STRING);
+                    }
+
+                    buffer.write_string(Some(value));
+                }
+
+                #[allow(unreachable_patterns)]
+                _ => {
+                    return Err(Error::UnsupportedConstant {
+                        proto: index,
+                        index: constant_index as u32,
+                    });
+                }
+            }
+        }
+
+        buffer.write_int(proto.upvalue_descriptors.len() as u32);
+        for upvalue in proto.upvalue_descriptors.iter() {
+            buffer.write::<bool>(upvalue.in_stack);
+            buffer.write::<u8>(upvalue.index);
+            buffer.write::<u8>(upvalue.kind as u8);
+        }
+
+        buffer.write_int(proto.protos.len() as u32);
+        for proto in proto.protos.iter() {
+            self.write_proto(*proto, buffer)?;
+        }
+
+        buffer.write_int(proto.line_info.len() as u32);
+        for line in proto.line_info.iter() {
+            buffer.write::<u8>(*line as u8);
+        }
+
+        let absolute_lines = proto
+            .absolute_line_pcs
+            .iter()
+            .zip(proto.absolute_line_info.iter());
+
+        buffer.write_int(absolute_lines.len() as u32);
+        for (pc, line) in absolute_lines {
+            buffer.write_int(*pc);
+            buffer.write_int(*line as u32);
+        }
+
+        buffer.write_int(proto.locals.len() as u32);
+        for local in proto.locals.iter() {
+            buffer.write_string(local.name.as_deref());
+            buffer.write_int(local.start_pc);
+            buffer.write_int(local.end_pc);
+        }
+
+        buffer.write_int(proto.upvalues.len() as u32);
+        for upvalue in proto.upvalues.iter() {
+            buffer.write_string(upvalue.as_deref());
+        }
+
+        Ok(())
+    }
+}
+
+fn validate_instructions(proto: u32, instructions: &[Instruction]) -> Result<(), Error> {
+    for (pc, instruction) in instructions.iter().enumerate() {
+        if let Err(opcode) = instruction.lua54().try_opcode() {
+            return Err(Error::InvalidOpcode {
+                proto,
+                pc: pc as u32,
+                opcode,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+// sizes are stored most significant group first, the last byte has its high bit set
+trait Varint {
+    fn read_varint(&mut self, limit: u64) -> Result<u64, Error>;
+    fn write_varint(&mut self, value: u64);
+
+    fn read_int(&mut self) -> Result<u32, Error>;
+    fn write_int(&mut self, value: u32);
+
+    fn read_string(&mut self, max_length: u64) -> Result<Option<RawLuaString>, Error>;
+    fn write_string(&mut self, string: Option<&[u8]>);
+}
+
+impl Varint for Buffer {
+    fn read_varint(&mut self, limit: u64) -> Result<u64, Error> {
+        let offset = self.position();
+        let limit = limit >> 7;
+
+        let mut result = 0u64;
+        loop {
+            let byte = self.read::<u8>()?;
+            if result >= limit {
+                return Err(Error::Malformed {
+                    offset,
+                    reason: "variant integer overflow",
+                });
+            }
+
+            result = (result << 7) | (byte & 0x7f) as u64;
+            if byte & 0x80 != 0 {
+                return Ok(result);
+            }
+        }
+    }
+
+    fn write_varint(&mut self, mut value: u64) {
+        let mut bytes = [0u8; 10];
+        let mut start = bytes.len();
+        loop {
+            start -= 1;
+            bytes[start] = (value & 0x7f) as u8;
+            value >>= 7;
+
+            if value == 0 {
+                break;
+            }
+        }
+
+        bytes[bytes.len() - 1] |= 0x80;
+        self.write_bytes(&bytes[start..]);
+    }
+
+    fn read_int(&mut self) -> Result<u32, Error> {
+        Ok(self.read_varint(i32::MAX as u64)? as u32)
+    }
+
+    fn write_int(&mut self, value: u32) {
+        self.write_varint(value as u64);
+    }
+
+    // strings carry no trailing NUL, the size is the length + 1 and 0 means NULL
+    fn read_string(&mut self, max_length: u64) -> Result<Option<RawLuaString>, Error> {
+        let offset = self.position();
+        let size = self.read_varint(u64::MAX)?;
+        if size == 0 {
+            return Ok(None);
+        }
+
+        ParseOptions::check(size - 1, max_length, offset, "string length")?;
+        Ok(Some(self.read_bytes(size - 1)?))
+    }
+
+    fn write_string(&mut self, string: Option<&[u8]>) {
+        match string {
+            Some(string) => {
+                self.write_varint(string.len() as u64 + 1);
+                self.write_bytes(string);
+            }
+
+            None => self.write_varint(0),
+        }
+    }
+}
@@ -0,0 +1,729 @@
+use crate::{opcode::Instruction, *};
+use buffer::Buffer;
+
+pub const LUAJIT_MAGIC: &[u8; 3] = b"\x1bLJ";
+
+pub const BCDUMP_F_BE: u8 = 0x01;
+pub const BCDUMP_F_STRIP: u8 = 0x02;
+pub const BCDUMP_F_FFI: u8 = 0x04;
+pub const BCDUMP_F_FR2: u8 = 0x08;
+const BCDUMP_F_KNOWN: u32 = 0x0f;
+
+pub const PROTO_CHILD: u8 = 0x01;
+pub const PROTO_VARARG: u8 = 0x02;
+
+const PROTO_UV_LOCAL: u16 = 0x8000;
+const PROTO_UV_IMMUTABLE: u16 = 0x4000;
+
+// names of the hidden loop locals, dumped as their index + 1
+const VARNAMES: [&[u8]; 6] = [
+    b"(for index)",
+    b"(for limit)",
+    b"(for step)",
+    b"(for generator)",
+    b"(for state)",
+    b"(for control)",
+];
+
+pub trait LuaBytecode {
+    fn from(data: &[u8]) -> Result<Bytecode, Error>;
+    fn from_with_options(data: &[u8], options: ParseOptions) -> Result<Bytecode, Error>;
+    fn parse_header(&self, buffer: &mut Buffer) -> Result<Header, Error>;
+    fn parse_proto(
+        &mut self,
+        buffer: &mut Buffer,
+        options: &ParseOptions,
+        stack: &mut Vec<u32>,
+    ) -> Result<Proto, Error>;
+
+    fn write(&mut self) -> Result<Vec<u8>, Error>;
+    fn write_proto(&self, index: u32, buffer: &mut Buffer) -> Result<(), Error>;
+}
+
+impl LuaBytecode for Bytecode {
+    fn from(data: &[u8]) -> Result<Bytecode, Error> {
+        Self::from_with_options(data, ParseOptions::default())
+    }
+
+    // protos are dumped children first, each child constant pops the last finished proto
+    fn from_with_options(data: &[u8], options: ParseOptions) -> Result<Bytecode, Error> {
+        let mut bytecode = Bytecode::default();
+        let mut buffer = Buffer::new(data.to_vec());
+
+        bytecode.header = bytecode.parse_header(&mut buffer)?;
+        buffer.set_big_endian(bytecode.header.is_big_endian);
+
+        let mut chunk_name = None;
+        if bytecode.header.luajit_flags & BCDUMP_F_STRIP == 0 {
+            let offset = buffer.position();
+            let length = buffer.read_uleb128()? as u64;
+            ParseOptions::check(length, options.max_string_length, offset, "string length")?;
+
+            chunk_name = Some(buffer.read_bytes(length)?);
+        }
+
+        let mut stack = Vec::new();
+        loop {
+            let offset = buffer.position();
+            let length = buffer.read_uleb128()? as u64;
+            if length == 0 {
+                break;
+            }
+
+            ParseOptions::check(
+                bytecode.protos.len() as u64 + 1,
+                options.max_protos as u64,
+                offset,
+                "proto count",
+            )?;
+
+            let start = buffer.position();
+            let proto = bytecode.parse_proto(&mut buffer, &options, &mut stack)?;
+            if buffer.position() - start != length {
+                return Err(Error::Malformed {
+                    offset,
+                    reason: "proto length does not match its contents",
+                });
+            }
+
+            stack.push(bytecode.protos.len() as u32);
+            bytecode.protos.push(proto);
+        }
+
+        let [main_proto_id] = stack[..] else {
+            return Err(Error::Malformed {
+                offset: buffer.position(),
+                reason: "dump does not end with a single main proto",
+            });
+        };
+
+        bytecode.main_proto_id = main_proto_id;
+        bytecode.protos[main_proto_id as usize].name = chunk_name;
+
+        Ok(bytecode)
+    }
+
+    fn parse_header(&self, buffer: &mut Buffer) -> Result<Header, Error> {
+        let offset = buffer.position();
+        let magic = buffer.read_bytes(LUAJIT_MAGIC.len() as u64)?;
+        if magic != LUAJIT_MAGIC {
+            return Err(Error::BadMagic {
+                offset,
+                magic: u32::from_le_bytes([magic[0], magic[1], magic[2], 0]),
+            });
+        }
+
+        let offset = buffer.position();
+        let version = buffer.read::<u8>()?;
+        if !(1..=2).contains(&version) {
+            return Err(Error::UnsupportedVersion { offset, version });
+        }
+
+        let offset = buffer.position();
+        let flags = buffer.read_uleb128()?;
+        if flags & !BCDUMP_F_KNOWN != 0 || (version == 1 && flags & BCDUMP_F_FR2 as u32 != 0) {
+            return Err(Error::Malformed {
+                offset,
+                reason: "unknown dump flags",
+            });
+        }
+
+        Ok(Header {
+            version,
+            is_big_endian: flags & BCDUMP_F_BE as u32 != 0,
+            instruction_size: 4,
+            luajit_flags: flags as u8,
+            ..Default::default()
+        })
+    }
+
+    fn parse_proto(
+        &mut self,
+        buffer: &mut Buffer,
+        options: &ParseOptions,
+        stack: &mut Vec<u32>,
+    ) -> Result<Proto, Error> {
+        let proto_id = self.protos.len() as u32;
+        let max_length = options.max_string_length;
+
+        let flags = buffer.read::<u8>()?;
+        let mut proto = Proto {
+            flags,
+            is_vararg: flags & PROTO_VARARG != 0,
+            parameter_count: buffer.read::<u8>()?,
+            max_stack_size: buffer.read::<u8>()?,
+            upvalue_count: buffer.read::<u8>()?,
+            ..Default::default()
+        };
+
+        let constant_count = buffer.read_uleb128()?;
+        let number_constant_count = buffer.read_uleb128()?;
+
+        let offset = buffer.position();
+        let instruction_count = buffer.read_uleb128()?;
+        ParseOptions::check(
+            instruction_count as u64,
+            options.max_instructions as u64,
+            offset,
+            "instruction count",
+        )?;
+
+        let mut debug_size = 0;
+        if self.header.luajit_flags & BCDUMP_F_STRIP == 0 {
+            debug_size = buffer.read_uleb128()? as u64;
+            if debug_size > 0 {
+                let offset = buffer.position();
+                proto.line_defined = buffer.read_uleb128()?;
+                proto.last_line_defined = proto
+                    .line_defined
+                    .checked_add(buffer.read_uleb128()?)
+                    .ok_or(Error::Malformed {
+                        offset,
+                        reason: "line count overflows",
+                    })?;
+            }
+        }
+
+        for _ in 0..instruction_count {
+            proto.instructions.push(Instruction(buffer.read::<u32>()?));
+        }
+
+        for (pc, instruction) in proto.instructions.iter().enumerate() {
+            if let Err(opcode) = instruction.luajit().try_opcode(self.header.version) {
+                return Err(Error::InvalidOpcode {
+                    proto: proto_id,
+                    pc: pc as u32,
+                    opcode,
+                });
+            }
+        }
+
+        for _ in 0..proto.upvalue_count {
+            let offset = buffer.position();
+            let upvalue = buffer.read::<u16>()?;
+            let index =
+                u8::try_from(upvalue & !(PROTO_UV_LOCAL | PROTO_UV_IMMUTABLE)).map_err(|_| {
+                    Error::Malformed {
+                        offset,
+                        reason: "upvalue index out of range",
+                    }
+                })?;
+
+            proto.upvalue_descriptors.push(UpvalueDescriptor {
+                in_stack: upvalue & PROTO_UV_LOCAL != 0,
+                index,
+                kind: if upvalue & PROTO_UV_IMMUTABLE != 0 {
+                    UpvalueKind::Const
+                } else {
+                    UpvalueKind::Regular
+                },
+            });
+        }
+
+        for _ in 0..constant_count {
+            let offset = buffer.position();
+            let constant = match buffer.read_uleb128()? {
+                tag if tag == constant::LUAJIT_KGC_CHILD as u32 => {
+                    let child = stack.pop().ok_or(Error::Malformed {
+                        offset,
+                        reason: "child constant without a child proto",
+                    })?;
+
+                    proto.protos.push(child);
+                    Constant::Closure(child)
+                }
+
+                tag if tag == constant::LUAJIT_KGC_TABLE as u32 => {
+                    let array_count = buffer.read_uleb128()?;
+                    let hash_count = buffer.read_uleb128()?;
+
+                    let mut array = Vec::new();
+                    for _ in 0..array_count {
+                        array.push(buffer.read_table_constant(max_length)?);
+                    }
+
+                    let mut hash = Vec::new();
+                    for _ in 0..hash_count {
+                        let key = buffer.read_table_constant(max_length)?;
+                        hash.push((key, buffer.read_table_constant(max_length)?));
+                    }
+
+                    Constant::TemplateTable { array, hash }
+                }
+
+                tag if tag == constant::LUAJIT_KGC_I64 as u32 => {
+                    Constant::Int64(buffer.read_uleb128_pair()? as i64)
+                }
+                tag if tag == constant::LUAJIT_KGC_U64 as u32 => {
+                    Constant::UInt64(buffer.read_uleb128_pair()?)
+                }
+                tag if tag == constant::LUAJIT_KGC_COMPLEX as u32 => Constant::Complex(
+                    f64::from_bits(buffer.read_uleb128_pair()?),
+                    f64::from_bits(buffer.read_uleb128_pair()?),
+                ),
+
+                tag => {
+                    let length = (tag - constant::LUAJIT_KGC_STRING as u32) as u64;
+                    ParseOptions::check(length, max_length, offset, "string length")?;
+                    Constant::String(buffer.read_bytes(length)?)
+                }
+            };
+
+            proto.constants.push(constant);
+        }
+
+        for _ in 0..number_constant_count {
+            let (value, is_number) = buffer.read_uleb128_33()?;
+            let constant = if is_number {
+                let high = buffer.read_uleb128()? as u64;
+                Constant::Number(f64::from_bits(high << 32 | value as u64))
+            } else {
+                Constant::Integer(value as i32 as i64)
+            };
+
+            proto.number_constants.push(constant);
+        }
+
+        if debug_size > 0 {
+            let offset = buffer.position();
+            let line_count = proto.last_line_defined - proto.line_defined;
+            for _ in 0..instruction_count {
+                let line = match line_count {
+                    0..256 => buffer.read::<u8>()? as u32,
+                    256..65536 => buffer.read::<u16>()? as u32,
+                    _ => buffer.read::<u32>()?,
+                };
+
+                proto.line_info.push(line);
+            }
+
+            for _ in 0..proto.upvalue_count {
+                proto
+                    .upvalues
+                    .push(Some(buffer.read_cstring(Vec::new(), max_length)?));
+            }
+
+            let mut last_pc = 0u32;
+            loop {
+                let name = match buffer.read::<u8>()? {
+                    0 => break,
+                    tag if (tag as usize) <= VARNAMES.len() => VARNAMES[tag as usize - 1].to_vec(),
+                    tag => buffer.read_cstring(vec![tag], max_length)?,
+                };
+
+                let start_pc = last_pc.wrapping_add(buffer.read_uleb128()?);
+                let end_pc = start_pc.wrapping_add(buffer.read_uleb128()?);
+                last_pc = start_pc;
+
+                proto.locals.push(LocalVariable {
+                    name: Some(name),
+                    start_pc,
+                    end_pc,
+                    #[cfg(feature = "luau")]
+                    register: 0,
+                });
+            }
+
+            if buffer.position() - offset != debug_size {
+                return Err(Error::Malformed {
+                    offset,
+                    reason: "debug info length does not match its contents",
+                });
+            }
+        }
+
+        Ok(proto)
+    }
+
+    fn write(&mut self) -> Result<Vec<u8>, Error> {
+        let mut buffer = Buffer::new(Vec::new());
+        let flags = self.header.luajit_flags;
+
+        buffer.write_bytes(LUAJIT_MAGIC);
+        buffer.write::<u8>(self.header.version);
+        buffer.write_uleb128(flags as u64);
+        buffer.set_big_endian(flags & BCDUMP_F_BE != 0);
+
+        if flags & BCDUMP_F_STRIP == 0 {
+            let main_proto =
+                self.protos
+                    .get(self.main_proto_id as usize)
+                    .ok_or(Error::InvalidProtoRef {
+                        proto: self.main_proto_id,
+                    })?;
+
+            let chunk_name = main_proto.name.clone().unwrap_or_default();
+            buffer.write_uleb128(chunk_name.len() as u64);
+            buffer.write_bytes(&chunk_name);
+        }
+
+        self.write_proto(self.main_proto_id, &mut buffer)?;
+        buffer.write::<u8>(0);
+
+        buffer.set_position(0);
+        Ok(buffer.read_all())
+    }
+
+    fn write_proto(&self, index: u32, buffer: &mut Buffer) -> Result<(), Error> {
+        let proto = self
+            .protos
+            .get(index as usize)
+            .ok_or(Error::InvalidProtoRef { proto: index })?;
+
+        // the first child constant has to be the last child on the stack
+        for constant in proto.constants.iter().rev() {
+            if let Constant::Closure(child) = constant {
+                self.write_proto(*child, buffer)?;
+            }
+        }
+
+        let strip = self.header.luajit_flags & BCDUMP_F_STRIP != 0;
+
+        let mut body = Buffer::new(Vec::new());
+        body.set_big_endian(self.header.luajit_flags & BCDUMP_F_BE != 0);
+
+        let mut flags = proto.flags & !PROTO_VARARG;
+        if proto.is_vararg {
+            flags |= PROTO_VARARG;
+        }
+
+        body.write::<u8>(flags);
+        body.write::<u8>(proto.parameter_count);
+        body.write::<u8>(proto.max_stack_size);
+        body.write::<u8>(proto.upvalue_descriptors.len() as u8);
+        body.write_uleb128(proto.constants.len() as u64);
+        body.write_uleb128(proto.number_constants.len() as u64);
+        body.write_uleb128(proto.instructions.len() as u64);
+
+        let has_debug =
+            !proto.line_info.is_empty() || !proto.upvalues.is_empty() || !proto.locals.is_empty();
+
+        let mut debug = Buffer::new(Vec::new());
+        debug.set_big_endian(self.header.luajit_flags & BCDUMP_F_BE != 0);
+        if !strip && has_debug {
+            self.write_debug(proto, &mut debug);
+        }
+
+        let debug = {
+            debug.set_position(0);
+            debug.read_all()
+        };
+
+        if !strip {
+            body.write_uleb128(debug.len() as u64);
+            if !debug.is_empty() {
+                body.write_uleb128(proto.line_defined as u64);
+                body.write_uleb128(proto.last_line_defined.wrapping_sub(proto.line_defined) as u64);
+            }
+        }
+
+        for instruction in proto.instructions.iter() {
+            body.write::<u32>(instruction.0);
+        }
+
+        for upvalue in proto.upvalue_descriptors.iter() {
+            let mut value = upvalue.index as u16;
+            if upvalue.in_stack {
+                value |= PROTO_UV_LOCAL;
+            }
+            if upvalue.kind == UpvalueKind::Const {
+                value |= PROTO_UV_IMMUTABLE;
+            }
+
+            body.write::<u16>(value);
+        }
+
+        for (constant_index, constant) in proto.constants.iter().enumerate() {
+            match constant {
+                Constant::Closure(_) => body.write_uleb128(constant::LUAJIT_KGC_CHILD as u64),
+
+                Constant::TemplateTable { array, hash } => {
+                    body.write_uleb128(constant::LUAJIT_KGC_TABLE as u64);
+                    body.write_uleb128(array.len() as u64);
+                    body.write_uleb128(hash.len() as u64);
+
+                    let constant_index = constant_index as u32;
+                    for value in array.iter() {
+                        body.write_table_constant(value, index, constant_index)?;
+                    }
+
+                    for (key, value) in hash.iter() {
+                        body.write_table_constant(key, index, constant_index)?;
+                        body.write_table_constant(value, index, constant_index)?;
+                    }
+                }
+
+                Constant::Int64(value) => {
+                    body.write_uleb128(constant::LUAJIT_KGC_I64 as u64);
+                    body.write_uleb128_pair(*value as u64);
+                }
+
+                Constant::UInt64(value) => {
+                    body.write_uleb128(constant::LUAJIT_KGC_U64 as u64);
+                    body.write_uleb128_pair(*value);
+                }
+
+                Constant::Complex(real, imaginary) => {
+                    body.write_uleb128(constant::LUAJIT_KGC_COMPLEX as u64);
+                    body.write_uleb128_pair(real.to_bits());
+                    body.write_uleb128_pair(imaginary.to_bits());
+                }
+
+                Constant::String(value) => {
+                    body.write_uleb128(constant::LUAJIT_KGC_STRING as u64 + value.len() as u64);
+                    body.write_bytes(value);
+                }
+
+                _ => {
+                    return Err(Error::UnsupportedConstant {
+                        proto: index,
+                        index: constant_index as u32,
+                    });
+                }
+            }
+        }
+
+        for (constant_index, constant) in proto.number_constants.iter().enumerate() {
+            match constant {
+                Constant::Integer(value) if i32::try_from(*value).is_ok() => {
+                    body.write_uleb128_33(*value as u32, false);
+                }
+
+                Constant::Number(value) => {
+                    let bits = value.to_bits();
+                    body.write_uleb128_33(bits as u32, true);
+                    body.write_uleb128(bits >> 32);
+                }
+
+                _ => {
+                    return Err(Error::UnsupportedConstant {
+                        proto: index,
+                        index: (proto.constants.len() + constant_index) as u32,
+                    });
+                }
+            }
+        }
+
+        body.write_bytes(&debug);
+
+        let body = {
+            body.set_position(0);
+            body.read_all()
+        };
+
+        buffer.write_uleb128(body.len() as u64);
+        buffer.write_bytes(&body);
+
+        Ok(())
+    }
+}
+
+impl Bytecode {
+    fn write_debug(&self, proto: &Proto, buffer: &mut Buffer) {
+        let line_count = proto.last_line_defined.wrapping_sub(proto.line_defined);
+        for pc in 0..proto.instructions.len() {
+            let line = proto.line_info.get(pc).copied().unwrap_or_default();
+            match line_count {
+                0..256 => buffer.write::<u8>(line as u8),
+                256..65536 => buffer.write::<u16>(line as u16),
+                _ => buffer.write::<u32>(line),
+            }
+        }
+
+        for index in 0..proto.upvalue_descriptors.len() {
+            let name = proto
+                .upvalues
+                .get(index)
+                .cloned()
+                .flatten()
+                .unwrap_or_default();
+            buffer.write_bytes(&name);
+            buffer.write::<u8>(0);
+        }
+
+        let mut last_pc = 0u32;
+        for local in proto.locals.iter() {
+            let name = local.name.as_deref().unwrap_or_default();
+            match VARNAMES.iter().position(|varname| *varname == name) {
+                Some(index) => buffer.write::<u8>(index as u8 + 1),
+                None => {
+                    buffer.write_bytes(name);
+                    buffer.write::<u8>(0);
+                }
+            }
+
+            buffer.write_uleb128(local.start_pc.wrapping_sub(last_pc) as u64);
+            buffer.write_uleb128(local.end_pc.wrapping_sub(local.start_pc) as u64);
+            last_pc = local.start_pc;
+        }
+
+        buffer.write::<u8>(0);
+    }
+}
+
+trait Uleb128 {
+    fn read_uleb128(&mut self) -> Result<u32, Error>;
+    fn read_uleb128_33(&mut self) -> Result<(u32, bool), Error>;
+    fn read_uleb128_pair(&mut self) -> Result<u64, Error>;
+    fn read_cstring(
+        &mut self,
+        prefix: RawLuaString,
+        max_length: u64,
+    ) -> Result<RawLuaString, Error>;
+    fn read_table_constant(&mut self, max_length: u64) -> Result<Constant, Error>;
+
+    fn write_uleb128(&mut self, value: u64);
+    fn write_uleb128_33(&mut self, value: u32, is_number: bool);
+    fn write_uleb128_pair(&mut self, value: u64);
+    /// Fails with `UnsupportedConstant` on values a template table can not hold.
+    fn write_table_constant(
+        &mut self,
+        constant: &Constant,
+        proto: u32,
+        index: u32,
+    ) -> Result<(), Error>;
+}
+
+impl Uleb128 for Buffer {
+    fn read_uleb128(&mut self) -> Result<u32, Error> {
+        let offset = self.position();
+        let mut result = 0u64;
+        let mut shift = 0;
+
+        loop {
+            let byte = self.read::<u8>()?;
+            if shift >= 35 {
+                return Err(Error::Malformed {
+                    offset,
+                    reason: "uleb128 value is too long",
+                });
+            }
+
+            result |= ((byte & 0x7f) as u64) << shift;
+            shift += 7;
+
+            if byte & 0x80 == 0 {
+                return Ok(result as u32);
+            }
+        }
+    }
+
+    // the lowest bit of the first byte tells numbers from integers
+    fn read_uleb128_33(&mut self) -> Result<(u32, bool), Error> {
+        let offset = self.position();
+        let mut result = 0u64;
+        let mut shift = 0;
+
+        loop {
+            let byte = self.read::<u8>()?;
+            if shift >= 35 {
+                return Err(Error::Malformed {
+                    offset,
+                    reason: "uleb128 value is too long",
+                });
+            }
+
+            result |= ((byte & 0x7f) as u64) << shift;
+            shift += 7;
+
+            if byte & 0x80 == 0 {
+                return Ok(((result >> 1) as u32, result & 1 != 0));
+            }
+        }
+    }
+
+    fn read_uleb128_pair(&mut self) -> Result<u64, Error> {
+        let low = self.read_uleb128()? as u64;
+        let high = self.read_uleb128()? as u64;
+        Ok(high << 32 | low)
+    }
+
+    fn read_cstring(
+        &mut self,
+        mut prefix: RawLuaString,
+        max_length: u64,
+    ) -> Result<RawLuaString, Error> {
+        let offset = self.position();
+        loop {
+            match self.read::<u8>()? {
+                0 => return Ok(prefix),
+                byte => prefix.push(byte),
+            }
+
+            ParseOptions::check(prefix.len() as u64, max_length, offset, "string length")?;
+        }
+    }
+
+    fn read_table_constant(&mut self, max_length: u64) -> Result<Constant, Error> {
+        let offset = self.position();
+        let constant = match self.read_uleb128()? {
+            tag if tag == constant::LUAJIT_KTAB_NIL as u32 => Constant::Nil,
+            tag if tag == constant::LUAJIT_KTAB_FALSE as u32 => Constant::Bool(false),
+            tag if tag == constant::LUAJIT_KTAB_TRUE as u32 => Constant::Bool(true),
+            tag if tag == constant::LUAJIT_KTAB_INTEGER as u32 => {
+                Constant::Integer(self.read_uleb128()? as i32 as i64)
+            }
+            tag if tag == constant::LUAJIT_KTAB_NUMBER as u32 => {
+                Constant::Number(f64::from_bits(self.read_uleb128_pair()?))
+            }
+
+            tag => {
+                let length = (tag - constant::LUAJIT_KTAB_STRING as u32) as u64;
+                ParseOptions::check(length, max_length, offset, "string length")?;
+                Constant::String(self.read_bytes(length)?)
+            }
+        };
+
+        Ok(constant)
+    }
+
+    fn write_uleb128(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.write::<u8>((value & 0x7f) as u8 | 0x80);
+            value >>= 7;
+        }
+
+        self.write::<u8>(value as u8);
+    }
+
+    fn write_uleb128_33(&mut self, value: u32, is_number: bool) {
+        self.write_uleb128((value as u64) << 1 | is_number as u64);
+    }
+
+    fn write_uleb128_pair(&mut self, value: u64) {
+        self.write_uleb128(value & 0xffffffff);
+        self.write_uleb128(value >> 32);
+    }
+
+    fn write_table_constant(
+        &mut self,
+        constant: &Constant,
+        proto: u32,
+        index: u32,
+    ) -> Result<(), Error> {
+        match constant {
+            Constant::Nil => self.write_uleb128(constant::LUAJIT_KTAB_NIL as u64),
+            Constant::Bool(false) => self.write_uleb128(constant::LUAJIT_KTAB_FALSE as u64),
+            Constant::Bool(true) => self.write_uleb128(constant::LUAJIT_KTAB_TRUE as u64),
+
+            Constant::Integer(value) if i32::try_from(*value).is_ok() => {
+                self.write_uleb128(constant::LUAJIT_KTAB_INTEGER as u64);
+                self.write_uleb128(*value as u32 as u64);
+            }
+
+            Constant::Number(value) => {
+                self.write_uleb128(constant::LUAJIT_KTAB_NUMBER as u64);
+                self.write_uleb128_pair(value.to_bits());
+            }
+
+            Constant::String(value) => {
+                self.write_uleb128(constant::LUAJIT_KTAB_STRING as u64 + value.len() as u64);
+                self.write_bytes(value);
+            }
+
+            _ => return Err(Error::UnsupportedConstant { proto, index }),
+        }
+
+        Ok(())
+    }
+}
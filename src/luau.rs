@@ -1,16 +1,324 @@
+use std::collections::{BTreeMap, HashMap};
+
 use crate::buffer::Buffer;
-use crate::opcode::Instruction;
-use crate::{Constant, LocalVariable, Proto, RawLuaString, constant};
+use crate::opcode::{Instruction, LuauOpMode, LuauOpcode, Opcode};
+use crate::{
+    Constant, Error, LocalVariable, ParseOptions, Proto, ProtoTree, RawLuaString, constant,
+};
 
-const LBC_TYPE_TAGGED_USERDATA_END: u8 = 64 + 32;
+pub mod asm;
+pub mod builder;
+pub mod disasm;
+
+const LBC_TYPE_FUNCTION: u8 = 5;
+const LBC_TYPE_ANY: u8 = 15;
 const LBC_TYPE_TAGGED_USERDATA_BASE: u8 = 64;
+const LBC_TYPE_TAGGED_USERDATA_END: u8 = 64 + 32;
+const LBC_TYPE_OPTIONAL_BIT: u8 = 1 << 7;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LuauType {
+    Nil,
+    Boolean,
+    Number,
+    String,
+    Table,
+    Function,
+    Thread,
+    Userdata,
+    Vector,
+    Buffer,
+    Any,
+    /// Key of `LuaBytecode::userdata_type_map`.
+    TaggedUserdata(u8),
+    Unknown(u8),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LuauTypeTag {
+    pub kind: LuauType,
+    pub optional: bool,
+}
+
+impl From<u8> for LuauTypeTag {
+    fn from(tag: u8) -> Self {
+        let kind = match tag & !LBC_TYPE_OPTIONAL_BIT {
+            0 => LuauType::Nil,
+            1 => LuauType::Boolean,
+            2 => LuauType::Number,
+            3 => LuauType::String,
+            4 => LuauType::Table,
+            LBC_TYPE_FUNCTION => LuauType::Function,
+            6 => LuauType::Thread,
+            7 => LuauType::Userdata,
+            8 => LuauType::Vector,
+            9 => LuauType::Buffer,
+            LBC_TYPE_ANY => LuauType::Any,
+            tag @ LBC_TYPE_TAGGED_USERDATA_BASE..LBC_TYPE_TAGGED_USERDATA_END => {
+                LuauType::TaggedUserdata(tag - LBC_TYPE_TAGGED_USERDATA_BASE)
+            }
+            tag => LuauType::Unknown(tag),
+        };
+
+        LuauTypeTag {
+            kind,
+            optional: tag & LBC_TYPE_OPTIONAL_BIT != 0,
+        }
+    }
+}
+
+impl From<LuauTypeTag> for u8 {
+    fn from(tag: LuauTypeTag) -> Self {
+        let kind = match tag.kind {
+            LuauType::Nil => 0,
+            LuauType::Boolean => 1,
+            LuauType::Number => 2,
+            LuauType::String => 3,
+            LuauType::Table => 4,
+            LuauType::Function => LBC_TYPE_FUNCTION,
+            LuauType::Thread => 6,
+            LuauType::Userdata => 7,
+            LuauType::Vector => 8,
+            LuauType::Buffer => 9,
+            LuauType::Any => LBC_TYPE_ANY,
+            LuauType::TaggedUserdata(index) => LBC_TYPE_TAGGED_USERDATA_BASE + index,
+            LuauType::Unknown(tag) => tag,
+        };
+
+        match tag.optional {
+            true => kind | LBC_TYPE_OPTIONAL_BIT,
+            false => kind,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LuauTypedLocal {
+    pub kind: LuauTypeTag,
+    pub register: u8,
+    pub start_pc: u32,
+    pub end_pc: u32,
+}
+
+/// Type info of a proto, types version 1 chunks only carry the argument types.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LuauTypeInfo {
+    /// Argument types of the function signature, `None` when it has none.
+    pub arguments: Option<Vec<LuauTypeTag>>,
+    pub upvalues: Vec<LuauTypeTag>,
+    pub locals: Vec<LuauTypedLocal>,
+}
+
+impl LuauTypeInfo {
+    fn decode(buffer: &mut Buffer, size: u32, types_version: u8) -> Result<Self, Error> {
+        let start = buffer.position();
+        let mut type_info = LuauTypeInfo::default();
+
+        if types_version == 1 {
+            type_info.arguments = Some(Self::decode_signature(buffer, size)?);
+        } else {
+            let signature_size = buffer.read_variant()?;
+            let upvalue_count = buffer.read_variant()?;
+            let local_count = buffer.read_variant()?;
 
-#[derive(Default)]
+            if signature_size != 0 {
+                type_info.arguments = Some(Self::decode_signature(buffer, signature_size)?);
+            }
+
+            for _ in 0..upvalue_count {
+                type_info.upvalues.push(buffer.read::<u8>()?.into());
+            }
+
+            for _ in 0..local_count {
+                let kind = buffer.read::<u8>()?.into();
+                let register = buffer.read::<u8>()?;
+                let start_pc = buffer.read_variant()?;
+                let end_pc = start_pc.wrapping_add(buffer.read_variant()?);
+
+                type_info.locals.push(LuauTypedLocal {
+                    kind,
+                    register,
+                    start_pc,
+                    end_pc,
+                });
+            }
+        }
+
+        if buffer.position() - start != size as u64 {
+            return Err(Error::Malformed {
+                offset: start,
+                reason: "type info length does not match its contents",
+            });
+        }
+
+        Ok(type_info)
+    }
+
+    // a function type tag, the argument count and one tag per argument
+    fn decode_signature(buffer: &mut Buffer, size: u32) -> Result<Vec<LuauTypeTag>, Error> {
+        let offset = buffer.position();
+        if buffer.read::<u8>()? != LBC_TYPE_FUNCTION {
+            return Err(Error::Malformed {
+                offset,
+                reason: "type info signature is not a function type",
+            });
+        }
+
+        let argument_count = buffer.read::<u8>()?;
+        if argument_count as u32 + 2 != size {
+            return Err(Error::Malformed {
+                offset,
+                reason: "type info signature size does not match its argument count",
+            });
+        }
+
+        let mut arguments = Vec::new();
+        for _ in 0..argument_count {
+            arguments.push(buffer.read::<u8>()?.into());
+        }
+
+        Ok(arguments)
+    }
+
+    fn encode(&self, types_version: u8) -> Vec<u8> {
+        let mut signature = Vec::new();
+        if let Some(arguments) = self.arguments.as_ref() {
+            signature.push(LBC_TYPE_FUNCTION);
+            signature.push(arguments.len() as u8);
+            signature.extend(arguments.iter().map(|tag| u8::from(*tag)));
+        }
+
+        if types_version == 1 {
+            return signature;
+        }
+
+        let mut buffer = Buffer::new(Vec::new());
+        buffer.write_variant(signature.len() as u32);
+        buffer.write_variant(self.upvalues.len() as u32);
+        buffer.write_variant(self.locals.len() as u32);
+        buffer.write_bytes(&signature);
+
+        for tag in self.upvalues.iter() {
+            buffer.write::<u8>((*tag).into());
+        }
+
+        for local in self.locals.iter() {
+            buffer.write::<u8>(local.kind.into());
+            buffer.write::<u8>(local.register);
+            buffer.write_variant(local.start_pc);
+            buffer.write_variant(local.end_pc.wrapping_sub(local.start_pc));
+        }
+
+        buffer.set_position(0);
+        buffer.read_all()
+    }
+}
+
+/// A `Constant::Import` path, each id is the index of a string constant.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LuauImport {
+    pub ids: [u32; 3],
+    pub len: u8,
+}
+
+impl LuauImport {
+    /// Builds an import from one to three constant indices below 1024.
+    pub fn new(ids: &[u32]) -> Option<Self> {
+        if ids.is_empty() || ids.len() > 3 || ids.iter().any(|id| *id >= 1024) {
+            return None;
+        }
+
+        let mut import = LuauImport {
+            len: ids.len() as u8,
+            ..Default::default()
+        };
+        import.ids[..ids.len()].copy_from_slice(ids);
+
+        Some(import)
+    }
+
+    pub fn ids(&self) -> &[u32] {
+        &self.ids[..(self.len as usize).min(3)]
+    }
+
+    /// Resolves the path against the constants of the proto that owns the import.
+    pub fn resolve(&self, constants: &[Constant]) -> Option<Vec<RawLuaString>> {
+        self.ids()
+            .iter()
+            .map(|id| match constants.get(*id as usize)? {
+                Constant::String(name) => Some(name.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl From<i32> for LuauImport {
+    fn from(value: i32) -> Self {
+        let value = value as u32;
+
+        LuauImport {
+            ids: [(value >> 20) & 1023, (value >> 10) & 1023, value & 1023],
+            len: (value >> 30) as u8,
+        }
+    }
+}
+
+impl From<LuauImport> for i32 {
+    fn from(import: LuauImport) -> Self {
+        let [a, b, c] = import.ids.map(|id| id & 1023);
+        ((import.len as u32 & 3) << 30 | a << 20 | b << 10 | c) as i32
+    }
+}
+
+/// How `write_with_options` lays out the string table.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum StringTableMode {
+    /// Write `strings` as is, every referenced string must already be in it.
+    #[default]
+    Preserve,
+    /// Rebuild the table from the referenced strings in order of first use.
+    Rebuild,
+    /// Like `Rebuild`, but the most referenced strings get the smallest references.
+    Compact,
+}
+
+struct StringTable {
+    strings: Vec<RawLuaString>,
+    references: HashMap<RawLuaString, u32>,
+}
+
+impl StringTable {
+    fn new(strings: Vec<RawLuaString>) -> Self {
+        let mut references = HashMap::with_capacity(strings.len());
+        for (index, string) in strings.iter().enumerate() {
+            // duplicates resolve to their first occurrence
+            references.entry(string.clone()).or_insert(index as u32 + 1);
+        }
+
+        Self {
+            strings,
+            references,
+        }
+    }
+
+    fn reference(&self, string: &RawLuaString) -> Result<u32, Error> {
+        match self.references.get(string) {
+            Some(reference) => Ok(*reference),
+            None => Err(Error::MissingString {
+                string: string.clone(),
+            }),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
 pub struct LuaBytecode {
     pub version: u8,
     pub types_version: u8,
 
-    pub userdata_type_map: Vec<u32>,
+    /// Userdata type index to the reference of its name, indices may be sparse.
+    pub userdata_type_map: BTreeMap<u8, u32>,
 
     pub protos: Vec<Proto>,
     pub strings: Vec<RawLuaString>,
@@ -19,132 +327,158 @@ pub struct LuaBytecode {
 }
 
 impl LuaBytecode {
-    pub fn from(data: &[u8]) -> Result<LuaBytecode, String> {
+    pub fn from(data: &[u8]) -> Result<LuaBytecode, Error> {
+        Self::from_with_options(data, ParseOptions::default())
+    }
+
+    pub fn from_with_options(data: &[u8], options: ParseOptions) -> Result<LuaBytecode, Error> {
         let mut bytecode = LuaBytecode::default();
         let mut buffer = Buffer::new(data.to_vec());
 
-        bytecode.version = buffer.read::<u8>();
+        bytecode.version = buffer.read::<u8>()?;
         if bytecode.version == 0 {
-            let mut bytes = Vec::new();
-            loop {
-                let character = buffer.read::<u8>();
-                bytes.push(character);
-
-                if character == '\0' as u8 {
-                    break;
-                }
-            }
-
-            let error = std::ffi::CStr::from_bytes_with_nul(bytes.as_slice()).unwrap();
-            return Err(format!(
-                "Error message in bytecode: {}",
-                error.to_str().unwrap()
+            // the rest of the chunk is the compile error message
+            let message = buffer.read_all();
+            return Err(Error::CompileError(
+                String::from_utf8_lossy(&message).into_owned(),
             ));
         } else if bytecode.version < 4 || bytecode.version > 6 {
-            return Err("Bytecode version mismatch".into());
+            return Err(Error::UnsupportedVersion {
+                offset: 0,
+                version: bytecode.version,
+            });
         }
 
         bytecode.types_version = if bytecode.version >= 4 {
-            buffer.read::<u8>()
+            buffer.read::<u8>()?
         } else {
             0
         };
 
         // read string table
-        let string_count = buffer.read_variant();
+        let string_count = buffer.read_variant()?;
         for _ in 0..string_count {
-            bytecode.strings.push(buffer.read_string());
+            bytecode
+                .strings
+                .push(buffer.read_string(options.max_string_length)?);
         }
 
-        let userdata_type_limit = LBC_TYPE_TAGGED_USERDATA_END - LBC_TYPE_TAGGED_USERDATA_BASE;
-        bytecode
-            .userdata_type_map
-            .reserve(userdata_type_limit.into());
-
-        // userdata type remapping table
+        // userdata type remapping table, stored as index + 1 and ended by 0
         if bytecode.types_version == 3 {
-            let mut index = buffer.read::<u8>();
+            let mut index = buffer.read::<u8>()?;
             while index != 0 {
-                let reference = buffer.read_variant();
-                if index - 1 < userdata_type_limit {
-                    bytecode
-                        .userdata_type_map
-                        .insert((index - 1).into(), reference);
-                }
+                let reference = buffer.read_variant()?;
+                bytecode.userdata_type_map.insert(index - 1, reference);
 
-                index = buffer.read::<u8>();
+                index = buffer.read::<u8>()?;
             }
         }
 
         // read proto table
-        let proto_count = buffer.read_variant();
+        let offset = buffer.position();
+        let proto_count = buffer.read_variant()?;
+        ParseOptions::check(
+            proto_count as u64,
+            options.max_protos as u64,
+            offset,
+            "proto count",
+        )?;
+
         for i in 0..proto_count {
-            let proto = bytecode.parse_proto(i as u32, &mut buffer);
+            let proto = bytecode.parse_proto(i, &mut buffer, &options)?;
             bytecode.protos.push(proto);
         }
 
-        bytecode.main_proto_id = buffer.read_variant();
+        bytecode.main_proto_id = buffer.read_variant()?;
         Ok(bytecode)
     }
 
-    fn parse_proto(&self, index: u32, buffer: &mut Buffer) -> Proto {
-        let mut proto = Proto::default();
+    /// Resolves a tagged userdata type to its name through `userdata_type_map`.
+    pub fn userdata_type_name(&self, tag: LuauTypeTag) -> Option<&RawLuaString> {
+        let LuauType::TaggedUserdata(index) = tag.kind else {
+            return None;
+        };
 
-        proto.bytecode_id = index;
+        let reference = *self.userdata_type_map.get(&index)?;
+        self.strings.get(reference.checked_sub(1)? as usize)
+    }
 
-        proto.max_stack_size = buffer.read::<u8>();
-        proto.parameter_count = buffer.read::<u8>();
-        proto.upvalue_count = buffer.read::<u8>();
-        proto.is_vararg = buffer.read::<bool>();
+    fn parse_proto(
+        &self,
+        index: u32,
+        buffer: &mut Buffer,
+        options: &ParseOptions,
+    ) -> Result<Proto, Error> {
+        let mut proto = Proto {
+            bytecode_id: index,
+
+            max_stack_size: buffer.read::<u8>()?,
+            parameter_count: buffer.read::<u8>()?,
+            upvalue_count: buffer.read::<u8>()?,
+            vararg_byte: buffer.read::<u8>()?,
+            ..Default::default()
+        };
+        proto.is_vararg = proto.vararg_byte != 0;
 
         if self.version >= 4 {
-            proto.flags = buffer.read::<u8>();
+            proto.flags = buffer.read::<u8>()?;
             if self.types_version > 0 {
-                let types_size = buffer.read_variant();
+                let types_size = buffer.read_variant()?;
                 if types_size != 0 {
-                    let types = buffer.read::<u8>();
-                    if self.types_version == 2 || self.types_version == 3 {
-                        proto.type_info.push(types);
-                    }
-
-                    if self.types_version == 3 {
-                        proto.remap_userdata_types(&self.userdata_type_map);
-                    }
+                    proto.type_info = Some(LuauTypeInfo::decode(
+                        buffer,
+                        types_size,
+                        self.types_version,
+                    )?);
                 }
-
-                buffer.advance(types_size as u64);
             }
         }
 
-        let code_size = buffer.read_variant();
+        let offset = buffer.position();
+        let code_size = buffer.read_variant()?;
+        ParseOptions::check(
+            code_size as u64,
+            options.max_instructions as u64,
+            offset,
+            "instruction count",
+        )?;
+
         for _ in 0..code_size {
-            let instruction = buffer.read::<u32>();
+            let instruction = buffer.read::<u32>()?;
             let instruction = Instruction::from_bytes(&instruction.to_le_bytes());
             proto.instructions.push(instruction);
         }
 
-        let constant_count = buffer.read_variant();
-        for _ in 0..constant_count {
-            let kind = buffer.read::<u8>();
+        validate_instructions(index, &proto.instructions)?;
+
+        let constant_count = buffer.read_variant()?;
+        for constant_index in 0..constant_count {
+            let offset = buffer.position();
+            let kind = buffer.read::<u8>()?;
 
             let constant = match kind {
                 constant::LUAU_CONSTANT_NIL => Constant::Nil,
-                constant::LUAU_CONSTANT_BOOLEAN => Constant::Bool(buffer.read::<bool>()),
+                constant::LUAU_CONSTANT_BOOLEAN => Constant::from_bool_byte(buffer.read::<u8>()?),
 
-                constant::LUAU_CONSTANT_NUMBER => Constant::Number(buffer.read::<f64>()),
+                constant::LUAU_CONSTANT_NUMBER => Constant::Number(buffer.read::<f64>()?),
 
                 constant::LUAU_CONSTANT_STRING => {
-                    Constant::String(self.string_from_reference(buffer).unwrap())
+                    let offset = buffer.position();
+                    let string = self.string_from_reference(buffer)?;
+                    Constant::String(string.ok_or(Error::InvalidStringRef {
+                        offset,
+                        reference: 0,
+                    })?)
                 }
 
-                constant::LUAU_CONSTANT_IMPORT => Constant::Import(buffer.read::<i32>()),
+                constant::LUAU_CONSTANT_IMPORT => Constant::Import(buffer.read::<i32>()?),
 
                 constant::LUAU_CONSTANT_TABLE => {
-                    let length = buffer.read_variant();
+                    let length = buffer.read_variant()?;
 
                     let mut keys = vec![];
                     for _ in 0..length {
-                        let key = buffer.read_variant();
+                        let key = buffer.read_variant()?;
                         keys.push(key);
                     }
 
@@ -152,90 +486,129 @@ impl LuaBytecode {
                 }
 
                 constant::LUAU_CONSTANT_CLOSURE => {
-                    let proto_id = buffer.read_variant();
+                    let proto_id = buffer.read_variant()?;
                     Constant::Closure(proto_id)
                 }
 
                 constant::LUAU_CONSTANT_VECTOR => Constant::Vector(
-                    buffer.read::<f32>(),
-                    buffer.read::<f32>(),
-                    buffer.read::<f32>(),
-                    buffer.read::<f32>(),
+                    buffer.read::<f32>()?,
+                    buffer.read::<f32>()?,
+                    buffer.read::<f32>()?,
+                    buffer.read::<f32>()?,
                 ),
 
-                _ => unreachable!(),
+                tag => {
+                    return Err(Error::UnknownConstantKind {
+                        offset,
+                        proto: index,
+                        index: constant_index,
+                        tag,
+                    });
+                }
             };
 
             proto.constants.push(constant);
         }
 
-        let children = buffer.read_variant();
+        let children = buffer.read_variant()?;
         for _ in 0..children {
-            let proto_id = buffer.read_variant();
+            let proto_id = buffer.read_variant()?;
             proto.protos.push(proto_id);
         }
 
-        proto.line_defined = buffer.read_variant();
-        proto.name = self.string_from_reference(buffer);
+        proto.line_defined = buffer.read_variant()?;
+        proto.name = self.string_from_reference(buffer)?;
 
-        let has_lineinfo = buffer.read::<u8>() != 0;
+        let has_lineinfo = buffer.read::<u8>()? != 0;
         if has_lineinfo {
-            proto.linegaplog2 = buffer.read::<u8>();
+            let offset = buffer.position();
+            proto.linegaplog2 = buffer.read::<u8>()?;
+            if proto.instructions.is_empty() || proto.linegaplog2 >= 32 {
+                return Err(Error::Malformed {
+                    offset,
+                    reason: "line info for an empty or oversized proto",
+                });
+            }
+
             let intervals = ((proto.instructions.len() - 1) >> proto.linegaplog2) + 1;
 
             for _ in 0..proto.instructions.len() {
-                let last_offset = buffer.read::<u8>();
+                let last_offset = buffer.read::<u8>()?;
                 proto.line_info.push(last_offset as u32);
             }
 
             for _ in 0..intervals {
-                let last_line = buffer.read::<i32>();
+                let last_line = buffer.read::<i32>()?;
                 proto.absolute_line_info.push(last_line);
             }
         }
 
-        let has_debuginfo = buffer.read::<bool>();
-        if has_debuginfo {
-            let locvar_count = buffer.read_variant();
+        proto.has_debug_info = buffer.read::<bool>()?;
+        if proto.has_debug_info {
+            let locvar_count = buffer.read_variant()?;
+            // reference 0 is a NULL name
             for _ in 0..locvar_count {
                 proto.locals.push(LocalVariable {
-                    name: self.string_from_reference(buffer).unwrap(),
-                    start_pc: buffer.read_variant(),
-                    end_pc: buffer.read_variant(),
-                    register: buffer.read::<u8>(),
+                    name: self.string_from_reference(buffer)?,
+                    start_pc: buffer.read_variant()?,
+                    end_pc: buffer.read_variant()?,
+                    register: buffer.read::<u8>()?,
                 });
             }
 
-            let upvalue_count = buffer.read_variant();
-            assert_eq!(upvalue_count as u8, proto.upvalue_count);
+            let offset = buffer.position();
+            let upvalue_count = buffer.read_variant()?;
+            if upvalue_count != proto.upvalue_count as u32 {
+                return Err(Error::Malformed {
+                    offset,
+                    reason: "upvalue name count does not match upvalue count",
+                });
+            }
 
             for _ in 0..upvalue_count {
-                proto
-                    .upvalues
-                    .push(self.string_from_reference(buffer).unwrap());
+                proto.upvalues.push(self.string_from_reference(buffer)?);
             }
         }
 
-        proto
+        Ok(proto)
+    }
+
+    pub fn write(&self) -> Result<Vec<u8>, Error> {
+        self.write_with_options(StringTableMode::Preserve)
     }
 
-    pub fn write(&self) -> Vec<u8> {
+    pub fn write_with_options(&self, mode: StringTableMode) -> Result<Vec<u8>, Error> {
         let mut buffer = Buffer::new(Vec::new());
 
         buffer.write(self.version);
         buffer.write(self.types_version);
 
+        let strings = match mode {
+            StringTableMode::Preserve => StringTable::new(self.strings.clone()),
+            _ => self.rebuild_strings(mode == StringTableMode::Compact)?,
+        };
+
         // write string table
-        buffer.write_variant(self.strings.len() as u32);
-        for string in self.strings.iter() {
+        buffer.write_variant(strings.strings.len() as u32);
+        for string in strings.strings.iter() {
             buffer.write_string(string.clone());
         }
 
         // write userdata type remapping table
         if self.types_version == 3 {
-            for (index, name_reference) in self.userdata_type_map.iter().enumerate() {
-                buffer.write::<u8>(index as u8 + 1);
-                buffer.write_variant(*name_reference);
+            for (index, name_reference) in self.userdata_type_map.iter() {
+                let name_reference = match mode {
+                    StringTableMode::Preserve => *name_reference,
+                    _ => strings.reference(&self.userdata_type_string(*name_reference)?)?,
+                };
+
+                // 0 ends the table, so the last index has no encoding
+                let index = index.checked_add(1).ok_or(Error::ValueOutOfRange {
+                    proto: self.main_proto_id,
+                    value: "userdata type index",
+                })?;
+                buffer.write::<u8>(index);
+                buffer.write_variant(name_reference);
             }
             buffer.write::<u8>(0);
         }
@@ -243,33 +616,44 @@ impl LuaBytecode {
         // write proto table
         buffer.write_variant(self.protos.len() as u32);
         for i in 0..self.protos.len() {
-            self.write_proto(i as u32, &mut buffer);
+            self.write_proto(i as u32, &strings, &mut buffer)?;
         }
 
         buffer.write_variant(self.main_proto_id);
 
         buffer.set_position(0);
-        buffer.read_all()
+        Ok(buffer.read_all())
     }
 
-    fn write_proto(&self, index: u32, buffer: &mut Buffer) {
+    fn write_proto(
+        &self,
+        index: u32,
+        strings: &StringTable,
+        buffer: &mut Buffer,
+    ) -> Result<(), Error> {
         let proto = &self.protos[index as usize];
 
         buffer.write(proto.max_stack_size);
         buffer.write(proto.parameter_count);
-        buffer.write(proto.upvalue_count as u8);
-        buffer.write(proto.is_vararg as u8);
+        buffer.write(proto.upvalue_count);
+        // the raw byte survives as long as it still agrees with is_vararg
+        buffer.write::<u8>(match (proto.is_vararg, proto.vararg_byte) {
+            (false, _) => 0,
+            (true, 0) => 1,
+            (true, byte) => byte,
+        });
 
         if self.version >= 4 {
             buffer.write::<u8>(proto.flags);
             if self.types_version > 0 {
-                buffer.write_variant(proto.type_info.len() as u32);
-
-                if proto.type_info.len() > 0 {
-                    for i in 0..proto.type_info.len() {
-                        buffer.write::<u8>(proto.type_info[i]);
-                    }
-                }
+                let type_info = proto
+                    .type_info
+                    .as_ref()
+                    .map(|type_info| type_info.encode(self.types_version))
+                    .unwrap_or_default();
+
+                buffer.write_variant(type_info.len() as u32);
+                buffer.write_bytes(&type_info);
             }
         }
 
@@ -279,17 +663,18 @@ impl LuaBytecode {
         }
 
         buffer.write_variant(proto.constants.len() as u32);
-        for constant in proto.constants.iter() {
+        for (constant_index, constant) in proto.constants.iter().enumerate() {
             buffer.write::<u8>(constant.kind_luau());
 
             match constant {
                 Constant::Nil => (),
 
                 Constant::Bool(value) => buffer.write(*value),
+                Constant::RawBool(byte) => buffer.write(*byte),
                 Constant::Number(value) => buffer.write(*value),
 
                 Constant::String(value) => {
-                    let reference = self.string_reference(value.clone());
+                    let reference = strings.reference(value)?;
                     buffer.write_variant(reference);
                 }
 
@@ -305,13 +690,21 @@ impl LuaBytecode {
                 }
 
                 Constant::Vector(x, y, z, w) => {
-                    buffer.write(x);
-                    buffer.write(y);
-                    buffer.write(z);
-                    buffer.write(w);
+                    buffer.write(*x);
+                    buffer.write(*y);
+                    buffer.write(*z);
+                    buffer.write(*w);
                 }
 
                 Constant::Import(value) => buffer.write(*value),
+
+                #[allow(unreachable_patterns)]
+                _ => {
+                    return Err(Error::UnsupportedConstant {
+                        proto: index,
+                        index: constant_index as u32,
+                    });
+                }
             }
         }
 
@@ -320,37 +713,42 @@ impl LuaBytecode {
             buffer.write_variant(*proto);
         }
 
-        buffer.write_variant(proto.line_defined as u32);
-        if proto.name.is_some() {
-            let name_reference = self.string_reference(proto.name.as_ref().unwrap().clone());
+        buffer.write_variant(proto.line_defined);
+        if let Some(name) = proto.name.as_ref() {
+            let name_reference = strings.reference(name)?;
             buffer.write_variant(name_reference);
         } else {
             buffer.write_variant(0);
         }
 
-        let has_lines = proto.line_info.len() > 0 || proto.absolute_line_info.len() > 0;
+        let has_lines = !proto.line_info.is_empty() || !proto.absolute_line_info.is_empty();
         if has_lines {
             buffer.write::<u8>(1);
             buffer.write::<u8>(proto.linegaplog2);
 
             for i in 0..proto.instructions.len() {
-                buffer.write::<u8>(proto.line_info[i] as u8);
+                let line = proto.line_info.get(i).copied().unwrap_or_default();
+                buffer.write::<u8>(line as u8);
             }
 
-            for i in 0..proto.absolute_line_info.len() {
-                buffer.write::<i32>(proto.absolute_line_info[i]);
+            for line in proto.absolute_line_info.iter() {
+                buffer.write::<i32>(*line);
             }
         } else {
             buffer.write::<u8>(0);
         }
 
-        let has_debug = proto.locals.len() > 0 || proto.upvalues.len() > 0;
+        let has_debug =
+            proto.has_debug_info || !proto.locals.is_empty() || !proto.upvalues.is_empty();
         if has_debug {
             buffer.write::<u8>(1);
 
             buffer.write_variant(proto.locals.len() as u32);
             for local in proto.locals.iter() {
-                let name_reference = self.string_reference(local.name.clone());
+                let name_reference = match local.name.as_ref() {
+                    Some(name) => strings.reference(name)?,
+                    None => 0,
+                };
                 buffer.write_variant(name_reference);
                 buffer.write_variant(local.start_pc);
                 buffer.write_variant(local.end_pc);
@@ -359,97 +757,306 @@ impl LuaBytecode {
 
             buffer.write_variant(proto.upvalues.len() as u32);
             for upvalue in proto.upvalues.iter() {
-                let reference = self.string_reference(upvalue.clone());
+                let reference = match upvalue.as_ref() {
+                    Some(name) => strings.reference(name)?,
+                    None => 0,
+                };
                 buffer.write_variant(reference);
             }
         } else {
             buffer.write::<u8>(0);
         }
+
+        Ok(())
     }
 
-    fn string_from_reference(&self, buffer: &mut Buffer) -> Option<RawLuaString> {
-        let id = buffer.read_variant();
+    fn string_from_reference(&self, buffer: &mut Buffer) -> Result<Option<RawLuaString>, Error> {
+        let offset = buffer.position();
+        let id = buffer.read_variant()?;
         if id == 0 {
-            return None;
+            return Ok(None);
         }
 
-        Some(self.strings.get(id as usize - 1).unwrap().clone())
+        match self.strings.get(id as usize - 1) {
+            Some(string) => Ok(Some(string.clone())),
+            None => Err(Error::InvalidStringRef {
+                offset,
+                reference: id,
+            }),
+        }
     }
 
-    fn string_reference(&self, string: RawLuaString) -> u32 {
-        self.strings.iter().position(|s| s == &string).unwrap() as u32 + 1
+    fn userdata_type_string(&self, reference: u32) -> Result<RawLuaString, Error> {
+        match reference
+            .checked_sub(1)
+            .and_then(|id| self.strings.get(id as usize))
+        {
+            Some(string) => Ok(string.clone()),
+            None => Err(Error::InvalidStringRef {
+                offset: 0,
+                reference,
+            }),
+        }
+    }
+
+    // every string the chunk references, in order of first use
+    fn rebuild_strings(&self, compact: bool) -> Result<StringTable, Error> {
+        let mut strings = Vec::new();
+        let mut counts: HashMap<RawLuaString, u32> = HashMap::new();
+
+        let mut add = |string: &RawLuaString| {
+            let count = counts.entry(string.clone()).or_default();
+            if *count == 0 {
+                strings.push(string.clone());
+            }
+            *count += 1;
+        };
+
+        if self.types_version == 3 {
+            for reference in self.userdata_type_map.values() {
+                add(&self.userdata_type_string(*reference)?);
+            }
+        }
+
+        for proto in self.protos.iter() {
+            for constant in proto.constants.iter() {
+                if let Constant::String(value) = constant {
+                    add(value);
+                }
+            }
+
+            if let Some(name) = proto.name.as_ref() {
+                add(name);
+            }
+
+            for name in proto.locals.iter().filter_map(|local| local.name.as_ref()) {
+                add(name);
+            }
+
+            for name in proto.upvalues.iter().flatten() {
+                add(name);
+            }
+        }
+
+        if compact {
+            // stable, so equally used strings keep their first use order
+            strings.sort_by_key(|string| std::cmp::Reverse(counts[string]));
+        }
+
+        Ok(StringTable::new(strings))
     }
 }
 
-trait LuauProto {
-    fn remap_userdata_types(&mut self, userdata_type_map: &Vec<u32>);
+impl ProtoTree for LuaBytecode {
+    fn protos(&self) -> &[Proto] {
+        &self.protos
+    }
+
+    fn main_proto_id(&self) -> u32 {
+        self.main_proto_id
+    }
 }
 
-impl LuauProto for Proto {
-    fn remap_userdata_types(&mut self, userdata_type_map: &Vec<u32>) {
-        let buffer = &mut Buffer::new(self.type_info.clone());
+fn validate_instructions(proto: u32, instructions: &[Instruction]) -> Result<(), Error> {
+    decode_instructions(proto, instructions).try_for_each(|item| item.map(|_| ()))
+}
 
-        let count = LBC_TYPE_TAGGED_USERDATA_END - LBC_TYPE_TAGGED_USERDATA_BASE;
+/// Source line of every instruction, empty when the proto has no line info.
+pub fn instruction_lines(proto: &Proto) -> Vec<i32> {
+    if proto.absolute_line_info.is_empty() {
+        return Vec::new();
+    }
 
-        let type_size = buffer.read_variant();
-        let upvalue_count = buffer.read_variant();
-        let local_count = buffer.read_variant();
+    // both tables are stored as deltas
+    let mut absolute_lines = Vec::with_capacity(proto.absolute_line_info.len());
+    let mut last_line = 0i32;
+    for delta in proto.absolute_line_info.iter() {
+        last_line = last_line.wrapping_add(*delta);
+        absolute_lines.push(last_line);
+    }
 
-        if type_size != 0 {
-            for i in 2..type_size {
-                let _type = buffer.read::<u8>();
-                let index = _type - LBC_TYPE_TAGGED_USERDATA_BASE;
-                if index < count {
-                    buffer.write::<u8>((userdata_type_map[index as usize]) as u8);
-                }
+    let mut last_offset = 0u8;
+    (0..proto.instructions.len())
+        .map(|pc| {
+            let delta = proto.line_info.get(pc).copied().unwrap_or_default();
+            last_offset = last_offset.wrapping_add(delta as u8);
+
+            let interval = pc >> proto.linegaplog2.min(31);
+            let base = absolute_lines.get(interval).copied().unwrap_or(last_line);
+            base.wrapping_add(last_offset as i32)
+        })
+        .collect()
+}
+
+/// Encodes one source line per instruction into the delta tables, the inverse of `instruction_lines`.
+pub fn set_instruction_lines(proto: &mut Proto, lines: &[i32]) {
+    proto.line_info.clear();
+    proto.absolute_line_info.clear();
+    proto.linegaplog2 = 0;
+    if lines.is_empty() {
+        return;
+    }
+
+    // shrink the interval until every line of it is within 255 of its lowest one
+    let mut span = 1usize << 24;
+    let mut offset = 0;
+    while offset < lines.len() {
+        let (mut min, mut max) = (lines[offset], lines[offset]);
+        let mut next = offset;
+        while next < lines.len() && next < offset + span {
+            min = min.min(lines[next]);
+            max = max.max(lines[next]);
+            if max as i64 - min as i64 > 255 {
+                break;
             }
+            next += 1;
+        }
 
-            buffer.advance(type_size as u64);
+        if next < lines.len() && next - offset < span {
+            span = 1 << (usize::BITS - 1 - (next - offset).leading_zeros());
         }
+        offset += span;
+    }
 
-        if upvalue_count != 0 {
-            for i in 0..upvalue_count {
-                let _type = buffer.read::<u8>();
-                let index = _type - LBC_TYPE_TAGGED_USERDATA_BASE;
-                if index < count {
-                    buffer.write::<u8>((userdata_type_map[index as usize]) as u8);
-                }
+    proto.linegaplog2 = span.trailing_zeros() as u8;
+
+    let baselines = lines
+        .chunks(span)
+        .map(|interval| interval.iter().copied().min().unwrap())
+        .collect::<Vec<_>>();
+
+    let mut last_offset = 0u8;
+    for (pc, line) in lines.iter().enumerate() {
+        let offset = line.wrapping_sub(baselines[pc >> proto.linegaplog2]) as u8;
+        proto
+            .line_info
+            .push(offset.wrapping_sub(last_offset) as u32);
+        last_offset = offset;
+    }
+
+    let mut last_line = 0i32;
+    for baseline in baselines {
+        proto
+            .absolute_line_info
+            .push(baseline.wrapping_sub(last_line));
+        last_line = baseline;
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Operands {
+    ABC { a: u32, b: u32, c: u32 },
+    AD { a: u32, d: i32 },
+    E { e: i32 },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DecodedInstruction {
+    pub opcode: LuauOpcode,
+    pub operands: Operands,
+    pub aux: Option<u32>,
+}
+
+/// Walks the instructions of a proto, yielding `(pc, instruction)` with AUX words attached.
+pub fn decode_instructions(proto: u32, instructions: &[Instruction]) -> DecodedInstructions<'_> {
+    DecodedInstructions {
+        proto,
+        instructions,
+        pc: 0,
+    }
+}
+
+pub struct DecodedInstructions<'a> {
+    proto: u32,
+    instructions: &'a [Instruction],
+    pc: usize,
+}
+
+impl Iterator for DecodedInstructions<'_> {
+    type Item = Result<(u32, DecodedInstruction), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pc = self.pc;
+        let instruction = self.instructions.get(pc)?.luau();
+
+        // errors end the walk
+        self.pc = self.instructions.len();
+
+        let opcode = match instruction.try_opcode() {
+            Ok(Opcode::LuauOpcode(opcode)) => opcode,
+            #[allow(unreachable_patterns)]
+            Ok(_) => unreachable!(),
+
+            Err(opcode) => {
+                return Some(Err(Error::InvalidOpcode {
+                    proto: self.proto,
+                    pc: pc as u32,
+                    opcode,
+                }));
             }
+        };
 
-            buffer.advance(upvalue_count as u64);
-        }
+        let operands = match opcode.mode() {
+            LuauOpMode::ABC => Operands::ABC {
+                a: instruction.a(),
+                b: instruction.b(),
+                c: instruction.c(),
+            },
+            LuauOpMode::AD => Operands::AD {
+                a: instruction.a(),
+                d: instruction.d(),
+            },
+            LuauOpMode::E => Operands::E { e: instruction.e() },
+        };
 
-        if local_count != 0 {
-            for i in 0..local_count {
-                let _type = buffer.read::<u8>();
-                let index = _type - LBC_TYPE_TAGGED_USERDATA_BASE;
-                if index < count {
-                    buffer.write::<u8>((userdata_type_map[index as usize]) as u8);
+        let aux = match opcode.length() {
+            2 => match self.instructions.get(pc + 1) {
+                Some(aux) => Some(aux.0),
+                None => {
+                    return Some(Err(Error::TruncatedInstruction {
+                        proto: self.proto,
+                        pc: pc as u32,
+                    }));
                 }
+            },
+            _ => None,
+        };
 
-                buffer.advance(2);
-                buffer.read_variant();
-                buffer.read_variant();
-            }
-        }
+        self.pc = pc + opcode.length() as usize;
+        Some(Ok((
+            pc as u32,
+            DecodedInstruction {
+                opcode,
+                operands,
+                aux,
+            },
+        )))
     }
 }
 
 trait Variant {
-    fn read_variant(&mut self) -> u32;
+    fn read_variant(&mut self) -> Result<u32, Error>;
     fn write_variant(&mut self, value: u32);
 
-    fn read_string(&mut self) -> RawLuaString;
+    fn read_string(&mut self, max_length: u64) -> Result<RawLuaString, Error>;
     fn write_string(&mut self, string: RawLuaString);
 }
 
 impl Variant for Buffer {
-    fn read_variant(&mut self) -> u32 {
+    fn read_variant(&mut self) -> Result<u32, Error> {
+        let offset = self.position();
         let mut value: u32 = 0;
         let mut shift: u32 = 0;
 
         loop {
-            let byte = self.read::<u8>() as u8;
+            let byte = self.read::<u8>()?;
+            if shift >= 32 {
+                return Err(Error::Malformed {
+                    offset,
+                    reason: "variant integer is too long",
+                });
+            }
+
             value |= (byte as u32 & 127) << shift;
             shift += 7;
 
@@ -458,7 +1065,7 @@ impl Variant for Buffer {
             }
         }
 
-        value
+        Ok(value)
     }
 
     fn write_variant(&mut self, mut value: u32) {
@@ -471,21 +1078,16 @@ impl Variant for Buffer {
         }
     }
 
-    fn read_string(&mut self) -> RawLuaString {
-        let mut bytes = Vec::new();
+    fn read_string(&mut self, max_length: u64) -> Result<RawLuaString, Error> {
+        let offset = self.position();
+        let length = self.read_variant()?;
+        ParseOptions::check(length as u64, max_length, offset, "string length")?;
 
-        let length = self.read_variant();
-        for _ in 0..length {
-            bytes.push(self.read::<u8>());
-        }
-
-        bytes
+        self.read_bytes(length as u64)
     }
 
     fn write_string(&mut self, string: RawLuaString) {
         self.write_variant(string.len() as u32);
-        for byte in string {
-            self.write(byte);
-        }
+        self.write_bytes(&string);
     }
 }
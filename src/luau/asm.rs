@@ -0,0 +1,1015 @@
+//! A line based assembler for luau chunks.
+//!
+//! ```text
+//! .version 6
+//! .userdata Vec3
+//! .const greeting "hello"
+//!
+//! .function greet
+//!     .params 1
+//!     .argtypes string?
+//!         GETIMPORT R1 print
+//!         MOVE R2 R0
+//!         CALL R1 1 0
+//!         RETURN R0 0
+//! .end
+//!
+//!         .line 1
+//!         NEWCLOSURE R0 greet
+//!         LOADK R1 $greeting
+//!         JUMPXEQKNIL R1 done NOT
+//!         CALL R0 1 0
+//! done:   RETURN R0 0
+//! ```
+//!
+//! Operands are written like `luau-compile --text` prints them, bracketed previews are ignored except
+//! the import path of GETIMPORT and `N:` in front of an instruction sets its source line, so the body
+//! of a listing can be pasted back in once its other constants are declared with `.const`.
+//! Constants are written as `Kn`, `$name` of an earlier `.const`, a string literal or `#literal`,
+//! imports as dotted paths, jumps take a label or a raw offset and closures a child function name or `Pn`.
+//! The string table is built from scratch, the main function is the top level and comes last.
+
+use std::collections::HashMap;
+
+use crate::{
+    Constant, Error, LocalVariable, Proto,
+    asm::{NamedLabels, Token, error, literal, operand_error, parse_int, parse_number, tokenize},
+    luau::{
+        LuaBytecode, LuauImport, LuauType, LuauTypeInfo, LuauTypeTag, LuauTypedLocal,
+        StringTableMode,
+        builder::{Label, ProtoBuilder},
+        disasm::BUILTINS,
+    },
+    opcode::{LuauOpMode, LuauOpcode},
+};
+
+/// Assembles `source` into a chunk with a freshly built string table.
+pub fn assemble(source: &str) -> Result<LuaBytecode, Error> {
+    let mut assembler = Assembler {
+        version: 6,
+        types_version: 3,
+        userdata: Vec::new(),
+        protos: Vec::new(),
+        functions: vec![Function::new(0, None)],
+    };
+
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let tokens = tokenize(line, text)?;
+        assembler.line(line, &tokens)?;
+    }
+
+    if let [_, .., function] = assembler.functions.as_slice() {
+        return Err(error(function.line, "missing .end for this .function"));
+    }
+
+    if let Some((line, _)) = assembler.userdata.first()
+        && assembler.types_version != 3
+    {
+        return Err(error(*line, "userdata types need types version 3"));
+    }
+
+    let main = assembler.functions.pop().unwrap();
+    let main_proto_id = assembler.protos.len() as u32;
+    assembler.protos.push(main.finish(main_proto_id)?);
+
+    // userdata names come first in a rebuilt table, so their references stay 1..=n
+    let mut bytecode = LuaBytecode {
+        version: assembler.version,
+        types_version: assembler.types_version,
+        userdata_type_map: (0..).zip(1..=assembler.userdata.len() as u32).collect(),
+        protos: assembler.protos,
+        strings: assembler
+            .userdata
+            .iter()
+            .map(|(_, name)| name.as_bytes().to_vec())
+            .collect(),
+        main_proto_id,
+    };
+    bytecode.strings = bytecode.rebuild_strings(false)?.strings;
+
+    Ok(bytecode)
+}
+
+/// Assembles `source` and encodes it with a rebuilt string table.
+pub fn assemble_bytes(source: &str) -> Result<Vec<u8>, Error> {
+    assemble(source)?.write_with_options(StringTableMode::Rebuild)
+}
+
+struct Assembler {
+    version: u8,
+    types_version: u8,
+    // names with the line declaring them
+    userdata: Vec<(usize, String)>,
+
+    // protos are numbered when they end, children before their parent
+    protos: Vec<Proto>,
+    // the innermost function being assembled is last
+    functions: Vec<Function>,
+}
+
+impl Assembler {
+    fn line(&mut self, line: usize, tokens: &[Token]) -> Result<(), Error> {
+        let mut tokens = tokens;
+
+        // source line in front of a `luau-compile --text` instruction
+        if let Some(Token::Word(word)) = tokens.first()
+            && let Some(source_line) = word.strip_suffix(':').and_then(parse_int)
+        {
+            self.function().set_line(line, source_line)?;
+            tokens = &tokens[1..];
+        }
+
+        if let Some(Token::Word(word)) = tokens.first()
+            && let Some(label) = word.strip_suffix(':')
+        {
+            self.function().define_label(line, label)?;
+            tokens = &tokens[1..];
+        }
+
+        match tokens.first() {
+            None => Ok(()),
+            Some(Token::Word(word)) if word.starts_with('.') => {
+                self.directive(line, &word[1..], &tokens[1..])
+            }
+            Some(Token::Word(word)) => self.function().instruction(line, word, &tokens[1..]),
+            Some(Token::String(_)) => Err(error(line, "expected an instruction or directive")),
+        }
+    }
+
+    fn directive(&mut self, line: usize, name: &str, arguments: &[Token]) -> Result<(), Error> {
+        let byte = || match arguments.first().and_then(Token::word).and_then(parse_int) {
+            Some(value @ 0..=255) => Ok(value as u8),
+            _ => Err(error(line, format!(".{name} expects a byte"))),
+        };
+
+        match name {
+            "version" => self.version = byte()?,
+            "typesversion" => self.types_version = byte()?,
+
+            "userdata" => match arguments {
+                [Token::Word(userdata)] if self.userdata.len() < 32 => {
+                    self.userdata.push((line, userdata.clone()))
+                }
+                [Token::Word(_)] => return Err(error(line, "too many userdata types")),
+                _ => return Err(error(line, ".userdata expects a name")),
+            },
+
+            "function" => {
+                let name = match arguments {
+                    [] => None,
+                    [Token::Word(name)] => Some(name.clone()),
+                    _ => return Err(error(line, "expected a function name")),
+                };
+                self.functions.push(Function::new(line, name));
+            }
+
+            "end" => {
+                if self.functions.len() < 2 {
+                    return Err(error(line, ".end without .function"));
+                }
+
+                let function = self.functions.pop().unwrap();
+                let name = function.name.clone();
+
+                let id = self.protos.len() as u32;
+                self.protos.push(function.finish(id)?);
+
+                let parent = self.function();
+                parent.proto.protos.push(id);
+                parent.children.push(name);
+            }
+
+            "argtypes" => {
+                let arguments = self.types(line, arguments)?;
+                self.function().type_info().arguments = Some(arguments);
+            }
+
+            "upvaltypes" => {
+                let upvalues = self.types(line, arguments)?;
+                self.function().type_info().upvalues = upvalues;
+            }
+
+            "localtype" => match arguments {
+                [operand, Token::Word(start), Token::Word(end), kind] => {
+                    let kind = self.types(line, std::slice::from_ref(kind))?[0];
+                    let register = register(line, operand)? as u8;
+                    self.function().typed_locals.push((
+                        line,
+                        kind,
+                        register,
+                        start.clone(),
+                        end.clone(),
+                    ));
+                }
+                _ => {
+                    return Err(error(
+                        line,
+                        ".localtype expects a register, a start, an end and a type",
+                    ));
+                }
+            },
+
+            _ => return self.function().directive(line, name, arguments),
+        }
+
+        Ok(())
+    }
+
+    fn types(&self, line: usize, arguments: &[Token]) -> Result<Vec<LuauTypeTag>, Error> {
+        arguments
+            .iter()
+            .map(|argument| {
+                let word = argument.word().unwrap_or_default();
+                let (name, optional) = match word.strip_suffix('?') {
+                    Some(name) => (name, true),
+                    None => (word, false),
+                };
+
+                let kind = match name {
+                    "nil" => LuauType::Nil,
+                    "boolean" => LuauType::Boolean,
+                    "number" => LuauType::Number,
+                    "string" => LuauType::String,
+                    "table" => LuauType::Table,
+                    "function" => LuauType::Function,
+                    "thread" => LuauType::Thread,
+                    "userdata" => LuauType::Userdata,
+                    "vector" => LuauType::Vector,
+                    "buffer" => LuauType::Buffer,
+                    "any" => LuauType::Any,
+                    _ => match self
+                        .userdata
+                        .iter()
+                        .position(|(_, userdata)| userdata == name)
+                    {
+                        Some(index) => LuauType::TaggedUserdata(index as u8),
+                        None => return Err(error(line, format!("unknown type `{word}`"))),
+                    },
+                };
+
+                Ok(LuauTypeTag { kind, optional })
+            })
+            .collect()
+    }
+
+    fn function(&mut self) -> &mut Function {
+        self.functions.last_mut().unwrap()
+    }
+}
+
+struct Function {
+    // the line of the `.function` directive
+    line: usize,
+    name: Option<String>,
+    // everything but the code, which the builder owns
+    proto: Proto,
+    builder: ProtoBuilder,
+    // the source line of every emitted word
+    lines: Vec<usize>,
+    stack_size: Option<u8>,
+
+    labels: NamedLabels,
+    constants: HashMap<String, u32>,
+    children: Vec<Option<String>>,
+    closures: Vec<(usize, u32, String)>,
+    closure_constants: Vec<(usize, u32, String)>,
+    locals: Vec<(usize, Vec<u8>, u8, String, String)>,
+    typed_locals: Vec<(usize, LuauTypeTag, u8, String, String)>,
+}
+
+impl Function {
+    fn new(line: usize, name: Option<String>) -> Self {
+        Function {
+            line,
+            proto: Proto {
+                name: name.as_ref().map(|name| name.as_bytes().to_vec()),
+                ..Default::default()
+            },
+            name,
+            builder: ProtoBuilder::new(),
+            lines: Vec::new(),
+            stack_size: None,
+            labels: NamedLabels::default(),
+            constants: HashMap::new(),
+            children: Vec::new(),
+            closures: Vec::new(),
+            closure_constants: Vec::new(),
+            locals: Vec::new(),
+            typed_locals: Vec::new(),
+        }
+    }
+
+    fn define_label(&mut self, line: usize, name: &str) -> Result<(), Error> {
+        let builder = &mut self.builder;
+        let label = self
+            .labels
+            .define(line, name, builder.pc(), || builder.label())?;
+        builder.bind(label)
+    }
+
+    fn set_line(&mut self, line: usize, source_line: i64) -> Result<(), Error> {
+        let source_line = i32::try_from(source_line).map_err(|_| error(line, "invalid line"))?;
+        self.builder.set_line(source_line);
+        Ok(())
+    }
+
+    fn type_info(&mut self) -> &mut LuauTypeInfo {
+        self.proto.type_info.get_or_insert_default()
+    }
+
+    fn directive(&mut self, line: usize, name: &str, arguments: &[Token]) -> Result<(), Error> {
+        let int = |token: Option<&Token>| {
+            token
+                .and_then(Token::word)
+                .and_then(parse_int)
+                .ok_or_else(|| error(line, format!(".{name} expects an integer")))
+        };
+        let byte = |token: Option<&Token>| {
+            u8::try_from(int(token)?).map_err(|_| error(line, format!(".{name} is out of range")))
+        };
+
+        match name {
+            "name" => match arguments {
+                [Token::String(debug_name)] => self.proto.name = Some(debug_name.clone()),
+                _ => return Err(error(line, ".name expects a string")),
+            },
+
+            "linedefined" => {
+                self.proto.line_defined = u32::try_from(int(arguments.first())?)
+                    .map_err(|_| error(line, ".linedefined is out of range"))?;
+            }
+
+            "params" => self.proto.parameter_count = byte(arguments.first())?,
+            "stack" => self.stack_size = Some(byte(arguments.first())?),
+            "upvalues" => self.proto.upvalue_count = byte(arguments.first())?,
+            "flags" => self.proto.flags = byte(arguments.first())?,
+            "vararg" => self.proto.is_vararg = true,
+
+            "upvalue" => match arguments {
+                [Token::Word(upvalue)] => {
+                    self.proto.upvalues.push(Some(upvalue.as_bytes().to_vec()));
+                    self.proto.upvalue_count = self
+                        .proto
+                        .upvalue_count
+                        .max(self.proto.upvalues.len() as u8);
+                }
+                _ => return Err(error(line, ".upvalue expects a name")),
+            },
+
+            "local" => match arguments {
+                [
+                    Token::Word(local),
+                    operand,
+                    Token::Word(start),
+                    Token::Word(end),
+                ] => {
+                    let register = register(line, operand)? as u8;
+                    self.locals.push((
+                        line,
+                        local.as_bytes().to_vec(),
+                        register,
+                        start.clone(),
+                        end.clone(),
+                    ));
+                }
+                _ => {
+                    return Err(error(
+                        line,
+                        ".local expects a name, a register, a start and an end",
+                    ));
+                }
+            },
+
+            "const" => match arguments {
+                [Token::Word(constant), value @ ..] => {
+                    let index = self.constant_value(line, value)?;
+                    self.constants.insert(constant.clone(), index);
+                }
+                _ => return Err(error(line, ".const expects a name and a value")),
+            },
+
+            "line" => {
+                let source_line = int(arguments.first())?;
+                self.set_line(line, source_line)?;
+            }
+
+            "word" => {
+                let word = int(arguments.first())?;
+                let word = u32::try_from(word).map_err(|_| error(line, ".word is out of range"))?;
+                self.builder.emit_aux(word);
+                self.lines.push(line);
+            }
+
+            _ => return Err(error(line, format!("unknown directive `.{name}`"))),
+        }
+
+        Ok(())
+    }
+
+    fn constant_value(&mut self, line: usize, value: &[Token]) -> Result<u32, Error> {
+        let invalid = || error(line, "invalid constant value");
+
+        let constant = match value {
+            [Token::String(string)] => Constant::String(string.clone()),
+            [Token::Word(word)] => {
+                literal(word.strip_prefix('#').unwrap_or(word)).ok_or_else(invalid)?
+            }
+
+            [Token::Word(kind), Token::Word(path)] if kind == "import" => {
+                self.import(line, path)?
+            }
+
+            [Token::Word(kind), components @ ..] if kind == "vector" => {
+                let mut vector = [0f32; 4];
+                if !(3..=4).contains(&components.len()) {
+                    return Err(error(line, "a vector has 3 or 4 components"));
+                }
+                for (slot, component) in vector.iter_mut().zip(components) {
+                    let value = component.word().and_then(parse_number);
+                    *slot = value.ok_or_else(invalid)? as f32;
+                }
+
+                let [x, y, z, w] = vector;
+                Constant::Vector(x, y, z, w)
+            }
+
+            [Token::Word(kind), keys @ ..] if kind == "table" => {
+                let keys = keys
+                    .iter()
+                    .map(|key| self.constant(line, key))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Constant::Table(keys.len() as u32, keys)
+            }
+
+            [Token::Word(kind), Token::Word(name)] if kind == "closure" => {
+                // the proto id is only known once the child has ended
+                let index = self.builder.add_constant(Constant::Closure(0));
+                self.closure_constants.push((line, index, name.clone()));
+                return Ok(index);
+            }
+
+            _ => return Err(invalid()),
+        };
+
+        Ok(self.builder.constant(constant))
+    }
+
+    fn import(&mut self, line: usize, path: &str) -> Result<Constant, Error> {
+        let ids = path
+            .split('.')
+            .map(|part| {
+                self.builder
+                    .constant(Constant::String(part.as_bytes().to_vec()))
+            })
+            .collect::<Vec<_>>();
+
+        let import = LuauImport::new(&ids)
+            .ok_or_else(|| error(line, format!("`{path}` can not be imported")))?;
+        Ok(Constant::Import(import.into()))
+    }
+
+    fn instruction(
+        &mut self,
+        line: usize,
+        mnemonic: &str,
+        operands: &[Token],
+    ) -> Result<(), Error> {
+        let opcode = (0..=u8::MAX)
+            .filter_map(|op| LuauOpcode::try_from(op).ok())
+            .find(|opcode| opcode.info().name.eq_ignore_ascii_case(mnemonic))
+            .ok_or_else(|| error(line, format!("unknown opcode `{mnemonic}`")))?;
+
+        // listings bracket previews, only SETLIST, FORGLOOP and GETIMPORT carry information in them
+        let keep = matches!(
+            opcode,
+            LuauOpcode::SetList | LuauOpcode::ForGLoop | LuauOpcode::GetImport
+        );
+        let mut tokens = Vec::new();
+        let mut depth = 0;
+        for token in operands {
+            let word = token.word().unwrap_or_default();
+            let opens = word.starts_with('[');
+            let closes = word.ends_with(']');
+
+            if opens && closes && keep {
+                tokens.push(Token::Word(word[1..word.len() - 1].to_string()));
+            } else if opens || depth > 0 {
+                depth += opens as usize;
+                depth -= (closes && depth > 0) as usize;
+            } else {
+                tokens.push(token.clone());
+            }
+        }
+
+        let pc = self.builder.pc();
+        let mut operands = Cursor {
+            line,
+            name: opcode.info().name,
+            tokens: &tokens,
+            position: 0,
+        };
+
+        // D, E or the C of a skip, patched by the builder when it is a label
+        let mut label = None;
+        let (mut a, mut b, mut c, mut d, mut aux) = (0u32, 0u32, 0u32, 0i32, 0u32);
+
+        match opcode {
+            LuauOpcode::Nop | LuauOpcode::Break | LuauOpcode::NativeCall | LuauOpcode::Coverage => {
+            }
+
+            LuauOpcode::LoadNil | LuauOpcode::CloseUpvals => {
+                a = register(line, operands.next()?)?;
+            }
+
+            LuauOpcode::LoadB => {
+                a = register(line, operands.next()?)?;
+                b = operands.int(0..=255)? as u32;
+                if let Some(token) = operands.optional() {
+                    let word = token.word().unwrap_or_default();
+                    let token = Token::Word(word.strip_prefix('+').unwrap_or(word).to_string());
+                    (d, label) = self.target(line, opcode, &token)?;
+                    c = d as u32;
+                }
+            }
+
+            LuauOpcode::LoadN => {
+                a = register(line, operands.next()?)?;
+                d = operands.int(-32768..=32767)? as i32;
+            }
+
+            LuauOpcode::LoadK | LuauOpcode::DupClosure | LuauOpcode::DupTable => {
+                a = register(line, operands.next()?)?;
+                let token = operands.next()?;
+                d = match token.word().and_then(parse_int) {
+                    Some(index) if opcode == LuauOpcode::DupTable => index as i32,
+                    _ => self.constant(line, token)? as i32,
+                };
+                if !(0..=32767).contains(&d) {
+                    return Err(error(line, "constant index does not fit D"));
+                }
+            }
+
+            LuauOpcode::LoadKx | LuauOpcode::GetGlobal | LuauOpcode::SetGlobal => {
+                a = register(line, operands.next()?)?;
+                aux = self.constant(line, operands.next()?)?;
+            }
+
+            LuauOpcode::Move | LuauOpcode::Not | LuauOpcode::Minus | LuauOpcode::Length => {
+                a = register(line, operands.next()?)?;
+                b = register(line, operands.next()?)?;
+            }
+
+            LuauOpcode::GetUpval | LuauOpcode::SetUpval => {
+                a = register(line, operands.next()?)?;
+                b = upvalue(line, operands.next()?)?;
+            }
+
+            LuauOpcode::GetImport => {
+                a = register(line, operands.next()?)?;
+                let mut token = operands.next()?;
+                // a listing prints D and then the path, D indexes the table of the listed chunk
+                if let Some(path) = operands.optional()
+                    && !path.word().unwrap_or_default().is_empty()
+                {
+                    token = path;
+                }
+
+                let word = token.word().unwrap_or_default();
+                let index = if word.starts_with(['$', '#'])
+                    || word.strip_prefix('K').and_then(parse_int).is_some()
+                {
+                    self.constant(line, token)?
+                } else if let Some(index) = parse_int(word) {
+                    // a bare D operand
+                    index as u32
+                } else {
+                    let import = self.import(line, word)?;
+                    self.builder.constant(import)
+                };
+
+                aux = match self.builder.constants().get(index as usize) {
+                    Some(Constant::Import(import)) => *import as u32,
+                    _ => return Err(error(line, "GETIMPORT needs an import constant")),
+                };
+                d = index as i32;
+                if d > 32767 {
+                    return Err(error(line, "constant index does not fit D"));
+                }
+            }
+
+            LuauOpcode::GetTableKs | LuauOpcode::SetTableKs | LuauOpcode::NameCall => {
+                a = register(line, operands.next()?)?;
+                b = register(line, operands.next()?)?;
+                aux = self.constant(line, operands.next()?)?;
+            }
+
+            LuauOpcode::GetTableN | LuauOpcode::SetTableN => {
+                a = register(line, operands.next()?)?;
+                b = register(line, operands.next()?)?;
+                c = operands.int(1..=256)? as u32 - 1;
+            }
+
+            LuauOpcode::NewClosure => {
+                a = register(line, operands.next()?)?;
+                let word = operands.next()?.word().unwrap_or_default();
+                d = match word.strip_prefix('P').and_then(parse_int) {
+                    Some(index @ 0..=32767) => index as i32,
+                    _ => {
+                        self.closures.push((line, pc, word.to_string()));
+                        0
+                    }
+                };
+            }
+
+            LuauOpcode::Call => {
+                a = register(line, operands.next()?)?;
+                b = (operands.int(-1..=254)? + 1) as u32;
+                c = (operands.int(-1..=254)? + 1) as u32;
+            }
+
+            LuauOpcode::Return | LuauOpcode::GetVarargs => {
+                a = register(line, operands.next()?)?;
+                b = (operands.int(-1..=254)? + 1) as u32;
+            }
+
+            LuauOpcode::Jump | LuauOpcode::JumpBack | LuauOpcode::JumpX => {
+                (d, label) = self.target(line, opcode, operands.next()?)?;
+            }
+
+            LuauOpcode::JumpIf
+            | LuauOpcode::JumpIfNot
+            | LuauOpcode::ForNPrep
+            | LuauOpcode::ForNLoop
+            | LuauOpcode::ForGPrep
+            | LuauOpcode::ForGPrepInext
+            | LuauOpcode::ForGPrepNext => {
+                a = register(line, operands.next()?)?;
+                (d, label) = self.target(line, opcode, operands.next()?)?;
+            }
+
+            LuauOpcode::JumpIfEq
+            | LuauOpcode::JumpIfLe
+            | LuauOpcode::JumpIfLt
+            | LuauOpcode::JumpIfNotEq
+            | LuauOpcode::JumpIfNotLe
+            | LuauOpcode::JumpIfNotLt => {
+                a = register(line, operands.next()?)?;
+                aux = register(line, operands.next()?)?;
+                (d, label) = self.target(line, opcode, operands.next()?)?;
+            }
+
+            LuauOpcode::AddK
+            | LuauOpcode::SubK
+            | LuauOpcode::MulK
+            | LuauOpcode::DivK
+            | LuauOpcode::ModK
+            | LuauOpcode::PowK
+            | LuauOpcode::AndK
+            | LuauOpcode::OrK
+            | LuauOpcode::IDivK => {
+                a = register(line, operands.next()?)?;
+                b = register(line, operands.next()?)?;
+                c = self.small_constant(line, operands.next()?)?;
+            }
+
+            LuauOpcode::SubRk | LuauOpcode::DivRk => {
+                a = register(line, operands.next()?)?;
+                b = self.small_constant(line, operands.next()?)?;
+                c = register(line, operands.next()?)?;
+            }
+
+            LuauOpcode::NewTable => {
+                a = register(line, operands.next()?)?;
+                let hash_size = operands.int(0..=1 << 30)? as u32;
+                b = match hash_size {
+                    0 => 0,
+                    size => size.next_power_of_two().trailing_zeros() + 1,
+                };
+                aux = operands.int(0..=u32::MAX as i64)? as u32;
+            }
+
+            LuauOpcode::SetList => {
+                a = register(line, operands.next()?)?;
+                b = register(line, operands.next()?)?;
+                c = (operands.int(-1..=254)? + 1) as u32;
+                aux = match operands.optional() {
+                    Some(token) => parse_int(token.word().unwrap_or_default())
+                        .and_then(|index| u32::try_from(index).ok())
+                        .ok_or_else(|| error(line, "invalid table index"))?,
+                    None => 1,
+                };
+            }
+
+            LuauOpcode::ForGLoop => {
+                a = register(line, operands.next()?)?;
+                (d, label) = self.target(line, opcode, operands.next()?)?;
+                aux = operands.int(1..=255)? as u32;
+                if let Some(token) = operands.optional() {
+                    match token.word() {
+                        Some("inext") => aux |= 1 << 31,
+                        _ => return Err(error(line, "expected `inext`")),
+                    }
+                }
+            }
+
+            LuauOpcode::FastCall
+            | LuauOpcode::FastCall1
+            | LuauOpcode::FastCall2
+            | LuauOpcode::FastCall2K
+            | LuauOpcode::FastCall3 => {
+                a = builtin(line, operands.next()?)?;
+                match opcode {
+                    LuauOpcode::FastCall => (),
+                    LuauOpcode::FastCall2K => {
+                        b = register(line, operands.next()?)?;
+                        aux = self.constant(line, operands.next()?)?;
+                    }
+                    _ => {
+                        b = register(line, operands.next()?)?;
+                        if opcode != LuauOpcode::FastCall1 {
+                            aux = register(line, operands.next()?)?;
+                        }
+                        if opcode == LuauOpcode::FastCall3 {
+                            aux |= register(line, operands.next()?)? << 8;
+                        }
+                    }
+                }
+                (d, label) = self.target(line, opcode, operands.next()?)?;
+                c = d as u32;
+            }
+
+            LuauOpcode::PrepVarargs => a = operands.int(0..=255)? as u32,
+
+            LuauOpcode::Capture => {
+                let kind = operands.next()?.word().unwrap_or_default();
+                a = match kind {
+                    "VAL" => 0,
+                    "REF" => 1,
+                    "UPVAL" => 2,
+                    _ => return Err(error(line, "capture kind must be VAL, REF or UPVAL")),
+                };
+                b = match a {
+                    2 => upvalue(line, operands.next()?)?,
+                    _ => register(line, operands.next()?)?,
+                };
+            }
+
+            LuauOpcode::JumpXeqkNil
+            | LuauOpcode::JumpXeqkB
+            | LuauOpcode::JumpXeqkN
+            | LuauOpcode::JumpXeqkS => {
+                a = register(line, operands.next()?)?;
+                aux = match opcode {
+                    LuauOpcode::JumpXeqkNil => 0,
+                    LuauOpcode::JumpXeqkB => operands.int(0..=1)? as u32,
+                    _ => {
+                        let index = self.constant(line, operands.next()?)?;
+                        if index > 0xffffff {
+                            return Err(error(line, "constant index does not fit AUX"));
+                        }
+                        index
+                    }
+                };
+                (d, label) = self.target(line, opcode, operands.next()?)?;
+                if let Some(token) = operands.optional() {
+                    match token.word() {
+                        Some("NOT") => aux |= 1 << 31,
+                        _ => return Err(error(line, "expected `NOT`")),
+                    }
+                }
+            }
+
+            LuauOpcode::GetTable
+            | LuauOpcode::SetTable
+            | LuauOpcode::Add
+            | LuauOpcode::Sub
+            | LuauOpcode::Mul
+            | LuauOpcode::Div
+            | LuauOpcode::Mod
+            | LuauOpcode::Pow
+            | LuauOpcode::And
+            | LuauOpcode::Or
+            | LuauOpcode::Concat
+            | LuauOpcode::IDiv => {
+                a = register(line, operands.next()?)?;
+                b = register(line, operands.next()?)?;
+                c = register(line, operands.next()?)?;
+            }
+        }
+
+        operands.finish()?;
+
+        let builder = &mut self.builder;
+        match (opcode.info().mode, label) {
+            (LuauOpMode::ABC, Some(label)) => builder.emit_skip(opcode, a, b, label),
+            (LuauOpMode::ABC, None) => builder.emit_abc(opcode, a, b, c),
+            (_, Some(label)) => builder.emit_jump(opcode, a, label),
+            (LuauOpMode::AD, None) => builder.emit_ad(opcode, a, d),
+            (LuauOpMode::E, None) => builder.emit_e(opcode, d),
+        }
+        .map_err(operand_error(line))?;
+        self.lines.push(line);
+
+        if opcode.info().has_aux() {
+            self.builder.emit_aux(aux);
+            self.lines.push(line);
+        }
+
+        Ok(())
+    }
+
+    // a raw offset or a label the builder patches
+    fn target(
+        &mut self,
+        line: usize,
+        opcode: LuauOpcode,
+        operand: &Token,
+    ) -> Result<(i32, Option<Label>), Error> {
+        let word = operand
+            .word()
+            .ok_or_else(|| error(line, "expected a label"))?;
+
+        // skips live in C, jumps in D or E
+        let range = match opcode.info().mode {
+            LuauOpMode::ABC => 0..=255,
+            LuauOpMode::AD => -(1 << 15)..=(1 << 15) - 1,
+            LuauOpMode::E => -(1 << 23)..=(1 << 23) - 1,
+        };
+
+        match parse_int(word) {
+            Some(offset) if range.contains(&offset) => Ok((offset as i32, None)),
+            Some(_) => Err(error(line, "jump offset is out of range")),
+            None => {
+                let builder = &mut self.builder;
+                let label = self.labels.reference(line, word, || builder.label());
+                Ok((0, Some(label)))
+            }
+        }
+    }
+
+    fn small_constant(&mut self, line: usize, operand: &Token) -> Result<u32, Error> {
+        match self.constant(line, operand)? {
+            index @ 0..=255 => Ok(index),
+            _ => Err(error(line, "constant index does not fit a byte")),
+        }
+    }
+
+    fn constant(&mut self, line: usize, operand: &Token) -> Result<u32, Error> {
+        let word = match operand {
+            Token::String(string) => {
+                return Ok(self.builder.constant(Constant::String(string.clone())));
+            }
+            Token::Word(word) => word.as_str(),
+        };
+
+        let index = if let Some(name) = word.strip_prefix('$') {
+            self.constants.get(name).copied()
+        } else if let Some(literal) = word.strip_prefix('#').and_then(literal) {
+            Some(self.builder.constant(literal))
+        } else {
+            word.strip_prefix(['K', 'k'])
+                .and_then(parse_int)
+                .and_then(|index| u32::try_from(index).ok())
+        };
+
+        index.ok_or_else(|| error(line, format!("invalid constant `{word}`")))
+    }
+
+    fn finish(self, id: u32) -> Result<Proto, Error> {
+        self.labels.check()?;
+
+        let lines = self.lines;
+        let code = self.builder.finish().map_err(|err| match err {
+            Error::JumpOutOfRange { pc } => {
+                error(lines[pc as usize], "jump target is out of reach")
+            }
+            err => err,
+        })?;
+
+        let mut proto = Proto {
+            bytecode_id: id,
+            instructions: code.instructions,
+            constants: code.constants,
+            line_info: code.line_info,
+            absolute_line_info: code.absolute_line_info,
+            linegaplog2: code.linegaplog2,
+            max_stack_size: self.stack_size.unwrap_or(code.max_stack_size),
+            ..self.proto
+        };
+
+        let child = |line: usize, name: &str| {
+            self.children
+                .iter()
+                .position(|child| child.as_deref() == Some(name))
+                .ok_or_else(|| error(line, format!("unknown function `{name}`")))
+        };
+
+        for (line, pc, name) in self.closures.iter() {
+            let index = child(*line, name)?;
+            let mut instruction = proto.instructions[*pc as usize].luau();
+            instruction.set_d(index as i32);
+            proto.instructions[*pc as usize] = instruction.into();
+        }
+
+        for (line, index, name) in self.closure_constants.iter() {
+            let child = proto.protos[child(*line, name)?];
+            proto.constants[*index as usize] = Constant::Closure(child);
+        }
+
+        for (line, name, register, start, end) in self.locals {
+            proto.locals.push(LocalVariable {
+                name: Some(name),
+                start_pc: self.labels.position(line, &start)?,
+                end_pc: self.labels.position(line, &end)?,
+                register,
+            });
+        }
+
+        for (line, kind, register, start, end) in self.typed_locals {
+            let local = LuauTypedLocal {
+                kind,
+                register,
+                start_pc: self.labels.position(line, &start)?,
+                end_pc: self.labels.position(line, &end)?,
+            };
+            proto.type_info.get_or_insert_default().locals.push(local);
+        }
+
+        Ok(proto)
+    }
+}
+
+struct Cursor<'a> {
+    line: usize,
+    name: &'static str,
+    tokens: &'a [Token],
+    position: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn next(&mut self) -> Result<&'a Token, Error> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .ok_or_else(|| error(self.line, format!("{} is missing an operand", self.name)))?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn optional(&mut self) -> Option<&'a Token> {
+        let token = self.tokens.get(self.position)?;
+        self.position += 1;
+        Some(token)
+    }
+
+    fn int(&mut self, range: std::ops::RangeInclusive<i64>) -> Result<i64, Error> {
+        let token = self.next()?;
+        let word = token.word().unwrap_or_default();
+        match parse_int(word) {
+            Some(value) if range.contains(&value) => Ok(value),
+            _ => Err(error(self.line, format!("invalid operand `{word}`"))),
+        }
+    }
+
+    fn finish(&self) -> Result<(), Error> {
+        match self.position < self.tokens.len() {
+            true => Err(error(
+                self.line,
+                format!("{} has too many operands", self.name),
+            )),
+            false => Ok(()),
+        }
+    }
+}
+
+fn register(line: usize, operand: &Token) -> Result<u32, Error> {
+    let word = operand.word().unwrap_or_default();
+    let register = word.strip_prefix(['R', 'r']).unwrap_or(word);
+
+    match parse_int(register) {
+        Some(register @ 0..=255) => Ok(register as u32),
+        _ => Err(error(line, format!("invalid register `{word}`"))),
+    }
+}
+
+fn upvalue(line: usize, operand: &Token) -> Result<u32, Error> {
+    let word = operand.word().unwrap_or_default();
+    match parse_int(word.strip_prefix('U').unwrap_or(word)) {
+        Some(index @ 0..=255) => Ok(index as u32),
+        _ => Err(error(line, format!("invalid upvalue `{word}`"))),
+    }
+}
+
+fn builtin(line: usize, operand: &Token) -> Result<u32, Error> {
+    let word = operand.word().unwrap_or_default();
+    let id = match BUILTINS.iter().position(|name| *name == word) {
+        Some(id) => Some(id as i64),
+        None => parse_int(word.strip_prefix("builtin").unwrap_or(word)),
+    };
+
+    match id {
+        Some(id @ 1..=255) => Ok(id as u32),
+        _ => Err(error(line, format!("unknown builtin `{word}`"))),
+    }
+}
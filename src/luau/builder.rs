@@ -0,0 +1,255 @@
+use crate::{
+    Constant, Error, Proto,
+    builder::{Labels, intern},
+    luau::set_instruction_lines,
+    opcode::{Instruction, LuauInstruction, LuauOpMode, LuauOpcode, LuauOperand},
+};
+
+pub use crate::builder::Label;
+
+// register operands are a byte wide
+const MAX_STACK_SIZE: u32 = 255;
+
+#[derive(Copy, Clone)]
+enum Jump {
+    D,
+    E,
+    // LOADB skips through C
+    C,
+    // fast calls land after the CALL they guard
+    FastCall,
+}
+
+/// Emits the instructions of one luau proto, jumps are patched and the stack is sized on `finish`.
+#[derive(Default)]
+pub struct ProtoBuilder {
+    proto: Proto,
+    labels: Labels,
+    jumps: Vec<(u32, Label, Jump)>,
+    // the instruction waiting for its AUX word
+    pending_aux: Option<LuauOpcode>,
+
+    line: Option<i32>,
+    lines: Vec<i32>,
+    // one past the highest register in use
+    registers: u32,
+}
+
+impl ProtoBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The pc of the next word, AUX words take a pc of their own.
+    pub fn pc(&self) -> u32 {
+        self.proto.instructions.len() as u32
+    }
+
+    /// Source line of the instructions emitted from now on, the line info stays empty without one.
+    pub fn set_line(&mut self, line: i32) {
+        self.line = Some(line);
+    }
+
+    pub fn constant(&mut self, value: Constant) -> u32 {
+        intern(&mut self.proto.constants, value)
+    }
+
+    pub fn constants(&self) -> &[Constant] {
+        &self.proto.constants
+    }
+
+    /// Appends a constant even if an equal one exists, for constants patched later.
+    pub fn add_constant(&mut self, value: Constant) -> u32 {
+        self.proto.constants.push(value);
+        self.proto.constants.len() as u32 - 1
+    }
+
+    pub fn label(&mut self) -> Label {
+        self.labels.create()
+    }
+
+    /// Points `label` at the next word, binding it again moves it.
+    ///
+    /// Fails on a label created by another builder.
+    pub fn bind(&mut self, label: Label) -> Result<(), Error> {
+        self.labels.bind(label, self.pc())
+    }
+
+    /// Makes the stack at least `registers` slots large, for ranges the builder can not see.
+    pub fn reserve(&mut self, registers: u32) {
+        self.registers = self.registers.max(registers);
+    }
+
+    /// Fails when an operand does not fit its field.
+    pub fn emit_abc(&mut self, opcode: LuauOpcode, a: u32, b: u32, c: u32) -> Result<u32, Error> {
+        self.check("A", a <= 0xff)?;
+        self.check("B", b <= 0xff)?;
+        self.check("C", c <= 0xff)?;
+
+        // the operands are checked first, so the sums below stay far from overflowing
+        let info = opcode.info();
+        for (operand, value) in [(info.a, a), (info.b, b), (info.c, c)] {
+            // captured upvalues are not registers
+            let is_upvalue = opcode == LuauOpcode::Capture && a == 2;
+            if operand == LuauOperand::Register && !is_upvalue {
+                self.reserve(value + 1);
+            }
+        }
+
+        let range = match opcode {
+            // arguments follow the function, results replace it
+            LuauOpcode::Call => (a + b).max((a + c).saturating_sub(1)),
+            LuauOpcode::Return | LuauOpcode::GetVarargs => (a + b).saturating_sub(1),
+            LuauOpcode::SetList => (b + c).saturating_sub(1),
+            LuauOpcode::NameCall => a + 2,
+            _ => 0,
+        };
+        self.reserve(range);
+
+        let mut instruction = LuauInstruction(opcode as u32);
+        instruction.set_a(a).set_b(b).set_c(c);
+        Ok(self.emit(opcode, instruction.into()))
+    }
+
+    pub fn emit_ad(&mut self, opcode: LuauOpcode, a: u32, d: i32) -> Result<u32, Error> {
+        self.check("A", a <= 0xff)?;
+        self.check("D", i16::try_from(d).is_ok())?;
+
+        if opcode.info().a == LuauOperand::Register {
+            self.reserve(a + 1);
+        }
+
+        let range = match opcode {
+            LuauOpcode::ForNPrep
+            | LuauOpcode::ForNLoop
+            | LuauOpcode::ForGPrep
+            | LuauOpcode::ForGPrepInext
+            | LuauOpcode::ForGPrepNext => a + 3,
+            _ => 0,
+        };
+        self.reserve(range);
+
+        let mut instruction = LuauInstruction(opcode as u32);
+        instruction.set_a(a).set_d(d);
+        Ok(self.emit(opcode, instruction.into()))
+    }
+
+    pub fn emit_e(&mut self, opcode: LuauOpcode, e: i32) -> Result<u32, Error> {
+        self.check("E", (-(1 << 23)..1 << 23).contains(&e))?;
+
+        let mut instruction = LuauInstruction(opcode as u32);
+        instruction.set_e(e);
+        Ok(self.emit(opcode, instruction.into()))
+    }
+
+    /// Emits the AUX word of the previous instruction.
+    pub fn emit_aux(&mut self, aux: u32) -> u32 {
+        match self.pending_aux.take() {
+            Some(LuauOpcode::ForGLoop) => {
+                let a = self.proto.instructions.last().map(|last| last.luau().a());
+                self.reserve(a.unwrap_or_default() + 3 + (aux & 0xff));
+            }
+            Some(LuauOpcode::FastCall3) => self.reserve(((aux & 0xff).max((aux >> 8) & 0xff)) + 1),
+            Some(opcode) if opcode.info().aux == LuauOperand::Register => {
+                self.reserve((aux & 0xff) + 1)
+            }
+            _ => (),
+        }
+
+        self.push(Instruction(aux))
+    }
+
+    /// Emits a jump whose D or E offset is patched to `label` on `finish`.
+    pub fn emit_jump(&mut self, opcode: LuauOpcode, a: u32, label: Label) -> Result<u32, Error> {
+        let (pc, jump) = match opcode.info().mode {
+            LuauOpMode::E => (self.emit_e(opcode, 0)?, Jump::E),
+            _ => (self.emit_ad(opcode, a, 0)?, Jump::D),
+        };
+
+        self.jumps.push((pc, label, jump));
+        Ok(pc)
+    }
+
+    /// Emits `LOADB` or a fast call whose C skips to `label`, for fast calls that is the word after their `CALL`.
+    pub fn emit_skip(
+        &mut self,
+        opcode: LuauOpcode,
+        a: u32,
+        b: u32,
+        label: Label,
+    ) -> Result<u32, Error> {
+        let pc = self.emit_abc(opcode, a, b, 0)?;
+        let jump = match opcode.info().a {
+            LuauOperand::Builtin => Jump::FastCall,
+            _ => Jump::C,
+        };
+
+        self.jumps.push((pc, label, jump));
+        Ok(pc)
+    }
+
+    fn check(&self, operand: &'static str, fits: bool) -> Result<(), Error> {
+        match fits {
+            true => Ok(()),
+            false => Err(Error::OperandOutOfRange {
+                pc: self.pc(),
+                operand,
+            }),
+        }
+    }
+
+    fn emit(&mut self, opcode: LuauOpcode, instruction: Instruction) -> u32 {
+        if opcode.info().has_aux() {
+            self.pending_aux = Some(opcode);
+        }
+
+        self.push(instruction)
+    }
+
+    fn push(&mut self, instruction: Instruction) -> u32 {
+        let pc = self.pc();
+        self.proto.instructions.push(instruction);
+        self.lines.push(self.line.unwrap_or_default());
+        pc
+    }
+
+    /// Patches the jumps and fills the line info and `max_stack_size`.
+    ///
+    /// Fails when the stack grows past 255 slots.
+    pub fn finish(mut self) -> Result<Proto, Error> {
+        for (pc, label, jump) in self.jumps.iter() {
+            let mut instruction = self.proto.instructions[*pc as usize].luau();
+            let target = self.labels.position(*label)? as i64;
+
+            let (offset, range) = match jump {
+                Jump::D => (target - *pc as i64 - 1, -(1 << 15)..=(1 << 15) - 1),
+                Jump::E => (target - *pc as i64 - 1, -(1 << 23)..=(1 << 23) - 1),
+                Jump::C => (target - *pc as i64 - 1, 0..=255),
+                Jump::FastCall => (target - *pc as i64 - 2, 0..=255),
+            };
+            if !range.contains(&offset) {
+                return Err(Error::JumpOutOfRange { pc: *pc });
+            }
+
+            match jump {
+                Jump::D => instruction.set_d(offset as i32),
+                Jump::E => instruction.set_e(offset as i32),
+                Jump::C | Jump::FastCall => instruction.set_c(offset as u32),
+            };
+            self.proto.instructions[*pc as usize] = instruction.into();
+        }
+
+        if self.line.is_some() {
+            set_instruction_lines(&mut self.proto, &self.lines);
+        }
+
+        if self.registers > MAX_STACK_SIZE {
+            return Err(Error::StackOverflow {
+                size: self.registers,
+            });
+        }
+
+        self.proto.max_stack_size = self.registers as u8;
+        Ok(self.proto)
+    }
+}
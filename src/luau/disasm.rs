@@ -0,0 +1,405 @@
+use std::fmt::Write;
+
+use crate::{
+    Constant, Error, Proto,
+    luau::{
+        DecodedInstruction, LuaBytecode, LuauImport, Operands, decode_instructions,
+        instruction_lines,
+    },
+    number::format_g,
+    opcode::{LuauOpcode, LuauOperand},
+};
+
+// indexed by `LuauBuiltinFunction`
+pub(super) const BUILTINS: [&str; 89] = [
+    "none",
+    "assert",
+    "math.abs",
+    "math.acos",
+    "math.asin",
+    "math.atan2",
+    "math.atan",
+    "math.ceil",
+    "math.cosh",
+    "math.cos",
+    "math.deg",
+    "math.exp",
+    "math.floor",
+    "math.fmod",
+    "math.frexp",
+    "math.ldexp",
+    "math.log10",
+    "math.log",
+    "math.max",
+    "math.min",
+    "math.modf",
+    "math.pow",
+    "math.rad",
+    "math.sinh",
+    "math.sin",
+    "math.sqrt",
+    "math.tanh",
+    "math.tan",
+    "bit32.arshift",
+    "bit32.band",
+    "bit32.bnot",
+    "bit32.bor",
+    "bit32.bxor",
+    "bit32.btest",
+    "bit32.extract",
+    "bit32.lrotate",
+    "bit32.lshift",
+    "bit32.replace",
+    "bit32.rrotate",
+    "bit32.rshift",
+    "type",
+    "string.byte",
+    "string.char",
+    "string.len",
+    "typeof",
+    "string.sub",
+    "math.clamp",
+    "math.sign",
+    "math.round",
+    "rawset",
+    "rawget",
+    "rawequal",
+    "table.insert",
+    "table.unpack",
+    "vector",
+    "bit32.countlz",
+    "bit32.countrz",
+    "select",
+    "rawlen",
+    // bit32.extract with a constant field, named apart so listings reassemble
+    "bit32.extractk",
+    "getmetatable",
+    "setmetatable",
+    "tonumber",
+    "tostring",
+    "bit32.byteswap",
+    "buffer.readi8",
+    "buffer.readu8",
+    "buffer.writeu8",
+    "buffer.readi16",
+    "buffer.readu16",
+    "buffer.writeu16",
+    "buffer.readi32",
+    "buffer.readu32",
+    "buffer.writeu32",
+    "buffer.readf32",
+    "buffer.writef32",
+    "buffer.readf64",
+    "buffer.writef64",
+    "vector.magnitude",
+    "vector.normalize",
+    "vector.cross",
+    "vector.dot",
+    "vector.floor",
+    "vector.ceil",
+    "vector.abs",
+    "vector.sign",
+    "vector.clamp",
+    "vector.min",
+    "vector.max",
+];
+
+/// Renders every proto of the chunk like `luau-compile --text`, with source lines in front of each instruction.
+pub fn disassemble(bytecode: &LuaBytecode) -> Result<String, Error> {
+    let mut output = String::new();
+
+    for (id, proto) in bytecode.protos.iter().enumerate() {
+        let name = match proto.name.as_ref() {
+            Some(name) => String::from_utf8_lossy(name).into_owned(),
+            None => "??".to_string(),
+        };
+
+        let _ = writeln!(output, "Function {id} ({name}):");
+        disassemble_proto(&mut output, bytecode, id as u32, proto)?;
+        output.push('\n');
+    }
+
+    Ok(output)
+}
+
+fn disassemble_proto(
+    output: &mut String,
+    bytecode: &LuaBytecode,
+    id: u32,
+    proto: &Proto,
+) -> Result<(), Error> {
+    let instructions =
+        decode_instructions(id, &proto.instructions).collect::<Result<Vec<_>, _>>()?;
+
+    // labels are numbered in the order of their targets
+    let mut labels = vec![None; proto.instructions.len()];
+    for (pc, instruction) in instructions.iter() {
+        if let Some(label) = jump_target(*pc, instruction).and_then(|target| labels.get_mut(target))
+        {
+            *label = Some(0);
+        }
+    }
+
+    for (next_label, label) in labels.iter_mut().flatten().enumerate() {
+        *label = next_label;
+    }
+
+    let lines = instruction_lines(proto);
+    for (pc, instruction) in instructions.iter() {
+        if let Some(line) = lines.get(*pc as usize) {
+            let _ = write!(output, "{line}: ");
+        }
+
+        if let Some(label) = labels[*pc as usize] {
+            let _ = write!(output, "L{label}: ");
+        }
+
+        let label = |target: Option<usize>| match target
+            .and_then(|target| labels.get(target).copied().flatten())
+        {
+            Some(label) => format!("L{label}"),
+            None => "L?".to_string(),
+        };
+
+        let text = Formatter { bytecode, proto }
+            .instruction(instruction, label(jump_target(*pc, instruction)));
+        let _ = writeln!(output, "{text}");
+    }
+
+    Ok(())
+}
+
+fn jump_target(pc: u32, instruction: &DecodedInstruction) -> Option<usize> {
+    let info = instruction.opcode.info();
+    let offset = match instruction.operands {
+        Operands::AD { d, .. } if info.d == LuauOperand::Jump => d as i64 + 1,
+        Operands::E { e } if info.e == LuauOperand::Jump => e as i64 + 1,
+        // fast calls skip over the CALL that follows them
+        Operands::ABC { c, .. } if info.a == LuauOperand::Builtin => c as i64 + 2,
+        _ => return None,
+    };
+
+    usize::try_from(pc as i64 + offset).ok()
+}
+
+struct Formatter<'a> {
+    bytecode: &'a LuaBytecode,
+    proto: &'a Proto,
+}
+
+impl Formatter<'_> {
+    fn instruction(&self, instruction: &DecodedInstruction, label: String) -> String {
+        let opcode = instruction.opcode;
+        let name = opcode.info().name;
+        let aux = instruction.aux.unwrap_or_default();
+
+        let (a, b, c) = match instruction.operands {
+            Operands::ABC { a, b, c } => (a, b, c),
+            Operands::AD { a, .. } => (a, 0, 0),
+            Operands::E { .. } => (0, 0, 0),
+        };
+        let d = match instruction.operands {
+            Operands::AD { d, .. } => d,
+            _ => 0,
+        };
+
+        let k = |index: u32| format!("K{index} [{}]", self.constant(index));
+        let not = |aux: u32| if aux >> 31 != 0 { " NOT" } else { "" };
+
+        match opcode {
+            LuauOpcode::Nop | LuauOpcode::Break | LuauOpcode::NativeCall | LuauOpcode::Coverage => {
+                name.to_string()
+            }
+
+            LuauOpcode::LoadNil | LuauOpcode::CloseUpvals => format!("{name} R{a}"),
+            LuauOpcode::LoadB if c != 0 => format!("{name} R{a} {b} +{c}"),
+            LuauOpcode::LoadB => format!("{name} R{a} {b}"),
+            LuauOpcode::LoadN => format!("{name} R{a} {d}"),
+            LuauOpcode::LoadK | LuauOpcode::DupClosure => format!("{name} R{a} {}", k(d as u32)),
+            LuauOpcode::LoadKx => format!("{name} R{a} {}", k(aux)),
+            LuauOpcode::Move | LuauOpcode::Not | LuauOpcode::Minus | LuauOpcode::Length => {
+                format!("{name} R{a} R{b}")
+            }
+
+            LuauOpcode::GetGlobal | LuauOpcode::SetGlobal => format!("{name} R{a} {}", k(aux)),
+            LuauOpcode::GetUpval | LuauOpcode::SetUpval => format!("{name} R{a} {b}"),
+
+            LuauOpcode::GetImport => {
+                let path = LuauImport::from(aux as i32)
+                    .resolve(&self.proto.constants)
+                    .map(|path| self.join(&path))
+                    .unwrap_or_default();
+                format!("{name} R{a} {d} [{path}]")
+            }
+
+            LuauOpcode::GetTableKs | LuauOpcode::SetTableKs | LuauOpcode::NameCall => {
+                format!("{name} R{a} R{b} {}", k(aux))
+            }
+            LuauOpcode::GetTableN | LuauOpcode::SetTableN => format!("{name} R{a} R{b} {}", c + 1),
+
+            LuauOpcode::NewClosure => format!("{name} R{a} P{d}"),
+            LuauOpcode::Call => format!("{name} R{a} {} {}", b as i32 - 1, c as i32 - 1),
+            LuauOpcode::Return => format!("{name} R{a} {}", b as i32 - 1),
+
+            LuauOpcode::Jump | LuauOpcode::JumpBack | LuauOpcode::JumpX => {
+                format!("{name} {label}")
+            }
+            LuauOpcode::JumpIf
+            | LuauOpcode::JumpIfNot
+            | LuauOpcode::ForNPrep
+            | LuauOpcode::ForNLoop
+            | LuauOpcode::ForGPrep
+            | LuauOpcode::ForGPrepInext
+            | LuauOpcode::ForGPrepNext => format!("{name} R{a} {label}"),
+            LuauOpcode::JumpIfEq
+            | LuauOpcode::JumpIfLe
+            | LuauOpcode::JumpIfLt
+            | LuauOpcode::JumpIfNotEq
+            | LuauOpcode::JumpIfNotLe
+            | LuauOpcode::JumpIfNotLt => format!("{name} R{a} R{aux} {label}"),
+
+            LuauOpcode::AddK
+            | LuauOpcode::SubK
+            | LuauOpcode::MulK
+            | LuauOpcode::DivK
+            | LuauOpcode::ModK
+            | LuauOpcode::PowK
+            | LuauOpcode::AndK
+            | LuauOpcode::OrK
+            | LuauOpcode::IDivK => format!("{name} R{a} R{b} {}", k(c)),
+            LuauOpcode::SubRk | LuauOpcode::DivRk => format!("{name} R{a} {} R{c}", k(b)),
+
+            LuauOpcode::NewTable => {
+                // B past 64 can not be expanded, it is printed as is
+                let hash_size = match b {
+                    0 => 0,
+                    b => 1u64.checked_shl(b - 1).unwrap_or(b as u64),
+                };
+                format!("{name} R{a} {hash_size} {aux}")
+            }
+            LuauOpcode::DupTable => format!("{name} R{a} {d}"),
+            LuauOpcode::SetList => format!("{name} R{a} R{b} {} [{aux}]", c as i32 - 1),
+
+            LuauOpcode::ForGLoop => {
+                let inext = if aux >> 31 != 0 { " [inext]" } else { "" };
+                format!("{name} R{a} {label} {}{inext}", aux & 0xff)
+            }
+
+            LuauOpcode::FastCall => format!("{name} {} {label}", self.builtin(a)),
+            LuauOpcode::FastCall1 => format!("{name} {} R{b} {label}", self.builtin(a)),
+            LuauOpcode::FastCall2 => format!("{name} {} R{b} R{aux} {label}", self.builtin(a)),
+            LuauOpcode::FastCall2K => format!("{name} {} R{b} {} {label}", self.builtin(a), k(aux)),
+            LuauOpcode::FastCall3 => format!(
+                "{name} {} R{b} R{} R{} {label}",
+                self.builtin(a),
+                aux & 0xff,
+                (aux >> 8) & 0xff
+            ),
+
+            LuauOpcode::GetVarargs => format!("{name} R{a} {}", b as i32 - 1),
+            LuauOpcode::PrepVarargs => format!("{name} {a}"),
+
+            LuauOpcode::Capture => {
+                let (kind, register) = match a {
+                    0 => ("VAL", 'R'),
+                    1 => ("REF", 'R'),
+                    2 => ("UPVAL", 'U'),
+                    _ => ("?", 'R'),
+                };
+                format!("{name} {kind} {register}{b}")
+            }
+
+            LuauOpcode::JumpXeqkNil => format!("{name} R{a} {label}{}", not(aux)),
+            LuauOpcode::JumpXeqkB => format!("{name} R{a} {} {label}{}", aux & 1, not(aux)),
+            LuauOpcode::JumpXeqkN | LuauOpcode::JumpXeqkS => {
+                format!("{name} R{a} {} {label}{}", k(aux & 0xffffff), not(aux))
+            }
+
+            LuauOpcode::GetTable
+            | LuauOpcode::SetTable
+            | LuauOpcode::Add
+            | LuauOpcode::Sub
+            | LuauOpcode::Mul
+            | LuauOpcode::Div
+            | LuauOpcode::Mod
+            | LuauOpcode::Pow
+            | LuauOpcode::And
+            | LuauOpcode::Or
+            | LuauOpcode::Concat
+            | LuauOpcode::IDiv => format!("{name} R{a} R{b} R{c}"),
+        }
+    }
+
+    fn builtin(&self, id: u32) -> String {
+        match BUILTINS.get(id as usize) {
+            Some(name) => name.to_string(),
+            None => format!("builtin{id}"),
+        }
+    }
+
+    fn join(&self, path: &[Vec<u8>]) -> String {
+        path.iter()
+            .map(|part| String::from_utf8_lossy(part))
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+
+    fn constant(&self, index: u32) -> String {
+        let Some(constant) = self.proto.constants.get(index as usize) else {
+            return String::new();
+        };
+
+        match constant {
+            Constant::Nil => "nil".to_string(),
+            Constant::Bool(value) => value.to_string(),
+            Constant::RawBool(byte) => (*byte != 0).to_string(),
+            Constant::Number(value) => format_g(*value, 17),
+
+            // strings with control characters are left out
+            Constant::String(value) if value.iter().any(|byte| *byte < b' ') => String::new(),
+            Constant::String(value) if value.len() < 32 => quote(value),
+            Constant::String(value) => format!("{}...", quote(&value[..32])),
+
+            Constant::Import(value) => LuauImport::from(*value)
+                .resolve(&self.proto.constants)
+                .map(|path| self.join(&path))
+                .unwrap_or_default(),
+            Constant::Table(..) => "{...}".to_string(),
+            Constant::Closure(id) => {
+                let name = self
+                    .bytecode
+                    .protos
+                    .get(*id as usize)
+                    .and_then(|proto| proto.name.as_ref());
+                // anonymous closures have an empty preview, like luau-compile prints them
+                match name {
+                    Some(name) => quote(name),
+                    None => String::new(),
+                }
+            }
+            Constant::Vector(x, y, z, w) => {
+                let mut vector = [x, y, z].map(|value| format_g(*value as f64, 9)).join(", ");
+                if *w != 0.0 {
+                    let _ = write!(vector, ", {}", format_g(*w as f64, 9));
+                }
+                vector
+            }
+
+            #[allow(unreachable_patterns)]
+            _ => String::new(),
+        }
+    }
+}
+
+// quotes and backslashes are escaped so the preview reads back as one string
+fn quote(value: &[u8]) -> String {
+    let mut quoted = String::from("'");
+    for char in String::from_utf8_lossy(value).chars() {
+        if matches!(char, '\'' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(char);
+    }
+    quoted.push('\'');
+    quoted
+}
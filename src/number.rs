@@ -0,0 +1,35 @@
+/// C's `%.{precision}g`, which lua and luau use to print numbers.
+pub(crate) fn format_g(value: f64, precision: i32) -> String {
+    if value.is_nan() {
+        return if value.is_sign_negative() {
+            "-nan"
+        } else {
+            "nan"
+        }
+        .to_string();
+    } else if value.is_infinite() {
+        return if value < 0.0 { "-inf" } else { "inf" }.to_string();
+    } else if value == 0.0 {
+        return if value.is_sign_negative() { "-0" } else { "0" }.to_string();
+    }
+
+    let scientific = format!("{:.*e}", precision as usize - 1, value);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent = exponent.parse::<i32>().unwrap();
+
+    let trim = |digits: &str| match digits.contains('.') {
+        true => digits
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_string(),
+        false => digits.to_string(),
+    };
+
+    if !(-4..precision).contains(&exponent) {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}e{sign}{:02}", trim(mantissa), exponent.abs())
+    } else {
+        let decimals = (precision - 1 - exponent) as usize;
+        trim(&format!("{value:.decimals$}"))
+    }
+}
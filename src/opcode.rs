@@ -1,29 +1,103 @@
-#[cfg(feature = "lua51")]
+#[cfg(any(feature = "lua51", feature = "lua52", feature = "lua53"))]
 const LUA_OP_SIZE: u32 = 6;
-#[cfg(feature = "lua51")]
+#[cfg(any(feature = "lua51", feature = "lua52", feature = "lua53"))]
 const LUA_A_SIZE: u32 = 8;
-#[cfg(feature = "lua51")]
+#[cfg(any(feature = "lua51", feature = "lua52", feature = "lua53"))]
 const LUA_B_SIZE: u32 = 9;
-#[cfg(feature = "lua51")]
+#[cfg(any(feature = "lua51", feature = "lua52", feature = "lua53"))]
 const LUA_C_SIZE: u32 = 9;
-#[cfg(feature = "lua51")]
+#[cfg(any(feature = "lua51", feature = "lua52", feature = "lua53"))]
 const LUA_BX_SIZE: u32 = LUA_B_SIZE + LUA_C_SIZE;
+#[cfg(any(feature = "lua52", feature = "lua53"))]
+const LUA_AX_SIZE: u32 = LUA_BX_SIZE + LUA_A_SIZE;
 
-#[cfg(feature = "lua51")]
+#[cfg(any(feature = "lua51", feature = "lua52", feature = "lua53"))]
 const LUA_OP_POSITION: u32 = 0;
-#[cfg(feature = "lua51")]
+#[cfg(any(feature = "lua51", feature = "lua52", feature = "lua53"))]
 const LUA_A_POSITION: u32 = LUA_OP_SIZE;
-#[cfg(feature = "lua51")]
+#[cfg(any(feature = "lua51", feature = "lua52", feature = "lua53"))]
 const LUA_C_POSITION: u32 = LUA_A_POSITION + LUA_A_SIZE;
-#[cfg(feature = "lua51")]
+#[cfg(any(feature = "lua51", feature = "lua52", feature = "lua53"))]
 const LUA_B_POSITION: u32 = LUA_C_POSITION + LUA_C_SIZE;
-#[cfg(feature = "lua51")]
+#[cfg(any(feature = "lua51", feature = "lua52", feature = "lua53"))]
 const LUA_BX_POSITION: u32 = LUA_C_POSITION;
-
-#[cfg(feature = "lua51")]
+#[cfg(any(feature = "lua52", feature = "lua53"))]
+const LUA_AX_POSITION: u32 = LUA_A_POSITION;
+
+#[cfg(any(feature = "lua51", feature = "lua52", feature = "lua53"))]
+pub const MAX_ARG_A: u32 = (1 << LUA_A_SIZE) - 1;
+#[cfg(any(feature = "lua51", feature = "lua52", feature = "lua53"))]
+pub const MAX_ARG_B: u32 = (1 << LUA_B_SIZE) - 1;
+#[cfg(any(feature = "lua51", feature = "lua52", feature = "lua53"))]
+pub const MAX_ARG_C: u32 = (1 << LUA_C_SIZE) - 1;
+#[cfg(any(feature = "lua51", feature = "lua52", feature = "lua53"))]
 pub const MAX_ARG_BX: u32 = (1 << LUA_BX_SIZE) - 1;
-#[cfg(feature = "lua51")]
+#[cfg(any(feature = "lua51", feature = "lua52", feature = "lua53"))]
 pub const MAX_ARG_SBX: i32 = (MAX_ARG_BX as i32) >> 1;
+#[cfg(any(feature = "lua52", feature = "lua53"))]
+pub const MAX_ARG_AX: u32 = (1 << LUA_AX_SIZE) - 1;
+
+/// Set in B or C when the operand is a constant index instead of a register.
+#[cfg(any(feature = "lua51", feature = "lua52", feature = "lua53"))]
+pub const BIT_RK: u32 = 1 << (LUA_B_SIZE - 1);
+#[cfg(any(feature = "lua51", feature = "lua52", feature = "lua53"))]
+pub const MAX_INDEX_RK: u32 = BIT_RK - 1;
+
+#[cfg(any(feature = "lua51", feature = "lua52", feature = "lua53"))]
+pub fn is_k(x: u32) -> bool {
+    x & BIT_RK != 0
+}
+
+#[cfg(any(feature = "lua51", feature = "lua52", feature = "lua53"))]
+pub fn index_k(x: u32) -> u32 {
+    x & !BIT_RK
+}
+
+#[cfg(any(feature = "lua51", feature = "lua52", feature = "lua53"))]
+pub fn rk_as_k(x: u32) -> u32 {
+    x | BIT_RK
+}
+
+#[cfg(feature = "lua54")]
+const LUA54_OP_SIZE: u32 = 7;
+#[cfg(feature = "lua54")]
+const LUA54_A_SIZE: u32 = 8;
+#[cfg(feature = "lua54")]
+const LUA54_B_SIZE: u32 = 8;
+#[cfg(feature = "lua54")]
+const LUA54_C_SIZE: u32 = 8;
+#[cfg(feature = "lua54")]
+const LUA54_BX_SIZE: u32 = LUA54_B_SIZE + LUA54_C_SIZE + 1;
+#[cfg(feature = "lua54")]
+const LUA54_AX_SIZE: u32 = LUA54_BX_SIZE + LUA54_A_SIZE;
+#[cfg(feature = "lua54")]
+const LUA54_SJ_SIZE: u32 = LUA54_BX_SIZE + LUA54_A_SIZE;
+
+#[cfg(feature = "lua54")]
+const LUA54_A_POSITION: u32 = LUA54_OP_SIZE;
+#[cfg(feature = "lua54")]
+const LUA54_K_POSITION: u32 = LUA54_A_POSITION + LUA54_A_SIZE;
+#[cfg(feature = "lua54")]
+const LUA54_B_POSITION: u32 = LUA54_K_POSITION + 1;
+#[cfg(feature = "lua54")]
+const LUA54_C_POSITION: u32 = LUA54_B_POSITION + LUA54_B_SIZE;
+#[cfg(feature = "lua54")]
+const LUA54_BX_POSITION: u32 = LUA54_K_POSITION;
+#[cfg(feature = "lua54")]
+const LUA54_AX_POSITION: u32 = LUA54_A_POSITION;
+#[cfg(feature = "lua54")]
+const LUA54_SJ_POSITION: u32 = LUA54_A_POSITION;
+
+#[cfg(feature = "lua54")]
+pub const LUA54_MAX_ARG_BX: u32 = (1 << LUA54_BX_SIZE) - 1;
+#[cfg(feature = "lua54")]
+pub const LUA54_OFFSET_SBX: i32 = (LUA54_MAX_ARG_BX >> 1) as i32;
+#[cfg(feature = "lua54")]
+pub const LUA54_MAX_ARG_AX: u32 = (1 << LUA54_AX_SIZE) - 1;
+#[cfg(feature = "lua54")]
+pub const LUA54_OFFSET_SJ: i32 = ((1 << LUA54_SJ_SIZE) - 1) >> 1;
+#[cfg(feature = "lua54")]
+pub const LUA54_OFFSET_SC: i32 = ((1 << LUA54_C_SIZE) - 1) >> 1;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum LuaOpMode {
@@ -37,6 +111,162 @@ pub enum LuaOpMode {
     IAsBx,
 }
 
+/// How an instruction uses its B or C operand, `OpArgMask` in `lopcodes.h`.
+#[cfg(feature = "lua51")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LuaOpArgMode {
+    /// not used
+    N,
+    /// used, but not a register or constant
+    U,
+    /// a register or a jump offset
+    R,
+    /// a constant, or a register/constant (RK) in ABC mode
+    K,
+}
+
+/// One entry of `luaP_opmodes`.
+#[cfg(feature = "lua51")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LuaOpInfo {
+    /// The instruction is a test, the next one is always a jump.
+    pub test: bool,
+    pub sets_a: bool,
+    pub b: LuaOpArgMode,
+    pub c: LuaOpArgMode,
+    pub mode: LuaOpMode,
+}
+
+#[cfg(feature = "luau")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LuauOpMode {
+    ABC,
+    AD,
+    E,
+}
+
+/// What a Luau operand or AUX word refers to.
+#[cfg(feature = "luau")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LuauOperand {
+    None,
+    Register,
+    Constant,
+    Upvalue,
+    /// A signed offset relative to the next instruction.
+    Jump,
+    /// An index into the child protos of the current proto.
+    Proto,
+    /// A `LuauImport` path.
+    Import,
+    Builtin,
+    Immediate,
+}
+
+/// Per opcode metadata, the same for every Luau bytecode version.
+#[cfg(feature = "luau")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LuauOpInfo {
+    /// The name `luau-compile --text` prints.
+    pub name: &'static str,
+    pub mode: LuauOpMode,
+
+    pub a: LuauOperand,
+    pub b: LuauOperand,
+    pub c: LuauOperand,
+    pub d: LuauOperand,
+    pub e: LuauOperand,
+    /// `LuauOperand::None` when the opcode has no AUX word.
+    pub aux: LuauOperand,
+
+    pub branches: bool,
+    pub calls: bool,
+    pub returns: bool,
+    /// Execution never falls through to the next instruction.
+    pub ends_block: bool,
+}
+
+#[cfg(feature = "luau")]
+impl LuauOpInfo {
+    fn abc(name: &'static str, a: LuauOperand, b: LuauOperand, c: LuauOperand) -> Self {
+        LuauOpInfo {
+            name,
+            mode: LuauOpMode::ABC,
+            a,
+            b,
+            c,
+            d: LuauOperand::None,
+            e: LuauOperand::None,
+            aux: LuauOperand::None,
+            branches: false,
+            calls: false,
+            returns: false,
+            ends_block: false,
+        }
+    }
+
+    fn ad(name: &'static str, a: LuauOperand, d: LuauOperand) -> Self {
+        LuauOpInfo {
+            mode: LuauOpMode::AD,
+            d,
+            ..Self::abc(name, a, LuauOperand::None, LuauOperand::None)
+        }
+    }
+
+    fn e(name: &'static str, e: LuauOperand) -> Self {
+        LuauOpInfo {
+            mode: LuauOpMode::E,
+            e,
+            ..Self::abc(
+                name,
+                LuauOperand::None,
+                LuauOperand::None,
+                LuauOperand::None,
+            )
+        }
+    }
+
+    fn aux(self, aux: LuauOperand) -> Self {
+        LuauOpInfo { aux, ..self }
+    }
+
+    fn branch(self) -> Self {
+        LuauOpInfo {
+            branches: true,
+            ..self
+        }
+    }
+
+    fn jump(self) -> Self {
+        LuauOpInfo {
+            branches: true,
+            ends_block: true,
+            ..self
+        }
+    }
+
+    fn call(self) -> Self {
+        LuauOpInfo {
+            calls: true,
+            ..self
+        }
+    }
+
+    pub fn has_aux(&self) -> bool {
+        self.aux != LuauOperand::None
+    }
+}
+
+#[cfg(feature = "lua54")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Lua54OpMode {
+    IABC,
+    IABx,
+    IAsBx,
+    IAx,
+    IsJ,
+}
+
 #[cfg(feature = "lua51")]
 #[repr(u8)]
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -95,6 +325,363 @@ pub enum LuaOpcode {
     Vararg,
 }
 
+#[cfg(feature = "lua52")]
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Lua52Opcode {
+    Move,
+    LoadK,
+    LoadKx,
+    LoadBool,
+    LoadNil,
+    GetUpval,
+
+    GetTabUp,
+    GetTable,
+
+    SetTabUp,
+    SetUpval,
+    SetTable,
+
+    NewTable,
+
+    Self_,
+
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
+    Unm,
+    Not,
+    Len,
+
+    Concat,
+
+    Jmp,
+
+    Eq,
+    Lt,
+    Le,
+
+    Test,
+    TestSet,
+
+    Call,
+    TailCall,
+    Return,
+
+    ForLoop,
+    ForPrep,
+
+    TForCall,
+    TForLoop,
+
+    SetList,
+
+    Closure,
+
+    Vararg,
+
+    ExtraArg,
+}
+
+#[cfg(feature = "lua53")]
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Lua53Opcode {
+    Move,
+    LoadK,
+    LoadKx,
+    LoadBool,
+    LoadNil,
+    GetUpval,
+
+    GetTabUp,
+    GetTable,
+
+    SetTabUp,
+    SetUpval,
+    SetTable,
+
+    NewTable,
+
+    Self_,
+
+    Add,
+    Sub,
+    Mul,
+    Mod,
+    Pow,
+    Div,
+    IDiv,
+    BAnd,
+    BOr,
+    BXor,
+    Shl,
+    Shr,
+
+    Unm,
+    BNot,
+    Not,
+    Len,
+
+    Concat,
+
+    Jmp,
+
+    Eq,
+    Lt,
+    Le,
+
+    Test,
+    TestSet,
+
+    Call,
+    TailCall,
+    Return,
+
+    ForLoop,
+    ForPrep,
+
+    TForCall,
+    TForLoop,
+
+    SetList,
+
+    Closure,
+
+    Vararg,
+
+    ExtraArg,
+}
+
+#[cfg(feature = "lua54")]
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Lua54Opcode {
+    Move,
+    LoadI,
+    LoadF,
+    LoadK,
+    LoadKx,
+    LoadFalse,
+    LFalseSkip,
+    LoadTrue,
+    LoadNil,
+
+    GetUpval,
+    SetUpval,
+
+    GetTabUp,
+    GetTable,
+    GetI,
+    GetField,
+    SetTabUp,
+    SetTable,
+    SetI,
+    SetField,
+
+    NewTable,
+
+    Self_,
+
+    AddI,
+    AddK,
+    SubK,
+    MulK,
+    ModK,
+    PowK,
+    DivK,
+    IDivK,
+    BAndK,
+    BOrK,
+    BXorK,
+    ShrI,
+    ShlI,
+
+    Add,
+    Sub,
+    Mul,
+    Mod,
+    Pow,
+    Div,
+    IDiv,
+    BAnd,
+    BOr,
+    BXor,
+    Shl,
+    Shr,
+
+    MmBin,
+    MmBinI,
+    MmBinK,
+
+    Unm,
+    BNot,
+    Not,
+    Len,
+
+    Concat,
+
+    Close,
+    Tbc,
+
+    Jmp,
+
+    Eq,
+    Lt,
+    Le,
+    EqK,
+    EqI,
+    LtI,
+    LeI,
+    GtI,
+    GeI,
+
+    Test,
+    TestSet,
+
+    Call,
+    TailCall,
+    Return,
+    Return0,
+    Return1,
+
+    ForLoop,
+    ForPrep,
+    TForPrep,
+    TForCall,
+    TForLoop,
+
+    SetList,
+
+    Closure,
+
+    Vararg,
+    VarargPrep,
+
+    ExtraArg,
+}
+
+/// Opcodes are numbered like LuaJIT 2.1, 2.0 dumps are mapped through their own table.
+#[cfg(feature = "luajit")]
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LuaJitOpcode {
+    IsLt,
+    IsGe,
+    IsLe,
+    IsGt,
+    IsEqV,
+    IsNeV,
+    IsEqS,
+    IsNeS,
+    IsEqN,
+    IsNeN,
+    IsEqP,
+    IsNeP,
+
+    IsTc,
+    IsFc,
+    IsT,
+    IsF,
+    IsType,
+    IsNum,
+
+    Mov,
+    Not,
+    Unm,
+    Len,
+
+    AddVN,
+    SubVN,
+    MulVN,
+    DivVN,
+    ModVN,
+
+    AddNV,
+    SubNV,
+    MulNV,
+    DivNV,
+    ModNV,
+
+    AddVV,
+    SubVV,
+    MulVV,
+    DivVV,
+    ModVV,
+
+    Pow,
+    Cat,
+
+    KStr,
+    KCData,
+    KShort,
+    KNum,
+    KPri,
+    KNil,
+
+    UGet,
+    USetV,
+    USetS,
+    USetN,
+    USetP,
+    UClo,
+    FNew,
+
+    TNew,
+    TDup,
+    GGet,
+    GSet,
+    TGetV,
+    TGetS,
+    TGetB,
+    TGetR,
+    TSetV,
+    TSetS,
+    TSetB,
+    TSetM,
+    TSetR,
+
+    CallM,
+    Call,
+    CallMT,
+    CallT,
+    IterC,
+    IterN,
+    Varg,
+    IsNext,
+
+    RetM,
+    Ret,
+    Ret0,
+    Ret1,
+
+    ForI,
+    JForI,
+    ForL,
+    IForL,
+    JForL,
+    IterL,
+    IIterL,
+    JIterL,
+    Loop,
+    ILoop,
+    JLoop,
+    Jmp,
+
+    FuncF,
+    IFuncF,
+    JFuncF,
+    FuncV,
+    IFuncV,
+    JFuncV,
+    FuncC,
+    FuncCW,
+}
+
 #[cfg(feature = "luau")]
 #[repr(u8)]
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -184,10 +771,640 @@ pub enum LuauOpcode {
     IDivK,
 }
 
+#[cfg(feature = "lua51")]
+const LUA_OPCODES: [LuaOpcode; 38] = [
+    LuaOpcode::Move,
+    LuaOpcode::LoadK,
+    LuaOpcode::LoadBool,
+    LuaOpcode::LoadNil,
+    LuaOpcode::GetUpval,
+    LuaOpcode::GetGlobal,
+    LuaOpcode::GetTable,
+    LuaOpcode::SetGlobal,
+    LuaOpcode::SetUpval,
+    LuaOpcode::SetTable,
+    LuaOpcode::NewTable,
+    LuaOpcode::Self_,
+    LuaOpcode::Add,
+    LuaOpcode::Sub,
+    LuaOpcode::Mul,
+    LuaOpcode::Div,
+    LuaOpcode::Mod,
+    LuaOpcode::Pow,
+    LuaOpcode::Unm,
+    LuaOpcode::Not,
+    LuaOpcode::Len,
+    LuaOpcode::Concat,
+    LuaOpcode::Jmp,
+    LuaOpcode::Eq,
+    LuaOpcode::Lt,
+    LuaOpcode::Le,
+    LuaOpcode::Test,
+    LuaOpcode::TestSet,
+    LuaOpcode::Call,
+    LuaOpcode::TailCall,
+    LuaOpcode::Return,
+    LuaOpcode::ForLoop,
+    LuaOpcode::ForPrep,
+    LuaOpcode::TForLoop,
+    LuaOpcode::SetList,
+    LuaOpcode::Close,
+    LuaOpcode::Closure,
+    LuaOpcode::Vararg,
+];
+
+#[cfg(feature = "lua51")]
+impl TryFrom<u8> for LuaOpcode {
+    type Error = u8;
+
+    fn try_from(op: u8) -> Result<Self, Self::Error> {
+        LUA_OPCODES.get(op as usize).copied().ok_or(op)
+    }
+}
+
+#[cfg(feature = "lua52")]
+const LUA52_OPCODES: [Lua52Opcode; 40] = [
+    Lua52Opcode::Move,
+    Lua52Opcode::LoadK,
+    Lua52Opcode::LoadKx,
+    Lua52Opcode::LoadBool,
+    Lua52Opcode::LoadNil,
+    Lua52Opcode::GetUpval,
+    Lua52Opcode::GetTabUp,
+    Lua52Opcode::GetTable,
+    Lua52Opcode::SetTabUp,
+    Lua52Opcode::SetUpval,
+    Lua52Opcode::SetTable,
+    Lua52Opcode::NewTable,
+    Lua52Opcode::Self_,
+    Lua52Opcode::Add,
+    Lua52Opcode::Sub,
+    Lua52Opcode::Mul,
+    Lua52Opcode::Div,
+    Lua52Opcode::Mod,
+    Lua52Opcode::Pow,
+    Lua52Opcode::Unm,
+    Lua52Opcode::Not,
+    Lua52Opcode::Len,
+    Lua52Opcode::Concat,
+    Lua52Opcode::Jmp,
+    Lua52Opcode::Eq,
+    Lua52Opcode::Lt,
+    Lua52Opcode::Le,
+    Lua52Opcode::Test,
+    Lua52Opcode::TestSet,
+    Lua52Opcode::Call,
+    Lua52Opcode::TailCall,
+    Lua52Opcode::Return,
+    Lua52Opcode::ForLoop,
+    Lua52Opcode::ForPrep,
+    Lua52Opcode::TForCall,
+    Lua52Opcode::TForLoop,
+    Lua52Opcode::SetList,
+    Lua52Opcode::Closure,
+    Lua52Opcode::Vararg,
+    Lua52Opcode::ExtraArg,
+];
+
+#[cfg(feature = "lua52")]
+impl TryFrom<u8> for Lua52Opcode {
+    type Error = u8;
+
+    fn try_from(op: u8) -> Result<Self, Self::Error> {
+        LUA52_OPCODES.get(op as usize).copied().ok_or(op)
+    }
+}
+
+#[cfg(feature = "lua53")]
+const LUA53_OPCODES: [Lua53Opcode; 47] = [
+    Lua53Opcode::Move,
+    Lua53Opcode::LoadK,
+    Lua53Opcode::LoadKx,
+    Lua53Opcode::LoadBool,
+    Lua53Opcode::LoadNil,
+    Lua53Opcode::GetUpval,
+    Lua53Opcode::GetTabUp,
+    Lua53Opcode::GetTable,
+    Lua53Opcode::SetTabUp,
+    Lua53Opcode::SetUpval,
+    Lua53Opcode::SetTable,
+    Lua53Opcode::NewTable,
+    Lua53Opcode::Self_,
+    Lua53Opcode::Add,
+    Lua53Opcode::Sub,
+    Lua53Opcode::Mul,
+    Lua53Opcode::Mod,
+    Lua53Opcode::Pow,
+    Lua53Opcode::Div,
+    Lua53Opcode::IDiv,
+    Lua53Opcode::BAnd,
+    Lua53Opcode::BOr,
+    Lua53Opcode::BXor,
+    Lua53Opcode::Shl,
+    Lua53Opcode::Shr,
+    Lua53Opcode::Unm,
+    Lua53Opcode::BNot,
+    Lua53Opcode::Not,
+    Lua53Opcode::Len,
+    Lua53Opcode::Concat,
+    Lua53Opcode::Jmp,
+    Lua53Opcode::Eq,
+    Lua53Opcode::Lt,
+    Lua53Opcode::Le,
+    Lua53Opcode::Test,
+    Lua53Opcode::TestSet,
+    Lua53Opcode::Call,
+    Lua53Opcode::TailCall,
+    Lua53Opcode::Return,
+    Lua53Opcode::ForLoop,
+    Lua53Opcode::ForPrep,
+    Lua53Opcode::TForCall,
+    Lua53Opcode::TForLoop,
+    Lua53Opcode::SetList,
+    Lua53Opcode::Closure,
+    Lua53Opcode::Vararg,
+    Lua53Opcode::ExtraArg,
+];
+
+#[cfg(feature = "lua53")]
+impl TryFrom<u8> for Lua53Opcode {
+    type Error = u8;
+
+    fn try_from(op: u8) -> Result<Self, Self::Error> {
+        LUA53_OPCODES.get(op as usize).copied().ok_or(op)
+    }
+}
+
+#[cfg(feature = "lua54")]
+const LUA54_OPCODES: [Lua54Opcode; 83] = [
+    Lua54Opcode::Move,
+    Lua54Opcode::LoadI,
+    Lua54Opcode::LoadF,
+    Lua54Opcode::LoadK,
+    Lua54Opcode::LoadKx,
+    Lua54Opcode::LoadFalse,
+    Lua54Opcode::LFalseSkip,
+    Lua54Opcode::LoadTrue,
+    Lua54Opcode::LoadNil,
+    Lua54Opcode::GetUpval,
+    Lua54Opcode::SetUpval,
+    Lua54Opcode::GetTabUp,
+    Lua54Opcode::GetTable,
+    Lua54Opcode::GetI,
+    Lua54Opcode::GetField,
+    Lua54Opcode::SetTabUp,
+    Lua54Opcode::SetTable,
+    Lua54Opcode::SetI,
+    Lua54Opcode::SetField,
+    Lua54Opcode::NewTable,
+    Lua54Opcode::Self_,
+    Lua54Opcode::AddI,
+    Lua54Opcode::AddK,
+    Lua54Opcode::SubK,
+    Lua54Opcode::MulK,
+    Lua54Opcode::ModK,
+    Lua54Opcode::PowK,
+    Lua54Opcode::DivK,
+    Lua54Opcode::IDivK,
+    Lua54Opcode::BAndK,
+    Lua54Opcode::BOrK,
+    Lua54Opcode::BXorK,
+    Lua54Opcode::ShrI,
+    Lua54Opcode::ShlI,
+    Lua54Opcode::Add,
+    Lua54Opcode::Sub,
+    Lua54Opcode::Mul,
+    Lua54Opcode::Mod,
+    Lua54Opcode::Pow,
+    Lua54Opcode::Div,
+    Lua54Opcode::IDiv,
+    Lua54Opcode::BAnd,
+    Lua54Opcode::BOr,
+    Lua54Opcode::BXor,
+    Lua54Opcode::Shl,
+    Lua54Opcode::Shr,
+    Lua54Opcode::MmBin,
+    Lua54Opcode::MmBinI,
+    Lua54Opcode::MmBinK,
+    Lua54Opcode::Unm,
+    Lua54Opcode::BNot,
+    Lua54Opcode::Not,
+    Lua54Opcode::Len,
+    Lua54Opcode::Concat,
+    Lua54Opcode::Close,
+    Lua54Opcode::Tbc,
+    Lua54Opcode::Jmp,
+    Lua54Opcode::Eq,
+    Lua54Opcode::Lt,
+    Lua54Opcode::Le,
+    Lua54Opcode::EqK,
+    Lua54Opcode::EqI,
+    Lua54Opcode::LtI,
+    Lua54Opcode::LeI,
+    Lua54Opcode::GtI,
+    Lua54Opcode::GeI,
+    Lua54Opcode::Test,
+    Lua54Opcode::TestSet,
+    Lua54Opcode::Call,
+    Lua54Opcode::TailCall,
+    Lua54Opcode::Return,
+    Lua54Opcode::Return0,
+    Lua54Opcode::Return1,
+    Lua54Opcode::ForLoop,
+    Lua54Opcode::ForPrep,
+    Lua54Opcode::TForPrep,
+    Lua54Opcode::TForCall,
+    Lua54Opcode::TForLoop,
+    Lua54Opcode::SetList,
+    Lua54Opcode::Closure,
+    Lua54Opcode::Vararg,
+    Lua54Opcode::VarargPrep,
+    Lua54Opcode::ExtraArg,
+];
+
+#[cfg(feature = "lua54")]
+impl TryFrom<u8> for Lua54Opcode {
+    type Error = u8;
+
+    fn try_from(op: u8) -> Result<Self, Self::Error> {
+        LUA54_OPCODES.get(op as usize).copied().ok_or(op)
+    }
+}
+
+#[cfg(feature = "luajit")]
+const LUAJIT20_OPCODES: [LuaJitOpcode; 93] = [
+    LuaJitOpcode::IsLt,
+    LuaJitOpcode::IsGe,
+    LuaJitOpcode::IsLe,
+    LuaJitOpcode::IsGt,
+    LuaJitOpcode::IsEqV,
+    LuaJitOpcode::IsNeV,
+    LuaJitOpcode::IsEqS,
+    LuaJitOpcode::IsNeS,
+    LuaJitOpcode::IsEqN,
+    LuaJitOpcode::IsNeN,
+    LuaJitOpcode::IsEqP,
+    LuaJitOpcode::IsNeP,
+    LuaJitOpcode::IsTc,
+    LuaJitOpcode::IsFc,
+    LuaJitOpcode::IsT,
+    LuaJitOpcode::IsF,
+    LuaJitOpcode::Mov,
+    LuaJitOpcode::Not,
+    LuaJitOpcode::Unm,
+    LuaJitOpcode::Len,
+    LuaJitOpcode::AddVN,
+    LuaJitOpcode::SubVN,
+    LuaJitOpcode::MulVN,
+    LuaJitOpcode::DivVN,
+    LuaJitOpcode::ModVN,
+    LuaJitOpcode::AddNV,
+    LuaJitOpcode::SubNV,
+    LuaJitOpcode::MulNV,
+    LuaJitOpcode::DivNV,
+    LuaJitOpcode::ModNV,
+    LuaJitOpcode::AddVV,
+    LuaJitOpcode::SubVV,
+    LuaJitOpcode::MulVV,
+    LuaJitOpcode::DivVV,
+    LuaJitOpcode::ModVV,
+    LuaJitOpcode::Pow,
+    LuaJitOpcode::Cat,
+    LuaJitOpcode::KStr,
+    LuaJitOpcode::KCData,
+    LuaJitOpcode::KShort,
+    LuaJitOpcode::KNum,
+    LuaJitOpcode::KPri,
+    LuaJitOpcode::KNil,
+    LuaJitOpcode::UGet,
+    LuaJitOpcode::USetV,
+    LuaJitOpcode::USetS,
+    LuaJitOpcode::USetN,
+    LuaJitOpcode::USetP,
+    LuaJitOpcode::UClo,
+    LuaJitOpcode::FNew,
+    LuaJitOpcode::TNew,
+    LuaJitOpcode::TDup,
+    LuaJitOpcode::GGet,
+    LuaJitOpcode::GSet,
+    LuaJitOpcode::TGetV,
+    LuaJitOpcode::TGetS,
+    LuaJitOpcode::TGetB,
+    LuaJitOpcode::TSetV,
+    LuaJitOpcode::TSetS,
+    LuaJitOpcode::TSetB,
+    LuaJitOpcode::TSetM,
+    LuaJitOpcode::CallM,
+    LuaJitOpcode::Call,
+    LuaJitOpcode::CallMT,
+    LuaJitOpcode::CallT,
+    LuaJitOpcode::IterC,
+    LuaJitOpcode::IterN,
+    LuaJitOpcode::Varg,
+    LuaJitOpcode::IsNext,
+    LuaJitOpcode::RetM,
+    LuaJitOpcode::Ret,
+    LuaJitOpcode::Ret0,
+    LuaJitOpcode::Ret1,
+    LuaJitOpcode::ForI,
+    LuaJitOpcode::JForI,
+    LuaJitOpcode::ForL,
+    LuaJitOpcode::IForL,
+    LuaJitOpcode::JForL,
+    LuaJitOpcode::IterL,
+    LuaJitOpcode::IIterL,
+    LuaJitOpcode::JIterL,
+    LuaJitOpcode::Loop,
+    LuaJitOpcode::ILoop,
+    LuaJitOpcode::JLoop,
+    LuaJitOpcode::Jmp,
+    LuaJitOpcode::FuncF,
+    LuaJitOpcode::IFuncF,
+    LuaJitOpcode::JFuncF,
+    LuaJitOpcode::FuncV,
+    LuaJitOpcode::IFuncV,
+    LuaJitOpcode::JFuncV,
+    LuaJitOpcode::FuncC,
+    LuaJitOpcode::FuncCW,
+];
+
+#[cfg(feature = "luajit")]
+const LUAJIT21_OPCODES: [LuaJitOpcode; 97] = [
+    LuaJitOpcode::IsLt,
+    LuaJitOpcode::IsGe,
+    LuaJitOpcode::IsLe,
+    LuaJitOpcode::IsGt,
+    LuaJitOpcode::IsEqV,
+    LuaJitOpcode::IsNeV,
+    LuaJitOpcode::IsEqS,
+    LuaJitOpcode::IsNeS,
+    LuaJitOpcode::IsEqN,
+    LuaJitOpcode::IsNeN,
+    LuaJitOpcode::IsEqP,
+    LuaJitOpcode::IsNeP,
+    LuaJitOpcode::IsTc,
+    LuaJitOpcode::IsFc,
+    LuaJitOpcode::IsT,
+    LuaJitOpcode::IsF,
+    LuaJitOpcode::IsType,
+    LuaJitOpcode::IsNum,
+    LuaJitOpcode::Mov,
+    LuaJitOpcode::Not,
+    LuaJitOpcode::Unm,
+    LuaJitOpcode::Len,
+    LuaJitOpcode::AddVN,
+    LuaJitOpcode::SubVN,
+    LuaJitOpcode::MulVN,
+    LuaJitOpcode::DivVN,
+    LuaJitOpcode::ModVN,
+    LuaJitOpcode::AddNV,
+    LuaJitOpcode::SubNV,
+    LuaJitOpcode::MulNV,
+    LuaJitOpcode::DivNV,
+    LuaJitOpcode::ModNV,
+    LuaJitOpcode::AddVV,
+    LuaJitOpcode::SubVV,
+    LuaJitOpcode::MulVV,
+    LuaJitOpcode::DivVV,
+    LuaJitOpcode::ModVV,
+    LuaJitOpcode::Pow,
+    LuaJitOpcode::Cat,
+    LuaJitOpcode::KStr,
+    LuaJitOpcode::KCData,
+    LuaJitOpcode::KShort,
+    LuaJitOpcode::KNum,
+    LuaJitOpcode::KPri,
+    LuaJitOpcode::KNil,
+    LuaJitOpcode::UGet,
+    LuaJitOpcode::USetV,
+    LuaJitOpcode::USetS,
+    LuaJitOpcode::USetN,
+    LuaJitOpcode::USetP,
+    LuaJitOpcode::UClo,
+    LuaJitOpcode::FNew,
+    LuaJitOpcode::TNew,
+    LuaJitOpcode::TDup,
+    LuaJitOpcode::GGet,
+    LuaJitOpcode::GSet,
+    LuaJitOpcode::TGetV,
+    LuaJitOpcode::TGetS,
+    LuaJitOpcode::TGetB,
+    LuaJitOpcode::TGetR,
+    LuaJitOpcode::TSetV,
+    LuaJitOpcode::TSetS,
+    LuaJitOpcode::TSetB,
+    LuaJitOpcode::TSetM,
+    LuaJitOpcode::TSetR,
+    LuaJitOpcode::CallM,
+    LuaJitOpcode::Call,
+    LuaJitOpcode::CallMT,
+    LuaJitOpcode::CallT,
+    LuaJitOpcode::IterC,
+    LuaJitOpcode::IterN,
+    LuaJitOpcode::Varg,
+    LuaJitOpcode::IsNext,
+    LuaJitOpcode::RetM,
+    LuaJitOpcode::Ret,
+    LuaJitOpcode::Ret0,
+    LuaJitOpcode::Ret1,
+    LuaJitOpcode::ForI,
+    LuaJitOpcode::JForI,
+    LuaJitOpcode::ForL,
+    LuaJitOpcode::IForL,
+    LuaJitOpcode::JForL,
+    LuaJitOpcode::IterL,
+    LuaJitOpcode::IIterL,
+    LuaJitOpcode::JIterL,
+    LuaJitOpcode::Loop,
+    LuaJitOpcode::ILoop,
+    LuaJitOpcode::JLoop,
+    LuaJitOpcode::Jmp,
+    LuaJitOpcode::FuncF,
+    LuaJitOpcode::IFuncF,
+    LuaJitOpcode::JFuncF,
+    LuaJitOpcode::FuncV,
+    LuaJitOpcode::IFuncV,
+    LuaJitOpcode::JFuncV,
+    LuaJitOpcode::FuncC,
+    LuaJitOpcode::FuncCW,
+];
+
+#[cfg(feature = "luajit")]
+impl TryFrom<u8> for LuaJitOpcode {
+    type Error = u8;
+
+    fn try_from(op: u8) -> Result<Self, Self::Error> {
+        LUAJIT21_OPCODES.get(op as usize).copied().ok_or(op)
+    }
+}
+
+#[cfg(feature = "luajit")]
+impl LuaJitOpcode {
+    /// Decodes an opcode byte of a dump with the given version byte, 1 for 2.0 and 2 for 2.1.
+    pub fn from_version(op: u8, version: u8) -> Result<Self, u8> {
+        match version {
+            1 => LUAJIT20_OPCODES.get(op as usize).copied().ok_or(op),
+            _ => LuaJitOpcode::try_from(op),
+        }
+    }
+
+    /// Encodes the opcode for a dump with the given version byte, `None` if 2.0 lacks it.
+    pub fn to_version(self, version: u8) -> Option<u8> {
+        match version {
+            1 => LUAJIT20_OPCODES
+                .iter()
+                .position(|op| *op == self)
+                .map(|op| op as u8),
+            _ => Some(self as u8),
+        }
+    }
+}
+
+#[cfg(feature = "luau")]
+const LUAU_OPCODES: [LuauOpcode; 83] = [
+    LuauOpcode::Nop,
+    LuauOpcode::Break,
+    LuauOpcode::LoadNil,
+    LuauOpcode::LoadB,
+    LuauOpcode::LoadN,
+    LuauOpcode::LoadK,
+    LuauOpcode::Move,
+    LuauOpcode::GetGlobal,
+    LuauOpcode::SetGlobal,
+    LuauOpcode::GetUpval,
+    LuauOpcode::SetUpval,
+    LuauOpcode::CloseUpvals,
+    LuauOpcode::GetImport,
+    LuauOpcode::GetTable,
+    LuauOpcode::SetTable,
+    LuauOpcode::GetTableKs,
+    LuauOpcode::SetTableKs,
+    LuauOpcode::GetTableN,
+    LuauOpcode::SetTableN,
+    LuauOpcode::NewClosure,
+    LuauOpcode::NameCall,
+    LuauOpcode::Call,
+    LuauOpcode::Return,
+    LuauOpcode::Jump,
+    LuauOpcode::JumpBack,
+    LuauOpcode::JumpIf,
+    LuauOpcode::JumpIfNot,
+    LuauOpcode::JumpIfEq,
+    LuauOpcode::JumpIfLe,
+    LuauOpcode::JumpIfLt,
+    LuauOpcode::JumpIfNotEq,
+    LuauOpcode::JumpIfNotLe,
+    LuauOpcode::JumpIfNotLt,
+    LuauOpcode::Add,
+    LuauOpcode::Sub,
+    LuauOpcode::Mul,
+    LuauOpcode::Div,
+    LuauOpcode::Mod,
+    LuauOpcode::Pow,
+    LuauOpcode::AddK,
+    LuauOpcode::SubK,
+    LuauOpcode::MulK,
+    LuauOpcode::DivK,
+    LuauOpcode::ModK,
+    LuauOpcode::PowK,
+    LuauOpcode::And,
+    LuauOpcode::Or,
+    LuauOpcode::AndK,
+    LuauOpcode::OrK,
+    LuauOpcode::Concat,
+    LuauOpcode::Not,
+    LuauOpcode::Minus,
+    LuauOpcode::Length,
+    LuauOpcode::NewTable,
+    LuauOpcode::DupTable,
+    LuauOpcode::SetList,
+    LuauOpcode::ForNPrep,
+    LuauOpcode::ForNLoop,
+    LuauOpcode::ForGLoop,
+    LuauOpcode::ForGPrepInext,
+    LuauOpcode::FastCall3,
+    LuauOpcode::ForGPrepNext,
+    LuauOpcode::NativeCall,
+    LuauOpcode::GetVarargs,
+    LuauOpcode::DupClosure,
+    LuauOpcode::PrepVarargs,
+    LuauOpcode::LoadKx,
+    LuauOpcode::JumpX,
+    LuauOpcode::FastCall,
+    LuauOpcode::Coverage,
+    LuauOpcode::Capture,
+    LuauOpcode::SubRk,
+    LuauOpcode::DivRk,
+    LuauOpcode::FastCall1,
+    LuauOpcode::FastCall2,
+    LuauOpcode::FastCall2K,
+    LuauOpcode::ForGPrep,
+    LuauOpcode::JumpXeqkNil,
+    LuauOpcode::JumpXeqkB,
+    LuauOpcode::JumpXeqkN,
+    LuauOpcode::JumpXeqkS,
+    LuauOpcode::IDiv,
+    LuauOpcode::IDivK,
+];
+
+#[cfg(feature = "luau")]
+impl TryFrom<u8> for LuauOpcode {
+    type Error = u8;
+
+    fn try_from(op: u8) -> Result<Self, Self::Error> {
+        LUAU_OPCODES.get(op as usize).copied().ok_or(op)
+    }
+}
+
 #[cfg(feature = "lua51")]
 impl LuaOpcode {
+    #[deprecated(note = "panics on unknown opcodes, use `LuaOpcode::try_from` or `try_opcode`")]
     pub fn index(op: u8) -> LuaOpcode {
-        unsafe { std::mem::transmute(op) }
+        LuaOpcode::try_from(op).expect("invalid lua opcode")
+    }
+
+    /// The name `luac -l` prints.
+    pub fn name(&self) -> &'static str {
+        match self {
+            LuaOpcode::Move => "MOVE",
+            LuaOpcode::LoadK => "LOADK",
+            LuaOpcode::LoadBool => "LOADBOOL",
+            LuaOpcode::LoadNil => "LOADNIL",
+            LuaOpcode::GetUpval => "GETUPVAL",
+            LuaOpcode::GetGlobal => "GETGLOBAL",
+            LuaOpcode::GetTable => "GETTABLE",
+            LuaOpcode::SetGlobal => "SETGLOBAL",
+            LuaOpcode::SetUpval => "SETUPVAL",
+            LuaOpcode::SetTable => "SETTABLE",
+            LuaOpcode::NewTable => "NEWTABLE",
+            LuaOpcode::Self_ => "SELF",
+            LuaOpcode::Add => "ADD",
+            LuaOpcode::Sub => "SUB",
+            LuaOpcode::Mul => "MUL",
+            LuaOpcode::Div => "DIV",
+            LuaOpcode::Mod => "MOD",
+            LuaOpcode::Pow => "POW",
+            LuaOpcode::Unm => "UNM",
+            LuaOpcode::Not => "NOT",
+            LuaOpcode::Len => "LEN",
+            LuaOpcode::Concat => "CONCAT",
+            LuaOpcode::Jmp => "JMP",
+            LuaOpcode::Eq => "EQ",
+            LuaOpcode::Lt => "LT",
+            LuaOpcode::Le => "LE",
+            LuaOpcode::Test => "TEST",
+            LuaOpcode::TestSet => "TESTSET",
+            LuaOpcode::Call => "CALL",
+            LuaOpcode::TailCall => "TAILCALL",
+            LuaOpcode::Return => "RETURN",
+            LuaOpcode::ForLoop => "FORLOOP",
+            LuaOpcode::ForPrep => "FORPREP",
+            LuaOpcode::TForLoop => "TFORLOOP",
+            LuaOpcode::SetList => "SETLIST",
+            LuaOpcode::Close => "CLOSE",
+            LuaOpcode::Closure => "CLOSURE",
+            LuaOpcode::Vararg => "VARARG",
+        }
     }
 
     pub fn mode(&self) -> LuaOpMode {
@@ -212,20 +1429,18 @@ impl LuaOpcode {
             LuaOpcode::Unm | LuaOpcode::Not | LuaOpcode::Len => LuaOpMode::IAB,
 
             LuaOpcode::Concat => LuaOpMode::IABC,
-            LuaOpcode::Jmp => LuaOpMode::IAsBx, // sBx?
+            LuaOpcode::Jmp => LuaOpMode::IAsBx,
 
             LuaOpcode::Eq | LuaOpcode::Lt | LuaOpcode::Le => LuaOpMode::IABC,
 
-            LuaOpcode::Test => LuaOpMode::IAC,
-            LuaOpcode::TestSet => LuaOpMode::IABC,
+            LuaOpcode::Test | LuaOpcode::TestSet => LuaOpMode::IABC,
 
             LuaOpcode::Call | LuaOpcode::TailCall => LuaOpMode::IABC,
             LuaOpcode::Return => LuaOpMode::IAB,
 
             LuaOpcode::ForLoop => LuaOpMode::IAsBx,
             LuaOpcode::ForPrep => LuaOpMode::IAsBx,
-            LuaOpcode::TForLoop => LuaOpMode::IAC,
-            LuaOpcode::SetList => LuaOpMode::IABC,
+            LuaOpcode::TForLoop | LuaOpcode::SetList => LuaOpMode::IABC,
 
             LuaOpcode::Close => LuaOpMode::IA,
             LuaOpcode::Closure => LuaOpMode::IABx,
@@ -233,39 +1448,300 @@ impl LuaOpcode {
             LuaOpcode::Vararg => LuaOpMode::IAB,
         }
     }
+
+    pub fn info(&self) -> LuaOpInfo {
+        use LuaOpArgMode::{K, N, R, U};
+
+        let (test, sets_a, b, c) = match self {
+            LuaOpcode::Move => (false, true, R, N),
+            LuaOpcode::LoadK => (false, true, K, N),
+            LuaOpcode::LoadBool => (false, true, U, U),
+            LuaOpcode::LoadNil => (false, true, R, N),
+            LuaOpcode::GetUpval => (false, true, U, N),
+            LuaOpcode::GetGlobal => (false, true, K, N),
+            LuaOpcode::GetTable => (false, true, R, K),
+            LuaOpcode::SetGlobal => (false, false, K, N),
+            LuaOpcode::SetUpval => (false, false, U, N),
+            LuaOpcode::SetTable => (false, false, K, K),
+            LuaOpcode::NewTable => (false, true, U, U),
+            LuaOpcode::Self_ => (false, true, R, K),
+
+            LuaOpcode::Add
+            | LuaOpcode::Sub
+            | LuaOpcode::Mul
+            | LuaOpcode::Div
+            | LuaOpcode::Mod
+            | LuaOpcode::Pow => (false, true, K, K),
+
+            LuaOpcode::Unm | LuaOpcode::Not | LuaOpcode::Len => (false, true, R, N),
+            LuaOpcode::Concat => (false, true, R, R),
+            LuaOpcode::Jmp => (false, false, R, N),
+
+            LuaOpcode::Eq | LuaOpcode::Lt | LuaOpcode::Le => (true, false, K, K),
+            LuaOpcode::Test | LuaOpcode::TestSet => (true, true, R, U),
+
+            LuaOpcode::Call | LuaOpcode::TailCall => (false, true, U, U),
+            LuaOpcode::Return => (false, false, U, N),
+
+            LuaOpcode::ForLoop | LuaOpcode::ForPrep => (false, true, R, N),
+            LuaOpcode::TForLoop => (true, false, N, U),
+            LuaOpcode::SetList => (false, false, U, U),
+
+            LuaOpcode::Close => (false, false, N, N),
+            LuaOpcode::Closure => (false, true, U, N),
+            LuaOpcode::Vararg => (false, true, U, N),
+        };
+
+        LuaOpInfo {
+            test,
+            sets_a,
+            b,
+            c,
+            mode: self.mode(),
+        }
+    }
+}
+
+#[cfg(feature = "lua52")]
+impl Lua52Opcode {
+    pub fn mode(&self) -> LuaOpMode {
+        match self {
+            Lua52Opcode::Move => LuaOpMode::IAB,
+            Lua52Opcode::LoadK => LuaOpMode::IABx,
+            Lua52Opcode::LoadKx => LuaOpMode::IA,
+            Lua52Opcode::LoadBool => LuaOpMode::IABC,
+            Lua52Opcode::LoadNil => LuaOpMode::IAB,
+
+            Lua52Opcode::GetUpval | Lua52Opcode::SetUpval => LuaOpMode::IAB,
+            Lua52Opcode::GetTabUp | Lua52Opcode::SetTabUp => LuaOpMode::IABC,
+            Lua52Opcode::GetTable | Lua52Opcode::SetTable | Lua52Opcode::NewTable => {
+                LuaOpMode::IABC
+            }
+            Lua52Opcode::Self_ => LuaOpMode::IABC,
+
+            Lua52Opcode::Add
+            | Lua52Opcode::Sub
+            | Lua52Opcode::Mul
+            | Lua52Opcode::Div
+            | Lua52Opcode::Mod
+            | Lua52Opcode::Pow => LuaOpMode::IABC,
+
+            Lua52Opcode::Unm | Lua52Opcode::Not | Lua52Opcode::Len => LuaOpMode::IAB,
+
+            Lua52Opcode::Concat => LuaOpMode::IABC,
+            Lua52Opcode::Jmp => LuaOpMode::IAsBx,
+
+            Lua52Opcode::Eq | Lua52Opcode::Lt | Lua52Opcode::Le => LuaOpMode::IABC,
+
+            Lua52Opcode::Test => LuaOpMode::IAC,
+            Lua52Opcode::TestSet => LuaOpMode::IABC,
+
+            Lua52Opcode::Call | Lua52Opcode::TailCall => LuaOpMode::IABC,
+            Lua52Opcode::Return => LuaOpMode::IAB,
+
+            Lua52Opcode::ForLoop | Lua52Opcode::ForPrep => LuaOpMode::IAsBx,
+            Lua52Opcode::TForCall => LuaOpMode::IAC,
+            Lua52Opcode::TForLoop => LuaOpMode::IAsBx,
+            Lua52Opcode::SetList => LuaOpMode::IABC,
+
+            Lua52Opcode::Closure => LuaOpMode::IABx,
+            Lua52Opcode::Vararg => LuaOpMode::IAB,
+            Lua52Opcode::ExtraArg => LuaOpMode::IAx,
+        }
+    }
+}
+
+#[cfg(feature = "lua53")]
+impl Lua53Opcode {
+    pub fn mode(&self) -> LuaOpMode {
+        match self {
+            Lua53Opcode::Move => LuaOpMode::IAB,
+            Lua53Opcode::LoadK => LuaOpMode::IABx,
+            Lua53Opcode::LoadKx => LuaOpMode::IA,
+            Lua53Opcode::LoadBool => LuaOpMode::IABC,
+            Lua53Opcode::LoadNil => LuaOpMode::IAB,
+
+            Lua53Opcode::GetUpval | Lua53Opcode::SetUpval => LuaOpMode::IAB,
+            Lua53Opcode::GetTabUp | Lua53Opcode::SetTabUp => LuaOpMode::IABC,
+            Lua53Opcode::GetTable | Lua53Opcode::SetTable | Lua53Opcode::NewTable => {
+                LuaOpMode::IABC
+            }
+            Lua53Opcode::Self_ => LuaOpMode::IABC,
+
+            Lua53Opcode::Add
+            | Lua53Opcode::Sub
+            | Lua53Opcode::Mul
+            | Lua53Opcode::Mod
+            | Lua53Opcode::Pow
+            | Lua53Opcode::Div
+            | Lua53Opcode::IDiv
+            | Lua53Opcode::BAnd
+            | Lua53Opcode::BOr
+            | Lua53Opcode::BXor
+            | Lua53Opcode::Shl
+            | Lua53Opcode::Shr => LuaOpMode::IABC,
+
+            Lua53Opcode::Unm | Lua53Opcode::BNot | Lua53Opcode::Not | Lua53Opcode::Len => {
+                LuaOpMode::IAB
+            }
+
+            Lua53Opcode::Concat => LuaOpMode::IABC,
+            Lua53Opcode::Jmp => LuaOpMode::IAsBx,
+
+            Lua53Opcode::Eq | Lua53Opcode::Lt | Lua53Opcode::Le => LuaOpMode::IABC,
+
+            Lua53Opcode::Test => LuaOpMode::IAC,
+            Lua53Opcode::TestSet => LuaOpMode::IABC,
+
+            Lua53Opcode::Call | Lua53Opcode::TailCall => LuaOpMode::IABC,
+            Lua53Opcode::Return => LuaOpMode::IAB,
+
+            Lua53Opcode::ForLoop | Lua53Opcode::ForPrep => LuaOpMode::IAsBx,
+            Lua53Opcode::TForCall => LuaOpMode::IAC,
+            Lua53Opcode::TForLoop => LuaOpMode::IAsBx,
+            Lua53Opcode::SetList => LuaOpMode::IABC,
+
+            Lua53Opcode::Closure => LuaOpMode::IABx,
+            Lua53Opcode::Vararg => LuaOpMode::IAB,
+            Lua53Opcode::ExtraArg => LuaOpMode::IAx,
+        }
+    }
+}
+
+#[cfg(feature = "lua54")]
+impl Lua54Opcode {
+    pub fn mode(&self) -> Lua54OpMode {
+        match self {
+            Lua54Opcode::LoadI | Lua54Opcode::LoadF => Lua54OpMode::IAsBx,
+            Lua54Opcode::LoadK | Lua54Opcode::LoadKx => Lua54OpMode::IABx,
+
+            Lua54Opcode::Jmp => Lua54OpMode::IsJ,
+
+            Lua54Opcode::ForLoop
+            | Lua54Opcode::ForPrep
+            | Lua54Opcode::TForPrep
+            | Lua54Opcode::TForLoop => Lua54OpMode::IABx,
+
+            Lua54Opcode::Closure => Lua54OpMode::IABx,
+            Lua54Opcode::ExtraArg => Lua54OpMode::IAx,
+
+            _ => Lua54OpMode::IABC,
+        }
+    }
 }
 
 #[cfg(feature = "luau")]
 impl LuauOpcode {
+    #[deprecated(note = "panics on unknown opcodes, use `LuauOpcode::try_from` or `try_opcode`")]
     pub fn index(op: u8) -> LuauOpcode {
-        unsafe { std::mem::transmute(op) }
+        LuauOpcode::try_from(op).expect("invalid luau opcode")
     }
 
     pub fn length(&self) -> u8 {
+        1 + self.info().has_aux() as u8
+    }
+
+    pub fn mode(&self) -> LuauOpMode {
+        self.info().mode
+    }
+
+    pub fn info(&self) -> LuauOpInfo {
+        use LuauOperand::{
+            Builtin as F, Constant as K, Immediate as I, Import, Jump as J, None as N, Proto as P,
+            Register as R, Upvalue as U,
+        };
+
         match self {
-            LuauOpcode::GetGlobal
-            | LuauOpcode::SetGlobal
-            | LuauOpcode::GetImport
-            | LuauOpcode::GetTableKs
-            | LuauOpcode::SetTableKs
-            | LuauOpcode::NameCall
-            | LuauOpcode::JumpIfEq
-            | LuauOpcode::JumpIfLt
-            | LuauOpcode::JumpIfLe
-            | LuauOpcode::JumpIfNotEq
-            | LuauOpcode::JumpIfNotLt
-            | LuauOpcode::JumpIfNotLe
-            | LuauOpcode::NewTable
-            | LuauOpcode::SetList
-            | LuauOpcode::ForGLoop
-            | LuauOpcode::FastCall3
-            | LuauOpcode::LoadKx
-            | LuauOpcode::FastCall2
-            | LuauOpcode::FastCall2K
-            | LuauOpcode::JumpXeqkB
-            | LuauOpcode::JumpXeqkN
-            | LuauOpcode::JumpXeqkS => 2,
-            _ => 1,
+            LuauOpcode::Nop => LuauOpInfo::abc("NOP", N, N, N),
+            LuauOpcode::Break => LuauOpInfo::abc("BREAK", N, N, N),
+            LuauOpcode::LoadNil => LuauOpInfo::abc("LOADNIL", R, N, N),
+            LuauOpcode::LoadB => LuauOpInfo::abc("LOADB", R, I, J).branch(),
+            LuauOpcode::LoadN => LuauOpInfo::ad("LOADN", R, I),
+            LuauOpcode::LoadK => LuauOpInfo::ad("LOADK", R, K),
+            LuauOpcode::Move => LuauOpInfo::abc("MOVE", R, R, N),
+            LuauOpcode::GetGlobal => LuauOpInfo::abc("GETGLOBAL", R, N, I).aux(K),
+            LuauOpcode::SetGlobal => LuauOpInfo::abc("SETGLOBAL", R, N, I).aux(K),
+            LuauOpcode::GetUpval => LuauOpInfo::abc("GETUPVAL", R, U, N),
+            LuauOpcode::SetUpval => LuauOpInfo::abc("SETUPVAL", R, U, N),
+            LuauOpcode::CloseUpvals => LuauOpInfo::abc("CLOSEUPVALS", R, N, N),
+            LuauOpcode::GetImport => LuauOpInfo::ad("GETIMPORT", R, K).aux(Import),
+            LuauOpcode::GetTable => LuauOpInfo::abc("GETTABLE", R, R, R),
+            LuauOpcode::SetTable => LuauOpInfo::abc("SETTABLE", R, R, R),
+            LuauOpcode::GetTableKs => LuauOpInfo::abc("GETTABLEKS", R, R, I).aux(K),
+            LuauOpcode::SetTableKs => LuauOpInfo::abc("SETTABLEKS", R, R, I).aux(K),
+            LuauOpcode::GetTableN => LuauOpInfo::abc("GETTABLEN", R, R, I),
+            LuauOpcode::SetTableN => LuauOpInfo::abc("SETTABLEN", R, R, I),
+            LuauOpcode::NewClosure => LuauOpInfo::ad("NEWCLOSURE", R, P),
+            LuauOpcode::NameCall => LuauOpInfo::abc("NAMECALL", R, R, I).aux(K),
+            LuauOpcode::Call => LuauOpInfo::abc("CALL", R, I, I).call(),
+            LuauOpcode::Return => LuauOpInfo {
+                returns: true,
+                ends_block: true,
+                ..LuauOpInfo::abc("RETURN", R, I, N)
+            },
+            LuauOpcode::Jump => LuauOpInfo::ad("JUMP", N, J).jump(),
+            LuauOpcode::JumpBack => LuauOpInfo::ad("JUMPBACK", N, J).jump(),
+            LuauOpcode::JumpIf => LuauOpInfo::ad("JUMPIF", R, J).branch(),
+            LuauOpcode::JumpIfNot => LuauOpInfo::ad("JUMPIFNOT", R, J).branch(),
+            LuauOpcode::JumpIfEq => LuauOpInfo::ad("JUMPIFEQ", R, J).aux(R).branch(),
+            LuauOpcode::JumpIfLe => LuauOpInfo::ad("JUMPIFLE", R, J).aux(R).branch(),
+            LuauOpcode::JumpIfLt => LuauOpInfo::ad("JUMPIFLT", R, J).aux(R).branch(),
+            LuauOpcode::JumpIfNotEq => LuauOpInfo::ad("JUMPIFNOTEQ", R, J).aux(R).branch(),
+            LuauOpcode::JumpIfNotLe => LuauOpInfo::ad("JUMPIFNOTLE", R, J).aux(R).branch(),
+            LuauOpcode::JumpIfNotLt => LuauOpInfo::ad("JUMPIFNOTLT", R, J).aux(R).branch(),
+            LuauOpcode::Add => LuauOpInfo::abc("ADD", R, R, R),
+            LuauOpcode::Sub => LuauOpInfo::abc("SUB", R, R, R),
+            LuauOpcode::Mul => LuauOpInfo::abc("MUL", R, R, R),
+            LuauOpcode::Div => LuauOpInfo::abc("DIV", R, R, R),
+            LuauOpcode::Mod => LuauOpInfo::abc("MOD", R, R, R),
+            LuauOpcode::Pow => LuauOpInfo::abc("POW", R, R, R),
+            LuauOpcode::AddK => LuauOpInfo::abc("ADDK", R, R, K),
+            LuauOpcode::SubK => LuauOpInfo::abc("SUBK", R, R, K),
+            LuauOpcode::MulK => LuauOpInfo::abc("MULK", R, R, K),
+            LuauOpcode::DivK => LuauOpInfo::abc("DIVK", R, R, K),
+            LuauOpcode::ModK => LuauOpInfo::abc("MODK", R, R, K),
+            LuauOpcode::PowK => LuauOpInfo::abc("POWK", R, R, K),
+            LuauOpcode::And => LuauOpInfo::abc("AND", R, R, R),
+            LuauOpcode::Or => LuauOpInfo::abc("OR", R, R, R),
+            LuauOpcode::AndK => LuauOpInfo::abc("ANDK", R, R, K),
+            LuauOpcode::OrK => LuauOpInfo::abc("ORK", R, R, K),
+            LuauOpcode::Concat => LuauOpInfo::abc("CONCAT", R, R, R),
+            LuauOpcode::Not => LuauOpInfo::abc("NOT", R, R, N),
+            LuauOpcode::Minus => LuauOpInfo::abc("MINUS", R, R, N),
+            LuauOpcode::Length => LuauOpInfo::abc("LENGTH", R, R, N),
+            LuauOpcode::NewTable => LuauOpInfo::abc("NEWTABLE", R, I, N).aux(I),
+            LuauOpcode::DupTable => LuauOpInfo::ad("DUPTABLE", R, K),
+            LuauOpcode::SetList => LuauOpInfo::abc("SETLIST", R, R, I).aux(I),
+            LuauOpcode::ForNPrep => LuauOpInfo::ad("FORNPREP", R, J).branch(),
+            LuauOpcode::ForNLoop => LuauOpInfo::ad("FORNLOOP", R, J).branch(),
+            LuauOpcode::ForGLoop => LuauOpInfo::ad("FORGLOOP", R, J).aux(I).branch(),
+            LuauOpcode::ForGPrepInext => LuauOpInfo::ad("FORGPREP_INEXT", R, J).jump(),
+            LuauOpcode::FastCall3 => LuauOpInfo::abc("FASTCALL3", F, R, J).aux(R).branch().call(),
+            LuauOpcode::ForGPrepNext => LuauOpInfo::ad("FORGPREP_NEXT", R, J).jump(),
+            LuauOpcode::NativeCall => LuauOpInfo::abc("NATIVECALL", N, N, N),
+            LuauOpcode::GetVarargs => LuauOpInfo::abc("GETVARARGS", R, I, N),
+            LuauOpcode::DupClosure => LuauOpInfo::ad("DUPCLOSURE", R, K),
+            LuauOpcode::PrepVarargs => LuauOpInfo::abc("PREPVARARGS", I, N, N),
+            LuauOpcode::LoadKx => LuauOpInfo::abc("LOADKX", R, N, N).aux(K),
+            LuauOpcode::JumpX => LuauOpInfo::e("JUMPX", J).jump(),
+            LuauOpcode::FastCall => LuauOpInfo::abc("FASTCALL", F, N, J).branch().call(),
+            LuauOpcode::Coverage => LuauOpInfo::e("COVERAGE", I),
+            LuauOpcode::Capture => LuauOpInfo::abc("CAPTURE", I, R, N),
+            LuauOpcode::SubRk => LuauOpInfo::abc("SUBRK", R, K, R),
+            LuauOpcode::DivRk => LuauOpInfo::abc("DIVRK", R, K, R),
+            LuauOpcode::FastCall1 => LuauOpInfo::abc("FASTCALL1", F, R, J).branch().call(),
+            LuauOpcode::FastCall2 => LuauOpInfo::abc("FASTCALL2", F, R, J).aux(R).branch().call(),
+            LuauOpcode::FastCall2K => LuauOpInfo::abc("FASTCALL2K", F, R, J)
+                .aux(K)
+                .branch()
+                .call(),
+            LuauOpcode::ForGPrep => LuauOpInfo::ad("FORGPREP", R, J).jump(),
+            LuauOpcode::JumpXeqkNil => LuauOpInfo::ad("JUMPXEQKNIL", R, J).aux(I).branch(),
+            LuauOpcode::JumpXeqkB => LuauOpInfo::ad("JUMPXEQKB", R, J).aux(I).branch(),
+            LuauOpcode::JumpXeqkN => LuauOpInfo::ad("JUMPXEQKN", R, J).aux(K).branch(),
+            LuauOpcode::JumpXeqkS => LuauOpInfo::ad("JUMPXEQKS", R, J).aux(K).branch(),
+            LuauOpcode::IDiv => LuauOpInfo::abc("IDIV", R, R, R),
+            LuauOpcode::IDivK => LuauOpInfo::abc("IDIVK", R, R, K),
         }
     }
 }
@@ -274,196 +1750,516 @@ impl LuauOpcode {
 pub enum Opcode {
     #[cfg(feature = "lua51")]
     LuaOpcode(LuaOpcode),
+    #[cfg(feature = "lua52")]
+    Lua52Opcode(Lua52Opcode),
+    #[cfg(feature = "lua53")]
+    Lua53Opcode(Lua53Opcode),
+    #[cfg(feature = "lua54")]
+    Lua54Opcode(Lua54Opcode),
+    #[cfg(feature = "luajit")]
+    LuaJitOpcode(LuaJitOpcode),
     #[cfg(feature = "luau")]
     LuauOpcode(LuauOpcode),
 }
 
+/// A raw instruction word, its operands are read through the view of its format, e.g.
+/// `instruction.luau().a()`.
 #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
 pub struct Instruction(pub u32);
 
-#[cfg(feature = "lua51")]
-pub trait LuaInstruction {
-    fn opcode(&self) -> Opcode;
-    fn from_abc(op: Opcode, a: u32, b: u32, c: u32) -> Self;
-    fn from_abx(opcode: Opcode, a: u32, bx: u32) -> Self;
-
-    fn get(&self, size: u32, position: u32) -> u32;
-    fn a(&self) -> u32;
-    fn b(&self) -> u32;
-    fn c(&self) -> u32;
-    fn bx(&self) -> u32;
-    fn sbx(&self) -> i32;
-
-    fn set(&mut self, value: u32, position: u32, size: u32) -> &mut Self;
-    fn set_a(&mut self, a: u32) -> &mut Self;
-    fn set_b(&mut self, b: u32) -> &mut Self;
-    fn set_c(&mut self, c: u32) -> &mut Self;
-    fn set_bx(&mut self, bx: u32) -> &mut Self;
-    fn set_sbx(&mut self, sbx: i32) -> &mut Self;
-
-    /* creates a mask with `n' 0 bits at position `p' */
-    fn mask_0(&self, n: u32, p: u32) -> u32;
-    /* creates a mask with `n' 1 bits at position `p' */
-    fn mask_1(&self, n: u32, p: u32) -> u32;
+impl Instruction {
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Instruction(u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
 }
 
-#[cfg(feature = "luau")]
-pub trait LuauInstruction {
-    fn opcode(&self) -> Opcode;
+// every format gets its own view of the word, so enabling more features never makes a
+// method call ambiguous
+macro_rules! instruction_view {
+    ($($(#[doc = $doc:literal])* #[cfg($cfg:meta)] $view:ident $accessor:ident;)*) => {
+        $(
+            $(#[doc = $doc])*
+            #[cfg($cfg)]
+            #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+            pub struct $view(pub u32);
+
+            #[cfg($cfg)]
+            impl From<Instruction> for $view {
+                fn from(instruction: Instruction) -> Self {
+                    $view(instruction.0)
+                }
+            }
+
+            #[cfg($cfg)]
+            impl From<$view> for Instruction {
+                fn from(instruction: $view) -> Self {
+                    Instruction(instruction.0)
+                }
+            }
+
+            #[cfg($cfg)]
+            impl Instruction {
+                pub fn $accessor(self) -> $view {
+                    $view(self.0)
+                }
+            }
+        )*
+    };
+}
+
+instruction_view! {
+    /// A lua 5.1 instruction.
+    #[cfg(feature = "lua51")]
+    LuaInstruction lua51;
+    /// A lua 5.2 instruction, the operands sit where they do in 5.1.
+    #[cfg(feature = "lua52")]
+    Lua52Instruction lua52;
+    /// A lua 5.3 instruction, the operands sit where they do in 5.1.
+    #[cfg(feature = "lua53")]
+    Lua53Instruction lua53;
+    #[cfg(feature = "lua54")]
+    Lua54Instruction lua54;
+    /// A LuaJIT instruction, its opcode byte depends on the dump version.
+    #[cfg(feature = "luajit")]
+    LuaJitInstruction luajit;
+    #[cfg(feature = "luau")]
+    LuauInstruction luau;
+}
 
-    fn from_abc(op: Opcode, a: u32, b: u32, c: u32) -> Self;
-    fn from_ad(opcode: Opcode, a: u32, d: u32) -> Self;
-    fn from_e(opcode: Opcode, e: u32) -> Self;
+#[cfg(any(feature = "lua51", feature = "lua52", feature = "lua53"))]
+fn lua_opcode(opcode: Opcode) -> u32 {
+    match opcode {
+        #[cfg(feature = "lua51")]
+        Opcode::LuaOpcode(op) => op as u32,
+        #[cfg(feature = "lua52")]
+        Opcode::Lua52Opcode(op) => op as u32,
+        #[cfg(feature = "lua53")]
+        Opcode::Lua53Opcode(op) => op as u32,
+        #[allow(unreachable_patterns)]
+        _ => unreachable!(),
+    }
+}
 
-    fn a(&self) -> u32;
-    fn b(&self) -> u32;
-    fn c(&self) -> u32;
-    fn d(&self) -> u32;
-    fn e(&self) -> u32;
+// 5.1 to 5.3 share the layout, only the opcode numbering differs
+macro_rules! lua_instruction {
+    ($($(#[$attribute:meta])* $view:ident $opcode:ident $name:literal;)*) => {
+        $(
+            $(#[$attribute])*
+            impl $view {
+                /// Panics on an unknown opcode, untrusted words go through `try_opcode`.
+                pub fn opcode(&self) -> Opcode {
+                    let op = self.get(LUA_OP_SIZE, LUA_OP_POSITION) as u8;
+                    Opcode::$opcode($opcode::try_from(op).expect(concat!("invalid ", $name, " opcode")))
+                }
+
+                pub fn try_opcode(&self) -> Result<Opcode, u8> {
+                    let op = self.get(LUA_OP_SIZE, LUA_OP_POSITION) as u8;
+                    $opcode::try_from(op).map(Opcode::$opcode)
+                }
+
+                pub fn from_abc(opcode: Opcode, a: u32, b: u32, c: u32) -> Self {
+                    let mut instruction = $view(lua_opcode(opcode));
+                    instruction.set_a(a).set_b(b).set_c(c);
+                    instruction
+                }
+
+                pub fn from_abx(opcode: Opcode, a: u32, bx: u32) -> Self {
+                    let mut instruction = $view(lua_opcode(opcode));
+                    instruction.set_a(a).set_bx(bx);
+                    instruction
+                }
+
+                pub fn a(&self) -> u32 {
+                    self.get(LUA_A_SIZE, LUA_A_POSITION)
+                }
+
+                pub fn b(&self) -> u32 {
+                    self.get(LUA_B_SIZE, LUA_B_POSITION)
+                }
+
+                pub fn c(&self) -> u32 {
+                    self.get(LUA_C_SIZE, LUA_C_POSITION)
+                }
+
+                pub fn bx(&self) -> u32 {
+                    self.get(LUA_BX_SIZE, LUA_BX_POSITION)
+                }
+
+                pub fn sbx(&self) -> i32 {
+                    (self.bx() as i32) - MAX_ARG_SBX
+                }
+
+                pub fn set_a(&mut self, a: u32) -> &mut Self {
+                    self.set(a, LUA_A_SIZE, LUA_A_POSITION)
+                }
+
+                pub fn set_b(&mut self, b: u32) -> &mut Self {
+                    self.set(b, LUA_B_SIZE, LUA_B_POSITION)
+                }
+
+                pub fn set_c(&mut self, c: u32) -> &mut Self {
+                    self.set(c, LUA_C_SIZE, LUA_C_POSITION)
+                }
+
+                pub fn set_bx(&mut self, bx: u32) -> &mut Self {
+                    self.set(bx, LUA_BX_SIZE, LUA_BX_POSITION)
+                }
+
+                pub fn set_sbx(&mut self, sbx: i32) -> &mut Self {
+                    self.set_bx((sbx + MAX_ARG_SBX) as u32)
+                }
+
+                fn get(&self, size: u32, position: u32) -> u32 {
+                    (self.0 >> position) & mask_1(size, 0)
+                }
+
+                fn set(&mut self, value: u32, size: u32, position: u32) -> &mut Self {
+                    self.0 = (mask_1(size, position) & (value << position))
+                        | (self.0 & mask_0(size, position));
+
+                    self
+                }
+            }
+        )*
+    };
 }
 
-#[cfg(feature = "lua51")]
-impl LuaInstruction for Instruction {
-    fn opcode(&self) -> Opcode {
-        let op = ((self.0 >> LUA_OP_POSITION) as u8) & self.mask_1(LUA_OP_SIZE, 0) as u8;
-        Opcode::LuaOpcode(LuaOpcode::index(op))
+lua_instruction! {
+    #[cfg(feature = "lua51")]
+    LuaInstruction LuaOpcode "lua";
+    #[cfg(feature = "lua52")]
+    Lua52Instruction Lua52Opcode "lua 5.2";
+    #[cfg(feature = "lua53")]
+    Lua53Instruction Lua53Opcode "lua 5.3";
+}
+
+// the Ax operand is new in 5.2
+macro_rules! lua_instruction_ax {
+    ($($(#[$attribute:meta])* $view:ident;)*) => {
+        $(
+            $(#[$attribute])*
+            impl $view {
+                pub fn from_ax(opcode: Opcode, ax: u32) -> Self {
+                    let mut instruction = $view(lua_opcode(opcode));
+                    instruction.set_ax(ax);
+                    instruction
+                }
+
+                pub fn ax(&self) -> u32 {
+                    self.get(LUA_AX_SIZE, LUA_AX_POSITION)
+                }
+
+                pub fn set_ax(&mut self, ax: u32) -> &mut Self {
+                    self.set(ax, LUA_AX_SIZE, LUA_AX_POSITION)
+                }
+            }
+        )*
+    };
+}
+
+lua_instruction_ax! {
+    #[cfg(feature = "lua52")]
+    Lua52Instruction;
+    #[cfg(feature = "lua53")]
+    Lua53Instruction;
+}
+
+/* creates a mask with `n' 1 bits at position `p' */
+#[cfg(any(feature = "lua51", feature = "lua52", feature = "lua53"))]
+fn mask_1(n: u32, p: u32) -> u32 {
+    ((1u32 << n) - 1) << p
+}
+
+/* creates a mask with `n' 0 bits at position `p' */
+#[cfg(any(feature = "lua51", feature = "lua52", feature = "lua53"))]
+fn mask_0(n: u32, p: u32) -> u32 {
+    !mask_1(n, p)
+}
+
+#[cfg(feature = "lua54")]
+impl Lua54Instruction {
+    /// Panics on an unknown opcode, untrusted words go through `try_opcode`.
+    pub fn opcode(&self) -> Opcode {
+        let op = self.get(LUA54_OP_SIZE, 0) as u8;
+        Opcode::Lua54Opcode(Lua54Opcode::try_from(op).expect("invalid lua 5.4 opcode"))
     }
 
-    fn from_abc(opcode: Opcode, a: u32, b: u32, c: u32) -> Self {
-        let mut instruction = match opcode {
-            Opcode::LuaOpcode(op) => Instruction(op as u32),
-            _ => unreachable!(),
-        };
+    pub fn try_opcode(&self) -> Result<Opcode, u8> {
+        let op = self.get(LUA54_OP_SIZE, 0) as u8;
+        Lua54Opcode::try_from(op).map(Opcode::Lua54Opcode)
+    }
 
-        Instruction(instruction.set_a(a).set_b(b).set_c(c).0)
+    pub fn from_abck(opcode: Opcode, a: u32, b: u32, c: u32, k: bool) -> Self {
+        let mut instruction = Lua54Instruction(lua54_opcode(opcode));
+        instruction.set_a(a).set_b(b).set_c(c).set_k(k);
+        instruction
     }
 
-    fn from_abx(opcode: Opcode, a: u32, bx: u32) -> Self {
-        let mut instruction = match opcode {
-            Opcode::LuaOpcode(op) => Instruction(op as u32),
-            _ => unreachable!(),
-        };
+    pub fn from_abx(opcode: Opcode, a: u32, bx: u32) -> Self {
+        let mut instruction = Lua54Instruction(lua54_opcode(opcode));
+        instruction.set_a(a).set_bx(bx);
+        instruction
+    }
+
+    pub fn from_asbx(opcode: Opcode, a: u32, sbx: i32) -> Self {
+        let mut instruction = Lua54Instruction(lua54_opcode(opcode));
+        instruction.set_a(a).set_sbx(sbx);
+        instruction
+    }
+
+    pub fn from_ax(opcode: Opcode, ax: u32) -> Self {
+        let mut instruction = Lua54Instruction(lua54_opcode(opcode));
+        instruction.set_ax(ax);
+        instruction
+    }
+
+    pub fn from_sj(opcode: Opcode, sj: i32) -> Self {
+        let mut instruction = Lua54Instruction(lua54_opcode(opcode));
+        instruction.set_sj(sj);
+        instruction
+    }
+
+    pub fn a(&self) -> u32 {
+        self.get(LUA54_A_SIZE, LUA54_A_POSITION)
+    }
+
+    pub fn b(&self) -> u32 {
+        self.get(LUA54_B_SIZE, LUA54_B_POSITION)
+    }
+
+    pub fn c(&self) -> u32 {
+        self.get(LUA54_C_SIZE, LUA54_C_POSITION)
+    }
+
+    pub fn k(&self) -> bool {
+        self.get(1, LUA54_K_POSITION) != 0
+    }
+
+    pub fn sb(&self) -> i32 {
+        self.b() as i32 - LUA54_OFFSET_SC
+    }
+
+    pub fn sc(&self) -> i32 {
+        self.c() as i32 - LUA54_OFFSET_SC
+    }
+
+    pub fn bx(&self) -> u32 {
+        self.get(LUA54_BX_SIZE, LUA54_BX_POSITION)
+    }
 
-        Instruction(instruction.set_a(a).set_bx(bx).0)
+    pub fn sbx(&self) -> i32 {
+        self.bx() as i32 - LUA54_OFFSET_SBX
     }
 
-    fn a(&self) -> u32 {
-        self.get(LUA_A_SIZE, LUA_A_POSITION)
+    pub fn ax(&self) -> u32 {
+        self.get(LUA54_AX_SIZE, LUA54_AX_POSITION)
     }
 
-    fn b(&self) -> u32 {
-        self.get(LUA_B_SIZE, LUA_B_POSITION)
+    pub fn sj(&self) -> i32 {
+        self.get(LUA54_SJ_SIZE, LUA54_SJ_POSITION) as i32 - LUA54_OFFSET_SJ
     }
 
-    fn c(&self) -> u32 {
-        self.get(LUA_C_SIZE, LUA_C_POSITION)
+    pub fn set_a(&mut self, a: u32) -> &mut Self {
+        self.set(a, LUA54_A_SIZE, LUA54_A_POSITION)
     }
 
-    fn bx(&self) -> u32 {
-        self.get(LUA_BX_SIZE, LUA_BX_POSITION)
+    pub fn set_b(&mut self, b: u32) -> &mut Self {
+        self.set(b, LUA54_B_SIZE, LUA54_B_POSITION)
     }
 
-    fn sbx(&self) -> i32 {
-        (self.bx() as i32) - MAX_ARG_SBX
+    pub fn set_c(&mut self, c: u32) -> &mut Self {
+        self.set(c, LUA54_C_SIZE, LUA54_C_POSITION)
     }
 
-    fn set_a(&mut self, a: u32) -> &mut Self {
-        self.set(a, LUA_A_SIZE, LUA_A_POSITION)
+    pub fn set_k(&mut self, k: bool) -> &mut Self {
+        self.set(k as u32, 1, LUA54_K_POSITION)
     }
 
-    fn set_b(&mut self, b: u32) -> &mut Self {
-        self.set(b, LUA_B_SIZE, LUA_B_POSITION)
+    pub fn set_bx(&mut self, bx: u32) -> &mut Self {
+        self.set(bx, LUA54_BX_SIZE, LUA54_BX_POSITION)
     }
 
-    fn set_c(&mut self, c: u32) -> &mut Self {
-        self.set(c, LUA_C_SIZE, LUA_C_POSITION)
+    pub fn set_sbx(&mut self, sbx: i32) -> &mut Self {
+        self.set(
+            (sbx + LUA54_OFFSET_SBX) as u32,
+            LUA54_BX_SIZE,
+            LUA54_BX_POSITION,
+        )
     }
 
-    fn set_bx(&mut self, bx: u32) -> &mut Self {
-        self.set(bx, LUA_BX_SIZE, LUA_BX_POSITION)
+    pub fn set_ax(&mut self, ax: u32) -> &mut Self {
+        self.set(ax, LUA54_AX_SIZE, LUA54_AX_POSITION)
     }
 
-    fn set_sbx(&mut self, bx: i32) -> &mut Self {
-        self.set_bx((bx + MAX_ARG_SBX) as u32)
+    pub fn set_sj(&mut self, sj: i32) -> &mut Self {
+        self.set(
+            (sj + LUA54_OFFSET_SJ) as u32,
+            LUA54_SJ_SIZE,
+            LUA54_SJ_POSITION,
+        )
     }
 
     fn get(&self, size: u32, position: u32) -> u32 {
-        (self.0 >> position) & self.mask_1(size, 0)
+        (self.0 >> position) & ((1 << size) - 1)
     }
 
     fn set(&mut self, value: u32, size: u32, position: u32) -> &mut Self {
-        self.0 = (self.mask_1(size, position) & (value << position))
-            | (self.0 & self.mask_0(size, position));
-
+        let mask = ((1 << size) - 1) << position;
+        self.0 = (self.0 & !mask) | ((value << position) & mask);
         self
     }
+}
 
-    fn mask_1(&self, n: u32, p: u32) -> u32 {
-        ((1u32 << n) - 1) << p
+#[cfg(feature = "lua54")]
+fn lua54_opcode(opcode: Opcode) -> u32 {
+    match opcode {
+        Opcode::Lua54Opcode(op) => op as u32,
+        #[allow(unreachable_patterns)]
+        _ => unreachable!(),
     }
+}
 
-    fn mask_0(&self, n: u32, p: u32) -> u32 {
-        !self.mask_1(n, p)
+#[cfg(feature = "luajit")]
+fn luajit_opcode(opcode: Opcode, version: u8) -> Option<u32> {
+    match opcode {
+        Opcode::LuaJitOpcode(op) => op.to_version(version).map(u32::from),
+        #[allow(unreachable_patterns)]
+        _ => None,
     }
 }
 
-#[cfg(feature = "luau")]
-impl LuauInstruction for Instruction {
-    fn opcode(&self) -> Opcode {
+#[cfg(feature = "luajit")]
+impl LuaJitInstruction {
+    pub fn opcode(&self, version: u8) -> Opcode {
         let op = (self.0 & 0xff) as u8;
-        Opcode::LuauOpcode(LuauOpcode::index(op))
+        Opcode::LuaJitOpcode(
+            LuaJitOpcode::from_version(op, version).expect("invalid luajit opcode"),
+        )
     }
 
-    fn from_abc(opcode: Opcode, a: u32, b: u32, c: u32) -> Self {
-        let op = match opcode {
-            Opcode::LuauOpcode(op) => op as u32,
-            _ => unreachable!(),
-        };
+    pub fn try_opcode(&self, version: u8) -> Result<Opcode, u8> {
+        let op = (self.0 & 0xff) as u8;
+        LuaJitOpcode::from_version(op, version).map(Opcode::LuaJitOpcode)
+    }
 
-        Instruction((op) | (a << 8) | (b << 16) | (c << 24))
+    /// `None` when `opcode` is not a luajit opcode or the dump `version` lacks it.
+    pub fn from_abc(opcode: Opcode, version: u8, a: u32, b: u32, c: u32) -> Option<Self> {
+        // unlike luau, B is the top byte and C sits below it
+        let op = luajit_opcode(opcode, version)?;
+        Some(LuaJitInstruction(op | (a << 8) | (c << 16) | (b << 24)))
     }
 
-    fn from_ad(opcode: Opcode, a: u32, d: u32) -> Self {
-        let op = match opcode {
-            Opcode::LuauOpcode(op) => op as u32,
-            _ => unreachable!(),
-        };
+    /// `None` when `opcode` is not a luajit opcode or the dump `version` lacks it.
+    pub fn from_ad(opcode: Opcode, version: u8, a: u32, d: u32) -> Option<Self> {
+        let op = luajit_opcode(opcode, version)?;
+        Some(LuaJitInstruction(op | (a << 8) | (d << 16)))
+    }
 
-        Instruction((op) | (a << 8) | (d << 16))
+    pub fn a(&self) -> u32 {
+        (self.0 >> 8) & 0xff
     }
 
-    fn from_e(opcode: Opcode, e: u32) -> Self {
-        let op = match opcode {
-            Opcode::LuauOpcode(op) => op as u32,
-            _ => unreachable!(),
-        };
+    pub fn b(&self) -> u32 {
+        self.0 >> 24
+    }
+
+    pub fn c(&self) -> u32 {
+        (self.0 >> 16) & 0xff
+    }
+
+    pub fn d(&self) -> u32 {
+        self.0 >> 16
+    }
+}
+
+#[cfg(feature = "luau")]
+fn luau_opcode(opcode: Opcode) -> u32 {
+    match opcode {
+        Opcode::LuauOpcode(op) => op as u32,
+        #[allow(unreachable_patterns)]
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(feature = "luau")]
+impl LuauInstruction {
+    /// Panics on an unknown opcode, untrusted words go through `try_opcode`.
+    pub fn opcode(&self) -> Opcode {
+        let op = (self.0 & 0xff) as u8;
+        Opcode::LuauOpcode(LuauOpcode::try_from(op).expect("invalid luau opcode"))
+    }
+
+    pub fn try_opcode(&self) -> Result<Opcode, u8> {
+        let op = (self.0 & 0xff) as u8;
+        LuauOpcode::try_from(op).map(Opcode::LuauOpcode)
+    }
+
+    pub fn from_abc(opcode: Opcode, a: u32, b: u32, c: u32) -> Self {
+        LuauInstruction(luau_opcode(opcode) | (a << 8) | (b << 16) | (c << 24))
+    }
+
+    pub fn from_ad(opcode: Opcode, a: u32, d: i32) -> Self {
+        LuauInstruction(luau_opcode(opcode) | (a << 8) | (d as u32) << 16)
+    }
 
-        Instruction(op | e << 8)
+    pub fn from_e(opcode: Opcode, e: i32) -> Self {
+        LuauInstruction(luau_opcode(opcode) | (e as u32) << 8)
     }
 
-    fn a(&self) -> u32 {
+    pub fn a(&self) -> u32 {
         (self.0 >> 8) & 0xff
     }
 
-    fn b(&self) -> u32 {
+    pub fn b(&self) -> u32 {
         (self.0 >> 16) & 0xff
     }
 
-    fn c(&self) -> u32 {
+    pub fn c(&self) -> u32 {
         (self.0 >> 24) & 0xff
     }
 
-    fn d(&self) -> u32 {
-        LuauInstruction::b(self) | LuauInstruction::c(self)
+    /// Signed 16 bit operand in bits 16-31.
+    pub fn d(&self) -> i32 {
+        (self.0 as i32) >> 16
     }
 
-    fn e(&self) -> u32 {
-        LuauInstruction::a(self) | LuauInstruction::d(self)
+    /// Signed 24 bit operand in bits 8-31.
+    pub fn e(&self) -> i32 {
+        (self.0 as i32) >> 8
     }
-}
 
-impl Instruction {
-    pub fn from_bytes(bytes: &[u8]) -> Self {
-        Instruction(u32::from_le_bytes(bytes.try_into().unwrap()))
+    pub fn set_a(&mut self, a: u32) -> &mut Self {
+        self.0 = (self.0 & !0xff00) | (a & 0xff) << 8;
+        self
+    }
+
+    pub fn set_b(&mut self, b: u32) -> &mut Self {
+        self.0 = (self.0 & !0xff0000) | (b & 0xff) << 16;
+        self
+    }
+
+    pub fn set_c(&mut self, c: u32) -> &mut Self {
+        self.0 = (self.0 & !0xff000000) | (c & 0xff) << 24;
+        self
+    }
+
+    pub fn set_d(&mut self, d: i32) -> &mut Self {
+        self.0 = (self.0 & 0xffff) | (d as u32) << 16;
+        self
+    }
+
+    pub fn set_e(&mut self, e: i32) -> &mut Self {
+        self.0 = (self.0 & 0xff) | (e as u32) << 8;
+        self
+    }
+
+    /// The word following the instruction at `pc`, if its opcode takes one.
+    pub fn aux(instructions: &[Instruction], pc: usize) -> Option<u32> {
+        match instructions.get(pc)?.luau().try_opcode() {
+            Ok(Opcode::LuauOpcode(op)) if op.length() == 2 => {
+                instructions.get(pc + 1).map(|aux| aux.0)
+            }
+            _ => None,
+        }
     }
 }
@@ -0,0 +1,104 @@
+use std::collections::VecDeque;
+
+use crate::Proto;
+
+/// Navigation over the protos of a chunk, ids are indices into `protos()`.
+pub trait ProtoTree {
+    fn protos(&self) -> &[Proto];
+    fn main_proto_id(&self) -> u32;
+
+    fn main(&self) -> Option<&Proto> {
+        self.proto(self.main_proto_id())
+    }
+
+    fn proto(&self, id: u32) -> Option<&Proto> {
+        self.protos().get(id as usize)
+    }
+
+    fn children(&self, id: u32) -> &[u32] {
+        self.proto(id)
+            .map(|proto| proto.protos.as_slice())
+            .unwrap_or_default()
+    }
+
+    fn parent(&self, id: u32) -> Option<u32> {
+        self.protos()
+            .iter()
+            .position(|proto| proto.protos.contains(&id))
+            .map(|parent| parent as u32)
+    }
+
+    /// Pre-order walk from the main proto, every proto is visited once.
+    fn depth_first(&self) -> DepthFirst<'_, Self>
+    where
+        Self: Sized,
+    {
+        DepthFirst {
+            tree: self,
+            stack: vec![self.main_proto_id()],
+            visited: vec![false; self.protos().len()],
+        }
+    }
+
+    fn breadth_first(&self) -> BreadthFirst<'_, Self>
+    where
+        Self: Sized,
+    {
+        BreadthFirst {
+            tree: self,
+            queue: VecDeque::from([self.main_proto_id()]),
+            visited: vec![false; self.protos().len()],
+        }
+    }
+}
+
+pub struct DepthFirst<'a, T> {
+    tree: &'a T,
+    stack: Vec<u32>,
+    visited: Vec<bool>,
+}
+
+impl<'a, T: ProtoTree> Iterator for DepthFirst<'a, T> {
+    type Item = (u32, &'a Proto);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(id) = self.stack.pop() {
+            // malformed chunks can reference missing protos or form cycles
+            match self.visited.get_mut(id as usize) {
+                Some(visited) if !*visited => *visited = true,
+                _ => continue,
+            }
+
+            let proto = &self.tree.protos()[id as usize];
+            self.stack.extend(proto.protos.iter().rev());
+            return Some((id, proto));
+        }
+
+        None
+    }
+}
+
+pub struct BreadthFirst<'a, T> {
+    tree: &'a T,
+    queue: VecDeque<u32>,
+    visited: Vec<bool>,
+}
+
+impl<'a, T: ProtoTree> Iterator for BreadthFirst<'a, T> {
+    type Item = (u32, &'a Proto);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(id) = self.queue.pop_front() {
+            match self.visited.get_mut(id as usize) {
+                Some(visited) if !*visited => *visited = true,
+                _ => continue,
+            }
+
+            let proto = &self.tree.protos()[id as usize];
+            self.queue.extend(proto.protos.iter());
+            return Some((id, proto));
+        }
+
+        None
+    }
+}
@@ -32,7 +32,7 @@ fn number() {
     assert_eq!(main_proto.locals.len(), 3);
     assert_eq!(main_proto.constants.len(), 2);
 
-    let data = bytecode.write();
+    let data = bytecode.write().unwrap();
 
     let bytecode = <Bytecode as LuaBytecode>::from(data.as_slice()).unwrap();
     let main_proto = &bytecode.protos[bytecode.main_proto_id as usize];
@@ -46,7 +46,7 @@ fn number() {
 fn instruction() {
     use lua_bytecode::opcode::{Instruction, LuaInstruction, LuaOpcode};
 
-    match Instruction(0).opcode() {
+    match Instruction(0).lua51().opcode() {
         lua_bytecode::opcode::Opcode::LuaOpcode(op) => {
             assert_eq!(op, LuaOpcode::Move);
         }
@@ -54,7 +54,7 @@ fn instruction() {
         _ => unreachable!(),
     }
 
-    match Instruction(1).opcode() {
+    match Instruction(1).lua51().opcode() {
         lua_bytecode::opcode::Opcode::LuaOpcode(op) => {
             assert_eq!(op, LuaOpcode::LoadK);
         }
@@ -62,7 +62,7 @@ fn instruction() {
         _ => unreachable!(),
     }
 
-    match Instruction(37).opcode() {
+    match Instruction(37).lua51().opcode() {
         lua_bytecode::opcode::Opcode::LuaOpcode(op) => {
             assert_eq!(op, LuaOpcode::Vararg);
         }
@@ -70,7 +70,7 @@ fn instruction() {
         _ => unreachable!(),
     }
 
-    let instruction = Instruction::from_abc(Opcode::LuaOpcode(LuaOpcode::Call), 0, 1 + 1, 1);
+    let instruction = LuaInstruction::from_abc(Opcode::LuaOpcode(LuaOpcode::Call), 0, 1 + 1, 1);
     match instruction.opcode() {
         Opcode::LuaOpcode(op) => {
             assert_eq!(op, LuaOpcode::Call);
@@ -83,7 +83,7 @@ fn instruction() {
         _ => unreachable!(),
     }
 
-    let instruction = Instruction::from_abx(Opcode::LuaOpcode(LuaOpcode::GetGlobal), 0, 100);
+    let instruction = LuaInstruction::from_abx(Opcode::LuaOpcode(LuaOpcode::GetGlobal), 0, 100);
     match instruction.opcode() {
         Opcode::LuaOpcode(op) => {
             assert_eq!(op, LuaOpcode::GetGlobal);
@@ -95,3 +95,704 @@ fn instruction() {
         _ => unreachable!(),
     }
 }
+
+#[test]
+fn malformed() {
+    use lua_bytecode::Error;
+
+    let result = <Bytecode as LuaBytecode>::from(b"\x1bLub\x51");
+    assert!(matches!(result, Err(Error::BadMagic { offset: 0, .. })));
+
+    let result = <Bytecode as LuaBytecode>::from(b"\x1bLua\x52");
+    assert!(matches!(
+        result,
+        Err(Error::UnsupportedVersion {
+            offset: 4,
+            version: 0x52
+        })
+    ));
+}
+
+fn sample() -> Bytecode {
+    use lua_bytecode::{
+        Header, Proto,
+        constant::Constant,
+        opcode::{LuaInstruction, LuaOpcode},
+    };
+
+    let main_proto = Proto {
+        name: Some(b"@sample.lua\0".to_vec()),
+        max_stack_size: 2,
+        is_vararg: true,
+        instructions: vec![
+            LuaInstruction::from_abx(Opcode::LuaOpcode(LuaOpcode::LoadK), 0, 0).into(),
+            LuaInstruction::from_abc(Opcode::LuaOpcode(LuaOpcode::Return), 0, 1, 0).into(),
+        ],
+        constants: vec![
+            Constant::Number(500.0),
+            Constant::String(b"x\0".to_vec()),
+            Constant::Bool(true),
+            Constant::Nil,
+        ],
+        line_info: vec![1, 1],
+        upvalues: vec![Some(b"_ENV\0".to_vec())],
+        ..Default::default()
+    };
+
+    Bytecode {
+        header: Header {
+            version: 0x51,
+            int_size: 4,
+            size_t_size: 8,
+            instruction_size: 4,
+            number_size: 8,
+            ..Default::default()
+        },
+        protos: vec![main_proto],
+        main_proto_id: 0,
+    }
+}
+
+#[test]
+fn invalid_opcode() {
+    use lua_bytecode::{
+        Error,
+        opcode::{Instruction, LuaOpcode},
+    };
+
+    assert_eq!(LuaOpcode::try_from(37), Ok(LuaOpcode::Vararg));
+    assert_eq!(LuaOpcode::try_from(38), Err(38));
+    assert_eq!(Instruction(63).lua51().try_opcode(), Err(63));
+
+    let mut bytecode = sample();
+    bytecode.protos[0].instructions.insert(1, Instruction(40));
+
+    let data = bytecode.write().unwrap();
+    assert_eq!(
+        <Bytecode as LuaBytecode>::from(&data).err(),
+        Some(Error::InvalidOpcode {
+            proto: 0,
+            pc: 1,
+            opcode: 40
+        })
+    );
+}
+
+#[test]
+fn truncated() {
+    use lua_bytecode::Error;
+
+    let data = sample().write().unwrap();
+    assert!(<Bytecode as LuaBytecode>::from(&data).is_ok());
+
+    for length in 0..data.len() {
+        match <Bytecode as LuaBytecode>::from(&data[..length]) {
+            Err(Error::UnexpectedEof { offset }) => assert!(offset <= length as u64),
+            result => panic!("truncated at {length}: {result:?}"),
+        }
+    }
+}
+
+#[test]
+fn limits() {
+    use lua_bytecode::{Error, ParseOptions};
+
+    let mut bytecode = sample();
+    let data = bytecode.write().unwrap();
+
+    let options = ParseOptions {
+        max_instructions: 1,
+        ..ParseOptions::hardened()
+    };
+    assert!(matches!(
+        <Bytecode as LuaBytecode>::from_with_options(&data, options),
+        Err(Error::LimitExceeded {
+            limit: "instruction count",
+            ..
+        })
+    ));
+
+    // a chain of protos nested four levels deep
+    let leaf = bytecode.protos[0].clone();
+    for id in 0..4 {
+        bytecode.protos[id].protos = vec![id as u32 + 1];
+        bytecode.protos.push(leaf.clone());
+    }
+
+    let data = bytecode.write().unwrap();
+    let options = ParseOptions {
+        max_depth: 2,
+        ..ParseOptions::hardened()
+    };
+    assert!(matches!(
+        <Bytecode as LuaBytecode>::from_with_options(&data, options),
+        Err(Error::LimitExceeded {
+            limit: "nesting depth",
+            ..
+        })
+    ));
+
+    // a huge instruction count runs into the end of the input
+    let mut data = sample().write().unwrap();
+    let offset = 12 + (8 + 12) + 4 + 4 + 4;
+    data[offset..offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(matches!(
+        <Bytecode as LuaBytecode>::from(&data),
+        Err(Error::UnexpectedEof { .. })
+    ));
+}
+
+#[test]
+fn mutated() {
+    use lua_bytecode::ParseOptions;
+
+    let data = sample().write().unwrap();
+    for position in 0..data.len() {
+        for value in [0x00, 0x7f, 0x80, 0xff, data[position] ^ 0x01] {
+            let mut data = data.clone();
+            data[position] = value;
+
+            if let Ok(mut bytecode) =
+                <Bytecode as LuaBytecode>::from_with_options(&data, ParseOptions::hardened())
+            {
+                let _ = bytecode.write();
+            }
+        }
+    }
+}
+
+#[test]
+fn detect() {
+    use lua_bytecode::{Chunk, Format, detect_format, parse_any};
+
+    let data = sample().write().unwrap();
+    assert_eq!(detect_format(&data), Some(Format::Lua51));
+    assert_eq!(detect_format(b"\x1bLua\x54\x00"), Some(Format::Lua54));
+    assert_eq!(detect_format(b"\x1bLJ\x02"), Some(Format::LuaJit));
+    assert_eq!(detect_format(b"\x1bLua"), None);
+
+    match parse_any(&data).unwrap() {
+        Chunk::Lua51(bytecode) => assert_eq!(bytecode.protos.len(), 1),
+        #[allow(unreachable_patterns)]
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn header_sizes() {
+    use lua_bytecode::{Error, constant::Constant};
+
+    let data = sample().write().unwrap();
+    assert_eq!(&data[6..12], &[1, 4, 8, 4, 8, 0]);
+
+    // a 32-bit big-endian build with single precision numbers
+    let mut bytecode = sample();
+    bytecode.header.is_big_endian = true;
+    bytecode.header.size_t_size = 4;
+    bytecode.header.number_size = 4;
+
+    let data = bytecode.write().unwrap();
+    assert_eq!(&data[6..12], &[0, 4, 4, 4, 4, 0]);
+    assert_eq!(&data[12..16], &12u32.to_be_bytes());
+
+    let mut bytecode = <Bytecode as LuaBytecode>::from(&data).unwrap();
+    assert!(bytecode.header.is_big_endian);
+    assert_eq!(bytecode.header.size_t_size, 4);
+    assert!(matches!(
+        bytecode.protos[0].constants[0],
+        Constant::Number(500.0)
+    ));
+    assert_eq!(bytecode.write().unwrap(), data);
+
+    bytecode.protos[0].constants[0] = Constant::Number(0.1);
+    assert_eq!(
+        bytecode.write().err(),
+        Some(Error::LossyConstant { proto: 0, index: 0 })
+    );
+
+    // LUA_NUMBER=long builds store numbers as integers
+    let mut bytecode = sample();
+    bytecode.header.is_number_integral = true;
+    bytecode.protos[0].constants[0] = Constant::Number(-3.0);
+
+    let data = bytecode.write().unwrap();
+    let mut bytecode = <Bytecode as LuaBytecode>::from(&data).unwrap();
+    assert!(matches!(
+        bytecode.protos[0].constants[0],
+        Constant::Integer(-3)
+    ));
+    assert_eq!(bytecode.write().unwrap(), data);
+
+    let mut data = sample().write().unwrap();
+    data[6] = 2;
+    assert_eq!(
+        <Bytecode as LuaBytecode>::from(&data).err(),
+        Some(Error::Malformed {
+            offset: 6,
+            reason: "unknown endianness"
+        })
+    );
+
+    let mut data = sample().write().unwrap();
+    data[10] = 3;
+    assert_eq!(
+        <Bytecode as LuaBytecode>::from(&data).err(),
+        Some(Error::Malformed {
+            offset: 10,
+            reason: "unsupported number size"
+        })
+    );
+}
+
+#[test]
+fn retarget() {
+    use lua_bytecode::{Error, Header, constant::Constant};
+
+    let target = Header {
+        version: 0x51,
+        is_big_endian: true,
+        int_size: 4,
+        size_t_size: 4,
+        instruction_size: 4,
+        number_size: 4,
+        ..Default::default()
+    };
+
+    let mut bytecode = sample();
+    let data = bytecode.retarget(target).unwrap();
+    assert_eq!(bytecode.header, target);
+
+    let mut parsed = <Bytecode as LuaBytecode>::from(&data).unwrap();
+    assert_eq!(parsed.header, target);
+    assert!(matches!(
+        parsed.protos[0].constants[0],
+        Constant::Number(500.0)
+    ));
+
+    // and back to the 64-bit layout
+    let data = parsed.retarget(sample().header).unwrap();
+    assert_eq!(data, sample().write().unwrap());
+
+    let mut bytecode = sample();
+    bytecode.protos[0].constants[0] = Constant::Number(0.1);
+    assert_eq!(
+        bytecode.retarget(target).err(),
+        Some(Error::Retarget(vec![Error::LossyConstant {
+            proto: 0,
+            index: 0
+        }]))
+    );
+    assert_eq!(bytecode.header, sample().header);
+
+    let integral = Header {
+        is_number_integral: true,
+        ..sample().header
+    };
+    assert!(bytecode.retarget(integral).is_err());
+    bytecode.protos[0].constants[0] = Constant::Number(-7.0);
+    assert!(bytecode.retarget(integral).is_ok());
+
+    // every proto is checked before giving up
+    let mut bytecode = sample();
+    let mut child = bytecode.protos[0].clone();
+    child.line_defined = 300;
+    bytecode.protos[0].protos.push(1);
+    bytecode.protos[0].line_info[1] = 300;
+    bytecode.protos.push(child);
+
+    let narrow = Header {
+        int_size: 1,
+        ..sample().header
+    };
+    assert_eq!(
+        bytecode.retarget(narrow).err(),
+        Some(Error::Retarget(vec![
+            Error::ValueOutOfRange {
+                proto: 0,
+                value: "line info"
+            },
+            Error::ValueOutOfRange {
+                proto: 1,
+                value: "line_defined"
+            },
+        ]))
+    );
+    assert_eq!(bytecode.header, sample().header);
+}
+
+#[test]
+fn raw_values() {
+    use lua_bytecode::{Error, constant::Constant};
+
+    // lua reads any nonzero boolean byte as true
+    let mut data = sample().write().unwrap();
+    let position = data.windows(2).position(|pair| pair == [1, 1]).unwrap();
+    data[position + 1] = 2;
+
+    let mut bytecode = <Bytecode as LuaBytecode>::from(&data).unwrap();
+    assert!(matches!(
+        bytecode.protos[0].constants[2],
+        Constant::RawBool(2)
+    ));
+    assert_eq!(bytecode.write().unwrap(), data);
+
+    // integral numbers past 2^53 do not round through f64
+    let mut bytecode = sample();
+    bytecode.header.is_number_integral = true;
+    bytecode.protos[0].constants[0] = Constant::Integer(i64::MAX);
+
+    let data = bytecode.write().unwrap();
+    let mut bytecode = <Bytecode as LuaBytecode>::from(&data).unwrap();
+    assert!(matches!(
+        bytecode.protos[0].constants[0],
+        Constant::Integer(i64::MAX)
+    ));
+    assert_eq!(bytecode.write().unwrap(), data);
+
+    bytecode.header.is_number_integral = false;
+    assert_eq!(
+        bytecode.write().err(),
+        Some(Error::LossyConstant { proto: 0, index: 0 })
+    );
+}
+
+#[test]
+fn corpus() {
+    for entry in std::fs::read_dir("tests/corpus/lua51").unwrap() {
+        let path = entry.unwrap().path();
+        let data = std::fs::read(&path).unwrap();
+
+        let mut bytecode = <Bytecode as LuaBytecode>::from(&data).unwrap();
+        assert_eq!(bytecode.write().unwrap(), data, "{}", path.display());
+    }
+}
+
+#[test]
+fn tree() {
+    use lua_bytecode::ProtoTree;
+
+    let data = std::fs::read("tests/corpus/lua51/nested.luac").unwrap();
+    let mut bytecode = <Bytecode as LuaBytecode>::from(&data).unwrap();
+
+    assert_eq!(bytecode.main_proto_id, 0);
+    assert_eq!(
+        bytecode.main().unwrap().name.as_deref(),
+        Some(&b"@nested.lua\0"[..])
+    );
+    assert_eq!(bytecode.children(0), &[1, 3]);
+    assert_eq!(bytecode.children(1), &[2]);
+    assert_eq!(bytecode.parent(2), Some(1));
+    assert_eq!(bytecode.parent(3), Some(0));
+    assert_eq!(bytecode.parent(0), None);
+
+    let ids = |iter: &mut dyn Iterator<Item = (u32, &lua_bytecode::Proto)>| {
+        iter.map(|(id, _)| id).collect::<Vec<_>>()
+    };
+    assert_eq!(ids(&mut bytecode.depth_first()), vec![0, 1, 2, 3]);
+    assert_eq!(ids(&mut bytecode.breadth_first()), vec![0, 1, 3, 2]);
+
+    // ids are the pre-order positions, so they survive a write/read cycle
+    let written = bytecode.write().unwrap();
+    let reparsed = <Bytecode as LuaBytecode>::from(&written).unwrap();
+    for (id, proto) in bytecode.depth_first() {
+        assert_eq!(
+            reparsed.protos[id as usize].line_defined,
+            proto.line_defined
+        );
+        assert_eq!(reparsed.children(id), bytecode.children(id));
+    }
+}
+
+#[test]
+fn decode_instructions() {
+    use lua_bytecode::{
+        Error,
+        lua51::{DecodedInstruction, Operands, decode_instructions},
+        opcode::{Instruction, LuaInstruction, LuaOpcode},
+    };
+
+    let instructions: [Instruction; 4] = [
+        LuaInstruction::from_abc(Opcode::LuaOpcode(LuaOpcode::SetList), 0, 2, 0).into(),
+        Instruction(600),
+        LuaInstruction::from_abx(Opcode::LuaOpcode(LuaOpcode::Jmp), 0, 131070).into(),
+        LuaInstruction::from_abc(Opcode::LuaOpcode(LuaOpcode::Return), 0, 1, 0).into(),
+    ];
+
+    let decoded = decode_instructions(0, &instructions)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(
+        decoded,
+        vec![
+            (
+                0,
+                DecodedInstruction {
+                    opcode: LuaOpcode::SetList,
+                    operands: Operands::ABC { a: 0, b: 2, c: 0 },
+                    extra: Some(600),
+                }
+            ),
+            (
+                2,
+                DecodedInstruction {
+                    opcode: LuaOpcode::Jmp,
+                    operands: Operands::AsBx { a: 0, sbx: -1 },
+                    extra: None,
+                }
+            ),
+            (
+                3,
+                DecodedInstruction {
+                    opcode: LuaOpcode::Return,
+                    operands: Operands::ABC { a: 0, b: 1, c: 0 },
+                    extra: None,
+                }
+            ),
+        ]
+    );
+
+    let mut decoded = decode_instructions(1, &instructions[..1]);
+    assert_eq!(
+        decoded.next(),
+        Some(Err(Error::TruncatedInstruction { proto: 1, pc: 0 }))
+    );
+    assert_eq!(decoded.next(), None);
+}
+
+#[test]
+fn opcode_info() {
+    use lua_bytecode::opcode::{LuaOpArgMode, LuaOpcode, index_k, is_k, rk_as_k};
+
+    let info = LuaOpcode::Test.info();
+    assert!(info.test && info.sets_a);
+    assert_eq!(info.mode, LuaOpMode::IABC);
+    assert_eq!((info.b, info.c), (LuaOpArgMode::R, LuaOpArgMode::U));
+
+    let info = LuaOpcode::TForLoop.info();
+    assert!(info.test && !info.sets_a);
+    assert_eq!(info.mode, LuaOpMode::IABC);
+
+    let info = LuaOpcode::SetTable.info();
+    assert!(!info.test && !info.sets_a);
+    assert_eq!((info.b, info.c), (LuaOpArgMode::K, LuaOpArgMode::K));
+
+    assert_eq!(LuaOpcode::LoadK.info().mode, LuaOpMode::IABx);
+    assert_eq!(LuaOpcode::Close.info().b, LuaOpArgMode::N);
+
+    assert!(is_k(256) && !is_k(255));
+    assert_eq!(index_k(256 + 3), 3);
+    assert_eq!(rk_as_k(3), 259);
+}
+
+#[test]
+fn disasm() {
+    use lua_bytecode::lua51::disasm::disassemble;
+
+    let data = std::fs::read("tests/corpus/lua51/vararg.luac").unwrap();
+    let bytecode = <Bytecode as LuaBytecode>::from(&data).unwrap();
+    assert_eq!(
+        disassemble(&bytecode).unwrap(),
+        "
+main <vararg.lua:0,0> (6 instructions, 24 bytes)
+0+ params, 3 slots, 0 upvalues, 1 local, 5 constants, 0 functions
+\t1\t[1]\tNEWTABLE \t0 1 0
+\t2\t[1]\tLOADK    \t1 -1\t; 1
+\t3\t[1]\tSETLIST  \t0 1 0\t; 1
+\t5\t[2]\tVARARG   \t1 2
+\t6\t[2]\tRETURN   \t0 1
+constants (5):
+\t1\t1
+\t2\t\"x\"
+\t3\ttrue
+\t4\tfalse
+\t5\tnil
+locals (1):
+\t0\tt\t5\t7
+upvalues (0):
+"
+    );
+
+    let data = std::fs::read("tests/corpus/lua51/nested.luac").unwrap();
+    let bytecode = <Bytecode as LuaBytecode>::from(&data).unwrap();
+    let listing = disassemble(&bytecode).unwrap();
+    assert!(listing.contains("\nfunction <nested.lua:1,3> (4 instructions, 16 bytes)\n"));
+    assert!(listing.contains("\t2\t[2]\tGETUPVAL \t0 0\t; a\n"));
+    assert!(listing.contains("\t4\t[4]\tCLOSURE  \t2 1\t; proto 3\n"));
+    assert!(listing.contains("1+ param, 2 slots, 0 upvalues, 1 local, 0 constants, 0 functions\n"));
+}
+
+#[test]
+fn asm() {
+    use lua_bytecode::{
+        Error,
+        constant::Constant,
+        lua51::{asm, disasm::disassemble},
+    };
+
+    // the listing of vararg.luac with the SETLIST count spelled out
+    let source = r#"
+.source "@vararg.lua"
+.vararg 7
+.stack 3
+.const one 1
+.const x "x"
+.const yes #true
+.const no #false
+.const none #nil
+.local t 4 6
+
+    1   [1] NEWTABLE    0 1 0
+    2   [1] LOADK       1 -1    ; 1
+    3   [1] SETLIST     0 1 0   ; 1
+        .word 1
+    5   [2] VARARG      1 2
+    6   [2] RETURN      0 1
+"#;
+    let expected = std::fs::read("tests/corpus/lua51/vararg.luac").unwrap();
+    assert_eq!(asm::assemble_bytes(source).unwrap(), expected);
+
+    let source = r#"
+.header endian=big size_t=4
+.const limit 10
+        LOADK     0 #0
+        GETGLOBAL 1 "print"
+loop:   ADD       0 0 #1
+        LT        1 0 $limit
+        JMP       done
+        MOVE      2 1
+        CALL      2 1 1
+        JMP       loop
+done:   CLOSURE   2 inner
+        RETURN    0 1
+
+.function inner
+    .params 1
+    .upvalue outer
+    .local value 0 end
+        GETUPVAL  1 0
+end:    RETURN    1 2
+.end
+"#;
+    let data = asm::assemble_bytes(source).unwrap();
+    let bytecode = <Bytecode as LuaBytecode>::from(&data).unwrap();
+    assert!(bytecode.header.is_big_endian);
+    assert_eq!(bytecode.protos.len(), 2);
+
+    let main = &bytecode.protos[0];
+    assert_eq!(main.protos, vec![1]);
+    assert_eq!(main.max_stack_size, 3);
+    assert!(main.line_info.is_empty());
+    assert_eq!(
+        format!("{:?}", main.constants),
+        format!(
+            "{:?}",
+            [
+                Constant::Number(10.0),
+                Constant::Number(0.0),
+                Constant::String(b"print\0".to_vec()),
+                Constant::Number(1.0),
+            ]
+        )
+    );
+
+    let listing = disassemble(&bytecode).unwrap();
+    assert!(listing.contains("\t3\t[-]\tADD      \t0 0 -4\t; - 1\n"));
+    assert!(listing.contains("\t5\t[-]\tJMP      \t3\t; to 9\n"));
+    assert!(listing.contains("\t8\t[-]\tJMP      \t-6\t; to 3\n"));
+    assert!(listing.contains("\t9\t[-]\tCLOSURE  \t2 0\t; proto 1\n"));
+    assert!(listing.contains("1 param, 2 slots, 1 upvalue, 1 local, 0 constants, 0 functions\n"));
+    assert!(listing.contains("\t0\tvalue\t1\t2\n"));
+    assert!(listing.contains("\t0\touter\n"));
+
+    let line = |source: &str| match asm::assemble(source) {
+        Err(Error::Assembly { line, .. }) => line,
+        result => panic!("expected an assembly error, got {result:?}"),
+    };
+    assert_eq!(line("MOVE 0 1\nFOO 1"), 2);
+    assert_eq!(line("MOVE 0"), 1);
+    assert_eq!(line("\nJMP nowhere"), 2);
+    assert_eq!(line("LOADK 0 $missing"), 1);
+    assert_eq!(line("ADD 0 0 K300"), 1);
+    assert_eq!(line(".end"), 1);
+    assert_eq!(line("\n.function f\nRETURN 0 1"), 2);
+    assert_eq!(line("LOADK 0 #1\nLOADK 1 K7"), 2);
+    assert_eq!(line(".const one 1\nLOADK 0 -2"), 2);
+    assert_eq!(line("MOVE 0 1\nCLOSURE 0 0"), 2);
+}
+
+#[test]
+fn builder() {
+    use lua_bytecode::{
+        Error, constant::Constant, lua51::builder::ProtoBuilder, opcode::LuaOpcode,
+    };
+
+    let mut builder = ProtoBuilder::new();
+    builder.set_line(1);
+    let zero = builder.constant(Constant::Number(0.0));
+    let print = builder.constant(Constant::String(b"print\0".to_vec()));
+    assert_eq!(builder.constant(Constant::Number(0.0)), zero);
+    assert_eq!(builder.constant(Constant::Number(-0.0)), 2);
+
+    let top = builder.label();
+    let done = builder.label();
+    builder.emit_abx(LuaOpcode::LoadK, 0, zero).unwrap();
+    builder.bind(top).unwrap();
+    builder.set_line(2);
+    builder.emit_jump(LuaOpcode::Jmp, 0, done).unwrap();
+    builder.emit_abx(LuaOpcode::GetGlobal, 1, print).unwrap();
+    builder.emit_abc(LuaOpcode::Call, 1, 2, 1).unwrap();
+    builder.emit_jump(LuaOpcode::Jmp, 0, top).unwrap();
+    builder.bind(done).unwrap();
+    builder.set_line(3);
+    builder.emit_abc(LuaOpcode::Return, 0, 1, 0).unwrap();
+
+    let proto = builder.finish().unwrap();
+    assert_eq!(proto.constants.len(), 3);
+    assert_eq!(proto.line_info, vec![1, 2, 2, 2, 2, 3]);
+    assert_eq!(proto.max_stack_size, 3);
+    assert_eq!(proto.instructions[1].lua51().sbx(), 3);
+    assert_eq!(proto.instructions[4].lua51().sbx(), -4);
+
+    // luac never sizes a stack below 2, and lines are optional
+    let mut builder = ProtoBuilder::new();
+    builder.emit_abc(LuaOpcode::Return, 0, 1, 0).unwrap();
+    let proto = builder.finish().unwrap();
+    assert_eq!(proto.max_stack_size, 2);
+    assert!(proto.line_info.is_empty());
+
+    let mut builder = ProtoBuilder::new();
+    let nowhere = builder.label();
+    builder.emit_jump(LuaOpcode::Jmp, 0, nowhere).unwrap();
+    assert_eq!(
+        builder.finish().unwrap_err(),
+        Error::UnboundLabel { label: 0 }
+    );
+
+    // a label of another builder fails even when its index exists here
+    let foreign = ProtoBuilder::new().label();
+    let mut builder = ProtoBuilder::new();
+    builder.label();
+    assert_eq!(builder.bind(foreign), Err(Error::UnknownLabel { label: 0 }));
+
+    assert_eq!(
+        builder.emit_abc(LuaOpcode::Move, 300, 0, 0),
+        Err(Error::OperandOutOfRange {
+            pc: 0,
+            operand: "A"
+        })
+    );
+    assert_eq!(
+        builder.emit_abx(LuaOpcode::LoadK, 0, 1 << 18),
+        Err(Error::OperandOutOfRange {
+            pc: 0,
+            operand: "Bx"
+        })
+    );
+
+    // MAXSTACK is 250
+    builder.emit_abc(LuaOpcode::Move, 250, 0, 0).unwrap();
+    assert_eq!(
+        builder.finish().unwrap_err(),
+        Error::StackOverflow { size: 251 }
+    );
+}
@@ -0,0 +1,206 @@
+#![cfg(feature = "lua52")]
+
+use lua_bytecode::{
+    Bytecode, UpvalueDescriptor,
+    constant::Constant,
+    lua52::LuaBytecode,
+    opcode::{Instruction, Lua52Instruction, Lua52Opcode, LuaOpMode, Opcode},
+};
+
+// unmodified luac 5.2 output, each chunk is named after the script it was compiled from
+fn corpus(name: &str) -> Vec<u8> {
+    std::fs::read(format!("tests/corpus/lua52/{name}.luac")).unwrap()
+}
+
+#[test]
+fn instruction() {
+    assert_eq!(Lua52Opcode::try_from(6), Ok(Lua52Opcode::GetTabUp));
+    assert_eq!(Lua52Opcode::try_from(39), Ok(Lua52Opcode::ExtraArg));
+    assert_eq!(Lua52Opcode::try_from(40), Err(40));
+
+    let instruction =
+        Lua52Instruction::from_ax(Opcode::Lua52Opcode(Lua52Opcode::ExtraArg), 1 << 25);
+    assert_eq!(
+        instruction.opcode(),
+        Opcode::Lua52Opcode(Lua52Opcode::ExtraArg)
+    );
+    assert_eq!(instruction.ax(), 1 << 25);
+    assert_eq!(Lua52Opcode::ExtraArg.mode(), LuaOpMode::IAx);
+
+    let mut instruction = Lua52Instruction::from_abx(Opcode::Lua52Opcode(Lua52Opcode::Jmp), 1, 0);
+    instruction.set_sbx(-2);
+    assert_eq!(instruction.a(), 1);
+    assert_eq!(instruction.sbx(), -2);
+    assert_eq!(Lua52Opcode::Jmp.mode(), LuaOpMode::IAsBx);
+}
+
+#[test]
+fn corpus_round_trip() {
+    for entry in std::fs::read_dir("tests/corpus/lua52").unwrap() {
+        let path = entry.unwrap().path();
+        let data = std::fs::read(&path).unwrap();
+
+        let mut bytecode = <Bytecode as LuaBytecode>::from(&data).unwrap();
+        assert_eq!(bytecode.write().unwrap(), data, "{}", path.display());
+    }
+}
+
+#[test]
+fn nested() {
+    let data = corpus("nested");
+    assert_eq!(&data[12..18], b"\x19\x93\r\n\x1a\n");
+
+    let bytecode = <Bytecode as LuaBytecode>::from(&data).unwrap();
+    let header = bytecode.header;
+    assert_eq!((header.version, header.format), (0x52, 0));
+    assert!(!header.is_big_endian);
+    assert_eq!(
+        (header.int_size, header.size_t_size, header.number_size),
+        (4, 8, 8)
+    );
+
+    // every function repeats the source, main, outer and inner are dumped depth first
+    let [main, outer, inner] = &bytecode.protos[..] else {
+        panic!("expected three protos");
+    };
+    assert_eq!(bytecode.main_proto_id, 0);
+    assert_eq!(
+        (main.protos.clone(), outer.protos.clone()),
+        (vec![1], vec![2])
+    );
+    assert_eq!(inner.name.as_deref(), Some(&b"@nested.lua\0"[..]));
+    assert!(main.is_vararg);
+    assert_eq!((outer.line_defined, outer.last_line_defined), (2, 7));
+    assert_eq!((outer.parameter_count, outer.is_vararg), (1, false));
+
+    assert!(matches!(
+        main.constants[..],
+        [
+            Constant::Number(1.0),
+            Constant::Number(2.0),
+            Constant::Number(3.0)
+        ]
+    ));
+    assert_eq!(main.upvalues, vec![Some(b"_ENV\0".to_vec())]);
+
+    // inner reaches `a` through the upvalue of outer and `x` from its stack
+    assert_eq!(
+        inner.upvalues,
+        vec![Some(b"a\0".to_vec()), Some(b"x\0".to_vec())]
+    );
+    let in_stack = |descriptor: &UpvalueDescriptor| (descriptor.in_stack, descriptor.index);
+    assert_eq!(
+        inner
+            .upvalue_descriptors
+            .iter()
+            .map(in_stack)
+            .collect::<Vec<_>>(),
+        vec![(false, 0), (true, 0)]
+    );
+
+    let opcodes = inner
+        .instructions
+        .iter()
+        .map(|instruction| instruction.lua52().opcode())
+        .collect::<Vec<_>>();
+    let op = |opcode| Opcode::Lua52Opcode(opcode);
+    assert_eq!(
+        opcodes,
+        vec![
+            op(Lua52Opcode::GetUpval),
+            op(Lua52Opcode::GetUpval),
+            op(Lua52Opcode::Add),
+            op(Lua52Opcode::Add),
+            op(Lua52Opcode::Return),
+            op(Lua52Opcode::Return),
+        ]
+    );
+    assert_eq!(inner.line_info, vec![4, 4, 4, 4, 4, 5]);
+    assert_eq!(inner.locals.len(), 1);
+}
+
+#[test]
+fn constants() {
+    let data = corpus("constants");
+    let bytecode = <Bytecode as LuaBytecode>::from(&data).unwrap();
+    let constants = &bytecode.protos[0].constants;
+
+    // 5.2 has no integers, 2^53 + 1 rounds to a double
+    assert!(matches!(constants[2], Constant::Number(-7.0)));
+    assert!(matches!(constants[3], Constant::Number(9007199254740992.0)));
+    assert!(matches!(constants[4], Constant::Number(0.1)));
+
+    // strings keep their trailing NUL, the size_t length counts it
+    assert!(matches!(&constants[5], Constant::String(value) if value == b"short\0"));
+    assert!(matches!(&constants[7], Constant::String(value) if value.len() == 301));
+    assert!(matches!(constants[9], Constant::Nil));
+}
+
+#[test]
+fn malformed() {
+    use lua_bytecode::Error;
+
+    let mut data = corpus("nested");
+    data[17] = 0;
+    assert_eq!(
+        <Bytecode as LuaBytecode>::from(&data).err(),
+        Some(Error::Malformed {
+            offset: 12,
+            reason: "header tail does not match LUAC_TAIL"
+        })
+    );
+
+    let result = <Bytecode as LuaBytecode>::from(b"\x1bLua\x51");
+    assert!(matches!(
+        result,
+        Err(Error::UnsupportedVersion {
+            offset: 4,
+            version: 0x51
+        })
+    ));
+}
+
+#[test]
+fn invalid_opcode() {
+    use lua_bytecode::Error;
+
+    let mut bytecode = <Bytecode as LuaBytecode>::from(&corpus("nested")).unwrap();
+    bytecode.protos[1].instructions.insert(1, Instruction(40));
+
+    let data = bytecode.write().unwrap();
+    assert_eq!(
+        <Bytecode as LuaBytecode>::from(&data).err(),
+        Some(Error::InvalidOpcode {
+            proto: 1,
+            pc: 1,
+            opcode: 40
+        })
+    );
+}
+
+#[test]
+fn truncated() {
+    use lua_bytecode::Error;
+
+    let data = corpus("nested");
+    for length in 0..data.len() {
+        match <Bytecode as LuaBytecode>::from(&data[..length]) {
+            Err(Error::UnexpectedEof { offset }) => assert!(offset <= length as u64),
+            result => panic!("truncated at {length}: {result:?}"),
+        }
+    }
+}
+
+#[test]
+fn detect() {
+    use lua_bytecode::{Chunk, Format, detect_format, parse_any};
+
+    let data = corpus("vararg");
+    assert_eq!(detect_format(&data), Some(Format::Lua52));
+
+    match parse_any(&data).unwrap() {
+        Chunk::Lua52(bytecode) => assert!(bytecode.protos[0].is_vararg),
+        #[allow(unreachable_patterns)]
+        _ => unreachable!(),
+    }
+}
@@ -0,0 +1,262 @@
+#![cfg(feature = "lua53")]
+
+use lua_bytecode::{
+    Bytecode, UpvalueDescriptor,
+    constant::Constant,
+    lua53::LuaBytecode,
+    opcode::{Lua53Instruction, Lua53Opcode, LuaOpMode, Opcode},
+};
+
+// unmodified luac 5.3 output, each chunk is named after the script it was compiled from
+fn corpus(name: &str) -> Vec<u8> {
+    std::fs::read(format!("tests/corpus/lua53/{name}.luac")).unwrap()
+}
+
+#[test]
+fn instruction() {
+    assert_eq!(Lua53Opcode::try_from(19), Ok(Lua53Opcode::IDiv));
+    assert_eq!(Lua53Opcode::try_from(26), Ok(Lua53Opcode::BNot));
+    assert_eq!(Lua53Opcode::try_from(46), Ok(Lua53Opcode::ExtraArg));
+    assert_eq!(Lua53Opcode::try_from(47), Err(47));
+
+    let instruction = Lua53Instruction::from_abc(Opcode::Lua53Opcode(Lua53Opcode::Shl), 1, 2, 3);
+    assert_eq!(instruction.opcode(), Opcode::Lua53Opcode(Lua53Opcode::Shl));
+    assert_eq!(
+        (instruction.a(), instruction.b(), instruction.c()),
+        (1, 2, 3)
+    );
+    assert_eq!(Lua53Opcode::BNot.mode(), LuaOpMode::IAB);
+}
+
+#[test]
+fn corpus_round_trip() {
+    for entry in std::fs::read_dir("tests/corpus/lua53").unwrap() {
+        let path = entry.unwrap().path();
+        let data = std::fs::read(&path).unwrap();
+
+        let mut bytecode = <Bytecode as LuaBytecode>::from(&data).unwrap();
+        assert_eq!(bytecode.write().unwrap(), data, "{}", path.display());
+    }
+}
+
+#[test]
+fn nested() {
+    let data = corpus("nested");
+    let bytecode = <Bytecode as LuaBytecode>::from(&data).unwrap();
+    let header = bytecode.header;
+    assert_eq!((header.version, header.format), (0x53, 0));
+    assert!(!header.is_big_endian);
+    assert_eq!(
+        (header.int_size, header.size_t_size, header.integer_size),
+        (4, 8, 8)
+    );
+
+    // only the main function carries the source name
+    let [main, outer, inner] = &bytecode.protos[..] else {
+        panic!("expected three protos");
+    };
+    assert_eq!(
+        (main.protos.clone(), outer.protos.clone()),
+        (vec![1], vec![2])
+    );
+    assert_eq!(main.name.as_deref(), Some(&b"@nested.lua"[..]));
+    assert_eq!((outer.name.clone(), inner.name.clone()), (None, None));
+    assert_eq!((outer.line_defined, outer.last_line_defined), (2, 7));
+    assert_eq!((inner.parameter_count, inner.max_stack_size), (1, 3));
+
+    assert!(matches!(
+        main.constants[..],
+        [
+            Constant::Integer(1),
+            Constant::Integer(2),
+            Constant::Integer(3)
+        ]
+    ));
+    assert_eq!(main.upvalues, vec![Some(b"_ENV".to_vec())]);
+    assert_eq!(
+        inner.upvalues,
+        vec![Some(b"a".to_vec()), Some(b"x".to_vec())]
+    );
+    let in_stack = |descriptor: &UpvalueDescriptor| (descriptor.in_stack, descriptor.index);
+    assert_eq!(
+        inner
+            .upvalue_descriptors
+            .iter()
+            .map(in_stack)
+            .collect::<Vec<_>>(),
+        vec![(false, 0), (true, 0)]
+    );
+
+    let opcodes = inner
+        .instructions
+        .iter()
+        .map(|instruction| instruction.lua53().opcode())
+        .collect::<Vec<_>>();
+    let op = |opcode| Opcode::Lua53Opcode(opcode);
+    assert_eq!(
+        opcodes,
+        vec![
+            op(Lua53Opcode::GetUpval),
+            op(Lua53Opcode::GetUpval),
+            op(Lua53Opcode::Add),
+            op(Lua53Opcode::Add),
+            op(Lua53Opcode::Return),
+            op(Lua53Opcode::Return),
+        ]
+    );
+    assert_eq!(inner.line_info, vec![4, 4, 4, 4, 4, 5]);
+    assert_eq!(main.locals.len(), 2);
+}
+
+#[test]
+fn constants() {
+    let data = corpus("constants");
+    let bytecode = <Bytecode as LuaBytecode>::from(&data).unwrap();
+    let constants = &bytecode.protos[0].constants;
+
+    // integers keep their own tag, 2^53 + 1 survives
+    assert!(matches!(constants[0], Constant::Integer(1)));
+    assert!(matches!(constants[1], Constant::Number(2.5)));
+    assert!(matches!(constants[2], Constant::Integer(-7)));
+    assert!(matches!(constants[3], Constant::Integer(9007199254740993)));
+
+    // long strings switch to the 0xff size marker
+    assert!(matches!(&constants[5], Constant::String(value) if value == b"short"));
+    assert!(matches!(&constants[7], Constant::String(value) if value.len() == 300));
+    assert!(matches!(constants[9], Constant::Nil));
+}
+
+#[test]
+fn upvalue_names() {
+    let mut bytecode = <Bytecode as LuaBytecode>::from(&corpus("nested")).unwrap();
+
+    // a NULL name is stored with size 0, an empty one with size 1
+    bytecode.protos[0].upvalues = vec![None];
+    let data = bytecode.write().unwrap();
+    let mut reparsed = <Bytecode as LuaBytecode>::from(&data).unwrap();
+    assert_eq!(reparsed.protos[0].upvalues, vec![None]);
+    assert_eq!(reparsed.write().unwrap(), data);
+
+    bytecode.protos[0].upvalues = vec![Some(Vec::new())];
+    let empty = bytecode.write().unwrap();
+    assert_ne!(empty, data);
+    let reparsed = <Bytecode as LuaBytecode>::from(&empty).unwrap();
+    assert_eq!(reparsed.protos[0].upvalues, vec![Some(Vec::new())]);
+}
+
+#[test]
+fn malformed() {
+    use lua_bytecode::Error;
+
+    // LUAC_INT starts after the signature, version, format, LUAC_DATA and five sizes
+    let mut data = corpus("nested");
+    data[17] = 0x79;
+    assert_eq!(
+        <Bytecode as LuaBytecode>::from(&data).err(),
+        Some(Error::Malformed {
+            offset: 17,
+            reason: "integer check value does not match LUAC_INT"
+        })
+    );
+
+    let mut data = corpus("nested");
+    data[25] ^= 0x01;
+    assert_eq!(
+        <Bytecode as LuaBytecode>::from(&data).err(),
+        Some(Error::Malformed {
+            offset: 25,
+            reason: "number check value does not match LUAC_NUM"
+        })
+    );
+
+    let mut data = corpus("nested");
+    data[13] = 3;
+    assert_eq!(
+        <Bytecode as LuaBytecode>::from(&data).err(),
+        Some(Error::Malformed {
+            offset: 13,
+            reason: "unsupported size_t size"
+        })
+    );
+
+    let mut bytecode = <Bytecode as LuaBytecode>::from(&corpus("nested")).unwrap();
+    bytecode.header.integer_size = 3;
+    assert_eq!(
+        bytecode.write().err(),
+        Some(Error::Malformed {
+            offset: 15,
+            reason: "unsupported integer size"
+        })
+    );
+}
+
+#[test]
+fn header_sizes() {
+    use lua_bytecode::Error;
+
+    // a LUA_32BITS build, lua_Integer and lua_Number are 4 bytes wide
+    let data = std::fs::read("tests/corpus/lua53/int32.luac").unwrap();
+    let mut bytecode = <Bytecode as LuaBytecode>::from(&data).unwrap();
+    assert_eq!(bytecode.header.integer_size, 4);
+    assert_eq!(bytecode.header.number_size, 4);
+    assert!(!bytecode.header.is_big_endian);
+
+    let constants = &bytecode.protos[0].constants;
+    assert!(matches!(constants[0], Constant::Integer(123456)));
+    assert!(matches!(constants[1], Constant::Number(0.25)));
+    let constants = &bytecode.protos[1].constants;
+    assert!(matches!(constants[1], Constant::Integer(2147483647)));
+    assert_eq!(bytecode.write().unwrap(), data);
+
+    // the byte order of a chunk only shows in LUAC_INT
+    bytecode.header.is_big_endian = true;
+    let swapped = bytecode.write().unwrap();
+    assert_eq!(&swapped[17..21], &[0, 0, 0x56, 0x78]);
+
+    let mut reparsed = <Bytecode as LuaBytecode>::from(&swapped).unwrap();
+    assert!(reparsed.header.is_big_endian);
+    assert!(matches!(
+        reparsed.protos[0].constants[0],
+        Constant::Integer(123456)
+    ));
+    assert_eq!(reparsed.write().unwrap(), swapped);
+
+    reparsed.protos[0].constants[0] = Constant::Integer(1 << 40);
+    assert_eq!(
+        reparsed.write().err(),
+        Some(Error::LossyConstant { proto: 0, index: 0 })
+    );
+
+    reparsed.protos[0].constants[0] = Constant::Number(0.1);
+    assert_eq!(
+        reparsed.write().err(),
+        Some(Error::LossyConstant { proto: 0, index: 0 })
+    );
+}
+
+#[test]
+fn truncated() {
+    use lua_bytecode::Error;
+
+    let data = corpus("nested");
+    for length in 0..data.len() {
+        match <Bytecode as LuaBytecode>::from(&data[..length]) {
+            Err(Error::UnexpectedEof { offset }) => assert!(offset <= length as u64),
+            result => panic!("truncated at {length}: {result:?}"),
+        }
+    }
+}
+
+#[test]
+fn detect() {
+    use lua_bytecode::{Chunk, Format, detect_format, parse_any};
+
+    let data = corpus("vararg");
+    assert_eq!(detect_format(&data), Some(Format::Lua53));
+
+    match parse_any(&data).unwrap() {
+        Chunk::Lua53(bytecode) => assert!(bytecode.protos[0].is_vararg),
+        #[allow(unreachable_patterns)]
+        _ => unreachable!(),
+    }
+}
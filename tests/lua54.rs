@@ -0,0 +1,307 @@
+#![cfg(feature = "lua54")]
+
+use lua_bytecode::{
+    Bytecode, UpvalueDescriptor, UpvalueKind,
+    constant::Constant,
+    lua54::LuaBytecode,
+    opcode::{Lua54Instruction, Lua54OpMode, Lua54Opcode, Opcode},
+};
+
+// unmodified luac 5.4 output, each chunk is named after the script it was compiled from
+fn corpus(name: &str) -> Vec<u8> {
+    std::fs::read(format!("tests/corpus/lua54/{name}.luac")).unwrap()
+}
+
+#[test]
+fn instruction() {
+    assert_eq!(Lua54Opcode::try_from(56), Ok(Lua54Opcode::Jmp));
+    assert_eq!(Lua54Opcode::try_from(82), Ok(Lua54Opcode::ExtraArg));
+    assert_eq!(Lua54Opcode::try_from(83), Err(83));
+    assert_eq!(Lua54Opcode::Jmp.mode(), Lua54OpMode::IsJ);
+    assert_eq!(Lua54Opcode::LoadF.mode(), Lua54OpMode::IAsBx);
+
+    let instruction =
+        Lua54Instruction::from_abck(Opcode::Lua54Opcode(Lua54Opcode::EqI), 3, 127 - 4, 0, true);
+    assert_eq!(instruction.opcode(), Opcode::Lua54Opcode(Lua54Opcode::EqI));
+    assert_eq!(instruction.a(), 3);
+    assert_eq!(instruction.sb(), -4);
+    assert!(instruction.k());
+
+    let instruction = Lua54Instruction::from_sj(Opcode::Lua54Opcode(Lua54Opcode::Jmp), -16777215);
+    assert_eq!(instruction.sj(), -16777215);
+    assert_eq!(instruction.0 & 0x7f, Lua54Opcode::Jmp as u32);
+
+    let instruction =
+        Lua54Instruction::from_asbx(Opcode::Lua54Opcode(Lua54Opcode::LoadI), 255, -65535);
+    assert_eq!((instruction.a(), instruction.sbx()), (255, -65535));
+    assert_eq!(instruction.bx(), 0);
+
+    let instruction =
+        Lua54Instruction::from_ax(Opcode::Lua54Opcode(Lua54Opcode::ExtraArg), (1 << 25) - 1);
+    assert_eq!(instruction.ax(), (1 << 25) - 1);
+    assert_eq!(
+        instruction.try_opcode(),
+        Ok(Opcode::Lua54Opcode(Lua54Opcode::ExtraArg))
+    );
+}
+
+#[test]
+fn corpus_round_trip() {
+    for entry in std::fs::read_dir("tests/corpus/lua54").unwrap() {
+        let path = entry.unwrap().path();
+        let data = std::fs::read(&path).unwrap();
+
+        let mut bytecode = <Bytecode as LuaBytecode>::from(&data).unwrap();
+        assert_eq!(bytecode.write().unwrap(), data, "{}", path.display());
+    }
+}
+
+#[test]
+fn nested() {
+    let data = corpus("nested");
+    let bytecode = <Bytecode as LuaBytecode>::from(&data).unwrap();
+    let header = bytecode.header;
+    assert_eq!((header.version, header.format), (0x54, 0));
+    assert!(!header.is_big_endian);
+    assert_eq!((header.integer_size, header.number_size), (8, 8));
+
+    let [main, outer, inner] = &bytecode.protos[..] else {
+        panic!("expected three protos");
+    };
+    assert_eq!(
+        (main.protos.clone(), outer.protos.clone()),
+        (vec![1], vec![2])
+    );
+    assert_eq!(main.name.as_deref(), Some(&b"@nested.lua"[..]));
+    assert_eq!((outer.name.clone(), inner.name.clone()), (None, None));
+    assert_eq!((outer.line_defined, outer.last_line_defined), (2, 7));
+    assert_eq!((inner.parameter_count, inner.max_stack_size), (1, 3));
+
+    // small integers are loaded with LOADI and never reach the constant table
+    assert!(main.constants.is_empty());
+    assert_eq!(main.upvalues, vec![Some(b"_ENV".to_vec())]);
+    assert_eq!(
+        inner.upvalues,
+        vec![Some(b"a".to_vec()), Some(b"x".to_vec())]
+    );
+    let descriptor = |in_stack, index| UpvalueDescriptor {
+        in_stack,
+        index,
+        kind: UpvalueKind::Regular,
+    };
+    assert_eq!(
+        inner.upvalue_descriptors,
+        vec![descriptor(false, 0), descriptor(true, 0)]
+    );
+
+    let opcodes = inner
+        .instructions
+        .iter()
+        .map(|instruction| instruction.lua54().opcode())
+        .collect::<Vec<_>>();
+    let op = |opcode| Opcode::Lua54Opcode(opcode);
+    assert_eq!(
+        opcodes,
+        vec![
+            op(Lua54Opcode::GetUpval),
+            op(Lua54Opcode::GetUpval),
+            op(Lua54Opcode::Add),
+            op(Lua54Opcode::MmBin),
+            op(Lua54Opcode::Add),
+            op(Lua54Opcode::MmBin),
+            op(Lua54Opcode::Return1),
+            op(Lua54Opcode::Return0),
+        ]
+    );
+    assert_eq!(inner.line_info, vec![1, 0, 0, 0, 0, 0, 0, 1]);
+    assert_eq!(main.locals.len(), 2);
+}
+
+#[test]
+fn constants() {
+    let data = corpus("constants");
+    let bytecode = <Bytecode as LuaBytecode>::from(&data).unwrap();
+    let constants = &bytecode.protos[0].constants;
+
+    // 1 and -7 fit LOADI, 2^53 + 1 needs the constant table
+    assert!(matches!(constants[0], Constant::Number(2.5)));
+    assert!(matches!(constants[1], Constant::Integer(9007199254740993)));
+    assert!(matches!(constants[2], Constant::Number(0.1)));
+    assert!(matches!(&constants[3], Constant::String(value) if value == b"short"));
+    assert!(matches!(&constants[5], Constant::String(value) if value.len() == 300));
+    assert!(matches!(constants[7], Constant::Nil));
+}
+
+#[test]
+fn absolute_lines() {
+    let data = corpus("lines");
+    let bytecode = <Bytecode as LuaBytecode>::from(&data).unwrap();
+
+    // the 200 line jump to `return f(2)` does not fit a signed byte delta
+    let main = &bytecode.protos[0];
+    assert_eq!(main.line_info[2], 0x80);
+    assert_eq!(main.absolute_line_pcs, vec![2]);
+    assert_eq!(main.absolute_line_info, vec![203]);
+
+    let child = &bytecode.protos[1];
+    assert_eq!((child.line_defined, child.last_line_defined), (201, 203));
+    assert!(child.absolute_line_info.is_empty());
+}
+
+#[test]
+fn upvalue_kinds() {
+    let data = corpus("attribs");
+    let bytecode = <Bytecode as LuaBytecode>::from(&data).unwrap();
+
+    // `limit <const>` and `handle <close>` keep their attribute in the upvalue kind
+    let kinds = bytecode.protos[1]
+        .upvalue_descriptors
+        .iter()
+        .map(|descriptor| (descriptor.index, descriptor.kind))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        vec![(0, UpvalueKind::Const), (1, UpvalueKind::ToBeClosed)]
+    );
+}
+
+#[test]
+fn upvalue_names() {
+    let mut bytecode = <Bytecode as LuaBytecode>::from(&corpus("nested")).unwrap();
+
+    // a NULL name is stored with size 0, an empty one with size 1
+    bytecode.protos[0].upvalues = vec![None];
+    let data = bytecode.write().unwrap();
+    let mut reparsed = <Bytecode as LuaBytecode>::from(&data).unwrap();
+    assert_eq!(reparsed.protos[0].upvalues, vec![None]);
+    assert_eq!(reparsed.write().unwrap(), data);
+
+    bytecode.protos[0].upvalues = vec![Some(Vec::new())];
+    let empty = bytecode.write().unwrap();
+    assert_ne!(empty, data);
+    let reparsed = <Bytecode as LuaBytecode>::from(&empty).unwrap();
+    assert_eq!(reparsed.protos[0].upvalues, vec![Some(Vec::new())]);
+}
+
+#[test]
+fn varint() {
+    let data = corpus("nested");
+
+    // header, main upvalue count and the "@nested.lua" source
+    let offset = 4 + 2 + 6 + 3 + 8 + 8 + 1;
+    assert_eq!(data[offset], 0x80 | 12);
+    assert_eq!(&data[offset + 1..offset + 12], b"@nested.lua");
+
+    // line_defined 0 and last_line_defined 0 are single bytes
+    assert_eq!(&data[offset + 12..offset + 14], &[0x80, 0x80]);
+
+    // last_line_defined 203 takes two bytes, 7 bits each
+    let data = corpus("lines");
+    let needle = [0x01, 0x80 | 73, 0x01, 0x80 | 75];
+    assert!(data.windows(4).any(|window| window == needle));
+}
+
+#[test]
+fn malformed() {
+    use lua_bytecode::Error;
+
+    let mut data = corpus("nested");
+    let offset = 4 + 2 + 6 + 3;
+    data[offset] ^= 0x01;
+    assert_eq!(
+        <Bytecode as LuaBytecode>::from(&data).err(),
+        Some(Error::Malformed {
+            offset: offset as u64,
+            reason: "integer check value does not match LUAC_INT"
+        })
+    );
+
+    // an integer size no build produces is rejected before LUAC_INT is read
+    let mut data = corpus("nested");
+    data[4 + 2 + 6 + 1] = 3;
+    assert_eq!(
+        <Bytecode as LuaBytecode>::from(&data).err(),
+        Some(Error::Malformed {
+            offset: 13,
+            reason: "unsupported integer size"
+        })
+    );
+
+    // a NULL source followed by a line_defined varint past the int limit
+    let mut data = corpus("nested");
+    data.truncate(4 + 2 + 6 + 3 + 8 + 8 + 1);
+    data.push(0x80);
+    data.extend_from_slice(&[0x7f; 8]);
+    assert!(matches!(
+        <Bytecode as LuaBytecode>::from(&data),
+        Err(Error::Malformed {
+            reason: "variant integer overflow",
+            ..
+        })
+    ));
+}
+
+#[test]
+fn header_sizes() {
+    use lua_bytecode::Error;
+
+    // a LUA_32BITS build, lua_Integer and lua_Number are 4 bytes wide
+    let data = std::fs::read("tests/corpus/lua54/int32.luac").unwrap();
+    let mut bytecode = <Bytecode as LuaBytecode>::from(&data).unwrap();
+    assert_eq!(bytecode.header.integer_size, 4);
+    assert_eq!(bytecode.header.number_size, 4);
+    assert!(!bytecode.header.is_big_endian);
+
+    let constants = &bytecode.protos[0].constants;
+    assert!(matches!(constants[0], Constant::Integer(123456)));
+    assert!(matches!(constants[1], Constant::Number(0.25)));
+    let constants = &bytecode.protos[1].constants;
+    assert!(matches!(constants[1], Constant::Integer(2147483647)));
+    assert_eq!(bytecode.write().unwrap(), data);
+
+    // the byte order of a chunk only shows in LUAC_INT and the instructions
+    bytecode.header.is_big_endian = true;
+    let swapped = bytecode.write().unwrap();
+    assert_eq!(&swapped[15..19], &[0, 0, 0x56, 0x78]);
+
+    let mut reparsed = <Bytecode as LuaBytecode>::from(&swapped).unwrap();
+    assert!(reparsed.header.is_big_endian);
+    assert_eq!(
+        reparsed.protos[1].instructions,
+        bytecode.protos[1].instructions
+    );
+    assert_eq!(reparsed.write().unwrap(), swapped);
+
+    reparsed.protos[0].constants[0] = Constant::Integer(1 << 40);
+    assert_eq!(
+        reparsed.write().err(),
+        Some(Error::LossyConstant { proto: 0, index: 0 })
+    );
+}
+
+#[test]
+fn truncated() {
+    use lua_bytecode::Error;
+
+    let data = corpus("nested");
+    for length in 0..data.len() {
+        match <Bytecode as LuaBytecode>::from(&data[..length]) {
+            Err(Error::UnexpectedEof { offset }) => assert!(offset <= length as u64),
+            result => panic!("truncated at {length}: {result:?}"),
+        }
+    }
+}
+
+#[test]
+fn detect() {
+    use lua_bytecode::{Chunk, Format, detect_format, parse_any};
+
+    let data = corpus("vararg");
+    assert_eq!(detect_format(&data), Some(Format::Lua54));
+
+    match parse_any(&data).unwrap() {
+        Chunk::Lua54(bytecode) => assert!(bytecode.protos[0].is_vararg),
+        #[allow(unreachable_patterns)]
+        _ => unreachable!(),
+    }
+}
@@ -0,0 +1,362 @@
+#![cfg(feature = "luajit")]
+
+use lua_bytecode::{
+    Bytecode, UpvalueDescriptor, UpvalueKind,
+    constant::Constant,
+    luajit::{BCDUMP_F_BE, BCDUMP_F_FFI, BCDUMP_F_FR2, BCDUMP_F_STRIP, LuaBytecode},
+    opcode::{Instruction, LuaJitInstruction, LuaJitOpcode, Opcode},
+};
+
+// unmodified `luajit -b` output named after the compiled script, `-bg` kept the debug info
+// of nested_debug and nested_nogc64 comes from a build without LJ_GC64
+fn corpus(name: &str) -> Vec<u8> {
+    std::fs::read(format!("tests/corpus/luajit/{name}.luajit")).unwrap()
+}
+
+fn opcodes(bytecode: &Bytecode, proto: usize) -> Vec<Opcode> {
+    bytecode.protos[proto]
+        .instructions
+        .iter()
+        .map(|instruction| instruction.luajit().opcode(bytecode.header.version))
+        .collect()
+}
+
+#[test]
+fn instruction() {
+    assert_eq!(LuaJitOpcode::try_from(17), Ok(LuaJitOpcode::IsNum));
+    assert_eq!(LuaJitOpcode::from_version(16, 1), Ok(LuaJitOpcode::Mov));
+    assert_eq!(LuaJitOpcode::from_version(16, 2), Ok(LuaJitOpcode::IsType));
+    assert_eq!(LuaJitOpcode::from_version(93, 1), Err(93));
+    assert_eq!(LuaJitOpcode::IsType.to_version(1), None);
+    assert_eq!(LuaJitOpcode::Mov.to_version(1), Some(16));
+    assert_eq!(
+        LuaJitInstruction::from_ad(Opcode::LuaJitOpcode(LuaJitOpcode::IsType), 1, 0, 0),
+        None
+    );
+
+    let instruction =
+        LuaJitInstruction::from_abc(Opcode::LuaJitOpcode(LuaJitOpcode::AddVV), 1, 3, 0xfe, 0x7f)
+            .unwrap();
+    assert_eq!(
+        instruction.opcode(1),
+        Opcode::LuaJitOpcode(LuaJitOpcode::AddVV)
+    );
+    assert_eq!(
+        (instruction.a(), instruction.b(), instruction.c()),
+        (3, 0xfe, 0x7f)
+    );
+    assert_eq!(instruction.d(), 0xfe7f);
+    assert_eq!(instruction.0 & 0xff, 30);
+}
+
+#[test]
+fn corpus_round_trip() {
+    for entry in std::fs::read_dir("tests/corpus/luajit").unwrap() {
+        let path = entry.unwrap().path();
+        let data = std::fs::read(&path).unwrap();
+
+        let mut bytecode = <Bytecode as LuaBytecode>::from(&data).unwrap();
+        assert_eq!(bytecode.write().unwrap(), data, "{}", path.display());
+    }
+}
+
+#[test]
+fn nested() {
+    let data = corpus("nested");
+    assert_eq!(&data[..5], b"\x1bLJ\x02\x0a");
+
+    let bytecode = <Bytecode as LuaBytecode>::from(&data).unwrap();
+    assert_eq!(bytecode.header.version, 2);
+    assert_eq!(bytecode.header.luajit_flags, BCDUMP_F_STRIP | BCDUMP_F_FR2);
+
+    // children are dumped before their parents, the main proto comes last
+    let [inner, outer, main] = &bytecode.protos[..] else {
+        panic!("expected three protos");
+    };
+    assert_eq!(bytecode.main_proto_id, 2);
+    assert_eq!(
+        (main.protos.clone(), outer.protos.clone()),
+        (vec![1], vec![0])
+    );
+    assert!(matches!(main.constants[..], [Constant::Closure(1)]));
+    assert!(matches!(outer.constants[..], [Constant::Closure(0)]));
+    assert_eq!(main.name, None);
+    assert!(main.is_vararg && !outer.is_vararg);
+    assert_eq!((inner.parameter_count, inner.upvalue_count), (1, 2));
+
+    // `a` and `x` are never assigned, luajit marks both as immutable
+    let descriptor = |in_stack, kind| UpvalueDescriptor {
+        in_stack,
+        index: 0,
+        kind,
+    };
+    assert_eq!(
+        inner.upvalue_descriptors,
+        vec![
+            descriptor(false, UpvalueKind::Regular),
+            descriptor(true, UpvalueKind::Const)
+        ]
+    );
+    assert!(inner.upvalues.is_empty() && inner.line_info.is_empty());
+
+    let op = |opcode| Opcode::LuaJitOpcode(opcode);
+    assert_eq!(
+        opcodes(&bytecode, 0),
+        vec![
+            op(LuaJitOpcode::UGet),
+            op(LuaJitOpcode::UGet),
+            op(LuaJitOpcode::AddVV),
+            op(LuaJitOpcode::AddVV),
+            op(LuaJitOpcode::Ret1),
+        ]
+    );
+    assert_eq!(
+        opcodes(&bytecode, 1),
+        vec![
+            op(LuaJitOpcode::FNew),
+            op(LuaJitOpcode::UClo),
+            op(LuaJitOpcode::Ret1)
+        ]
+    );
+}
+
+#[test]
+fn debug_info() {
+    let data = corpus("nested_debug");
+    assert_eq!(&data[..5], b"\x1bLJ\x02\x08");
+    assert_eq!(&data[5..17], b"\x0b@nested.lua");
+
+    let bytecode = <Bytecode as LuaBytecode>::from(&data).unwrap();
+    let [inner, outer, main] = &bytecode.protos[..] else {
+        panic!("expected three protos");
+    };
+    assert_eq!(main.name.as_deref(), Some(&b"@nested.lua"[..]));
+    assert_eq!((outer.name.clone(), inner.name.clone()), (None, None));
+    assert_eq!((main.line_defined, main.last_line_defined), (0, 9));
+    assert_eq!((outer.line_defined, outer.last_line_defined), (2, 7));
+
+    // line numbers are stored relative to the line the function starts on
+    assert_eq!(outer.line_info, vec![3, 4, 4]);
+    assert_eq!(inner.line_info, vec![1; 5]);
+    assert_eq!(
+        inner.upvalues,
+        vec![Some(b"a".to_vec()), Some(b"x".to_vec())]
+    );
+    assert_eq!((main.locals.len(), outer.locals.len()), (2, 2));
+
+    // the stripped dump carries the same code
+    let stripped = <Bytecode as LuaBytecode>::from(&corpus("nested")).unwrap();
+    for (proto, stripped) in bytecode.protos.iter().zip(&stripped.protos) {
+        assert_eq!(proto.instructions, stripped.instructions);
+    }
+}
+
+#[test]
+fn frame_slots() {
+    let gc64 = <Bytecode as LuaBytecode>::from(&corpus("nested")).unwrap();
+    let data = corpus("nested_nogc64");
+    assert_eq!(&data[..5], b"\x1bLJ\x02\x02");
+
+    // the two slot frames of LJ_FR2 take an extra register for the call in the main proto
+    let bytecode = <Bytecode as LuaBytecode>::from(&data).unwrap();
+    assert_eq!(bytecode.header.luajit_flags, BCDUMP_F_STRIP);
+    assert_eq!(bytecode.protos[2].max_stack_size, 4);
+    assert_eq!(gc64.protos[2].max_stack_size, 5);
+    assert_eq!(bytecode.protos[0].instructions, gc64.protos[0].instructions);
+}
+
+#[test]
+fn constants() {
+    let data = corpus("constants");
+    let bytecode = <Bytecode as LuaBytecode>::from(&data).unwrap();
+    let constants = &bytecode.protos[0].constants;
+    assert!(matches!(&constants[0], Constant::String(value) if value == b"missing"));
+
+    // the constructor becomes a template table, slot 0 stays nil
+    let Constant::TemplateTable { array, hash } = &constants[1] else {
+        panic!("expected a template table");
+    };
+    assert!(hash.is_empty());
+    assert_eq!(array.len(), 11);
+    assert!(matches!(
+        array[..6],
+        [
+            Constant::Nil,
+            Constant::Integer(1),
+            Constant::Number(2.5),
+            Constant::Integer(-7),
+            Constant::Number(9007199254740992.0),
+            Constant::Number(0.1)
+        ]
+    ));
+    assert!(matches!(&array[8], Constant::String(value) if value.len() == 300));
+    assert!(matches!(
+        array[9..],
+        [Constant::Bool(true), Constant::Bool(false)]
+    ));
+}
+
+#[test]
+fn ffi() {
+    let data = corpus("ffi");
+    let bytecode = <Bytecode as LuaBytecode>::from(&data).unwrap();
+    assert_ne!(bytecode.header.luajit_flags & BCDUMP_F_FFI, 0);
+
+    assert!(matches!(
+        bytecode.protos[0].constants[..],
+        [
+            Constant::Complex(0.0, 1.0),
+            Constant::UInt64(u64::MAX),
+            Constant::Int64(-2)
+        ]
+    ));
+    assert_eq!(
+        bytecode.protos[0].instructions[0].luajit().opcode(2),
+        Opcode::LuaJitOpcode(LuaJitOpcode::KCData)
+    );
+}
+
+#[test]
+fn big_endian() {
+    let mut bytecode = <Bytecode as LuaBytecode>::from(&corpus("nested_debug")).unwrap();
+    let little = bytecode.clone();
+
+    bytecode.header.luajit_flags |= BCDUMP_F_BE;
+    let data = bytecode.write().unwrap();
+    assert_eq!(&data[..5], b"\x1bLJ\x02\x09");
+
+    let mut reparsed = <Bytecode as LuaBytecode>::from(&data).unwrap();
+    assert!(reparsed.header.is_big_endian);
+    for (proto, little) in reparsed.protos.iter().zip(&little.protos) {
+        assert_eq!(proto.instructions, little.instructions);
+        assert_eq!(proto.line_info, little.line_info);
+    }
+    assert_eq!(reparsed.write().unwrap(), data);
+}
+
+#[test]
+fn version_1() {
+    use lua_bytecode::Error;
+
+    // luajit 2.0 numbers its opcodes without ISTYPE and ISNUM and has no two slot frames
+    let mut bytecode = <Bytecode as LuaBytecode>::from(&corpus("nested_nogc64")).unwrap();
+    let expected = (0..3)
+        .map(|proto| opcodes(&bytecode, proto))
+        .collect::<Vec<_>>();
+
+    bytecode.header.version = 1;
+    for proto in &mut bytecode.protos {
+        for instruction in &mut proto.instructions {
+            let mut view = instruction.luajit();
+            let Opcode::LuaJitOpcode(opcode) = view.opcode(2) else {
+                unreachable!()
+            };
+            view.0 = view.0 & !0xff | opcode.to_version(1).unwrap() as u32;
+            *instruction = view.into();
+        }
+    }
+
+    let data = bytecode.write().unwrap();
+    assert_eq!(&data[..5], b"\x1bLJ\x01\x02");
+    let mut reparsed = <Bytecode as LuaBytecode>::from(&data).unwrap();
+    for (proto, expected) in expected.iter().enumerate() {
+        assert_eq!(&opcodes(&reparsed, proto), expected);
+    }
+    assert_eq!(reparsed.write().unwrap(), data);
+
+    reparsed.protos[0].instructions[0] = Instruction(93);
+    let data = reparsed.write().unwrap();
+    assert_eq!(
+        <Bytecode as LuaBytecode>::from(&data).err(),
+        Some(Error::InvalidOpcode {
+            proto: 0,
+            pc: 0,
+            opcode: 93
+        })
+    );
+
+    let mut data = data;
+    data[4] |= BCDUMP_F_FR2;
+    assert_eq!(
+        <Bytecode as LuaBytecode>::from(&data).err(),
+        Some(Error::Malformed {
+            offset: 4,
+            reason: "unknown dump flags"
+        })
+    );
+}
+
+#[test]
+fn malformed() {
+    use lua_bytecode::Error;
+
+    let mut data = corpus("nested");
+    data[4] = 0x1a;
+    assert_eq!(
+        <Bytecode as LuaBytecode>::from(&data).err(),
+        Some(Error::Malformed {
+            offset: 4,
+            reason: "unknown dump flags"
+        })
+    );
+
+    let mut data = corpus("nested");
+    data[3] = 3;
+    assert_eq!(
+        <Bytecode as LuaBytecode>::from(&data).err(),
+        Some(Error::UnsupportedVersion {
+            offset: 3,
+            version: 3
+        })
+    );
+
+    // a stripped chunk has no name, the first proto length follows the flags
+    let mut data = corpus("nested");
+    data[5] -= 1;
+    assert_eq!(
+        <Bytecode as LuaBytecode>::from(&data).err(),
+        Some(Error::Malformed {
+            offset: 5,
+            reason: "proto length does not match its contents"
+        })
+    );
+
+    let mut bytecode = <Bytecode as LuaBytecode>::from(&corpus("nested")).unwrap();
+    bytecode.protos[1].instructions[1] = Instruction(0xff);
+    let data = bytecode.write().unwrap();
+    assert_eq!(
+        <Bytecode as LuaBytecode>::from(&data).err(),
+        Some(Error::InvalidOpcode {
+            proto: 1,
+            pc: 1,
+            opcode: 0xff
+        })
+    );
+}
+
+#[test]
+fn truncated() {
+    use lua_bytecode::Error;
+
+    let data = corpus("nested_debug");
+    for length in 0..data.len() {
+        match <Bytecode as LuaBytecode>::from(&data[..length]) {
+            Err(Error::UnexpectedEof { offset }) => assert!(offset <= length as u64),
+            result => panic!("truncated at {length}: {result:?}"),
+        }
+    }
+}
+
+#[test]
+fn detect() {
+    use lua_bytecode::{Chunk, Format, detect_format, parse_any};
+
+    let data = corpus("vararg");
+    assert_eq!(detect_format(&data), Some(Format::LuaJit));
+
+    match parse_any(&data).unwrap() {
+        Chunk::LuaJit(bytecode) => assert!(bytecode.protos[0].is_vararg),
+        #[allow(unreachable_patterns)]
+        _ => unreachable!(),
+    }
+}
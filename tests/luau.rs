@@ -27,7 +27,7 @@ fn number() {
     assert_eq!(main_proto.locals.len(), 0);
     assert_eq!(main_proto.constants.len(), 2);
 
-    let data = bytecode.write();
+    let data = bytecode.write().unwrap();
 
     let bytecode = LuaBytecode::from(data.as_slice()).unwrap();
     let main_proto = &bytecode.protos[bytecode.main_proto_id as usize];
@@ -46,7 +46,7 @@ fn map_iter() {
     assert_eq!(main_proto.locals.len(), 0);
     assert_eq!(main_proto.constants.len(), 7);
 
-    let data = bytecode.write();
+    let data = bytecode.write().unwrap();
 
     let bytecode = LuaBytecode::from(data.as_slice()).unwrap();
     let main_proto = &bytecode.protos[bytecode.main_proto_id as usize];
@@ -58,7 +58,7 @@ fn map_iter() {
 
 #[test]
 fn instruction() {
-    match Instruction(0).opcode() {
+    match Instruction(0).luau().opcode() {
         lua_bytecode::opcode::Opcode::LuauOpcode(op) => {
             assert_eq!(op, LuauOpcode::Nop);
         }
@@ -66,7 +66,7 @@ fn instruction() {
         _ => unreachable!(),
     }
 
-    match Instruction(82).opcode() {
+    match Instruction(82).luau().opcode() {
         lua_bytecode::opcode::Opcode::LuauOpcode(op) => {
             assert_eq!(op, LuauOpcode::IDivK);
         }
@@ -74,7 +74,7 @@ fn instruction() {
         _ => unreachable!(),
     }
 
-    let instruction = Instruction::from_abc(Opcode::LuauOpcode(LuauOpcode::Call), 0, 1 + 1, 1);
+    let instruction = LuauInstruction::from_abc(Opcode::LuauOpcode(LuauOpcode::Call), 0, 1 + 1, 1);
     match instruction.opcode() {
         Opcode::LuauOpcode(op) => {
             assert_eq!(op, LuauOpcode::Call);
@@ -86,7 +86,7 @@ fn instruction() {
         _ => unreachable!(),
     }
 
-    let instruction = Instruction::from_ad(Opcode::LuauOpcode(LuauOpcode::LoadK), 0, 0);
+    let instruction = LuauInstruction::from_ad(Opcode::LuauOpcode(LuauOpcode::LoadK), 0, 0);
     match instruction.opcode() {
         Opcode::LuauOpcode(op) => {
             assert_eq!(op, LuauOpcode::LoadK);
@@ -97,3 +97,886 @@ fn instruction() {
         _ => unreachable!(),
     }
 }
+
+#[test]
+fn malformed() {
+    use lua_bytecode::Error;
+
+    let result = LuaBytecode::from(b"\0:1: Expected identifier");
+    assert_eq!(
+        result.err(),
+        Some(Error::CompileError(":1: Expected identifier".into()))
+    );
+
+    // a string constant that references a missing string
+    let result = LuaBytecode::from(&[6, 3, 0, 0, 1, 2, 0, 0, 0, 0, 0, 0, 1, 3, 5]);
+    assert!(matches!(result, Err(Error::InvalidStringRef { .. })));
+}
+
+fn sample() -> LuaBytecode {
+    use lua_bytecode::{Proto, constant::Constant};
+
+    let main_proto = Proto {
+        max_stack_size: 2,
+        is_vararg: true,
+        instructions: vec![
+            LuauInstruction::from_abc(Opcode::LuauOpcode(LuauOpcode::PrepVarargs), 0, 0, 0).into(),
+            LuauInstruction::from_ad(Opcode::LuauOpcode(LuauOpcode::LoadK), 0, 0).into(),
+            LuauInstruction::from_abc(Opcode::LuauOpcode(LuauOpcode::Return), 0, 1, 0).into(),
+        ],
+        constants: vec![
+            Constant::String(b"print".to_vec()),
+            Constant::Number(500.0),
+            Constant::Vector(1.0, 2.0, 3.0, 0.0),
+            Constant::Import(0x40000000),
+        ],
+        name: Some(b"main".to_vec()),
+        linegaplog2: 24,
+        line_info: vec![0, 1, 0],
+        absolute_line_info: vec![1],
+        ..Default::default()
+    };
+
+    LuaBytecode {
+        version: 6,
+        types_version: 3,
+        strings: vec![b"print".to_vec(), b"main".to_vec()],
+        protos: vec![main_proto],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn invalid_opcode() {
+    use lua_bytecode::Error;
+
+    assert_eq!(LuauOpcode::try_from(82), Ok(LuauOpcode::IDivK));
+    assert_eq!(LuauOpcode::try_from(83), Err(83));
+    assert_eq!(Instruction(0xff).luau().try_opcode(), Err(0xff));
+
+    // AUX words are not decoded as opcodes
+    let mut bytecode = sample();
+    let get_import = LuauInstruction::from_ad(Opcode::LuauOpcode(LuauOpcode::GetImport), 0, 0);
+    let code = &mut bytecode.protos[0].instructions;
+    code.splice(1..1, [get_import.into(), Instruction(0xff)]);
+    bytecode.protos[0].line_info = vec![0; 5];
+
+    let data = bytecode.write().unwrap();
+    assert!(LuaBytecode::from(&data).is_ok());
+
+    // without the preceding GETIMPORT the same word is decoded
+    bytecode.protos[0].instructions[1] =
+        LuauInstruction::from_abc(Opcode::LuauOpcode(LuauOpcode::Move), 0, 1, 0).into();
+
+    let data = bytecode.write().unwrap();
+    assert_eq!(
+        LuaBytecode::from(&data).err(),
+        Some(Error::InvalidOpcode {
+            proto: 0,
+            pc: 2,
+            opcode: 0xff
+        })
+    );
+}
+
+#[test]
+fn truncated() {
+    use lua_bytecode::Error;
+
+    let data = sample().write().unwrap();
+    assert!(LuaBytecode::from(&data).is_ok());
+
+    for length in 0..data.len() {
+        match LuaBytecode::from(&data[..length]) {
+            Err(Error::UnexpectedEof { offset }) => assert!(offset <= length as u64),
+            result => panic!("truncated at {length}: {:?}", result.err()),
+        }
+    }
+}
+
+#[test]
+fn limits() {
+    use lua_bytecode::{Error, ParseOptions};
+
+    let data = sample().write().unwrap();
+
+    let options = ParseOptions {
+        max_string_length: 4,
+        ..ParseOptions::hardened()
+    };
+    assert!(matches!(
+        LuaBytecode::from_with_options(&data, options),
+        Err(Error::LimitExceeded {
+            limit: "string length",
+            ..
+        })
+    ));
+
+    let options = ParseOptions {
+        max_protos: 0,
+        ..ParseOptions::hardened()
+    };
+    assert!(matches!(
+        LuaBytecode::from_with_options(&data, options),
+        Err(Error::LimitExceeded {
+            limit: "proto count",
+            ..
+        })
+    ));
+}
+
+#[test]
+fn mutated() {
+    use lua_bytecode::ParseOptions;
+
+    let data = sample().write().unwrap();
+    for position in 0..data.len() {
+        for value in [0x00, 0x7f, 0x80, 0xff, data[position] ^ 0x01] {
+            let mut data = data.clone();
+            data[position] = value;
+
+            if let Ok(bytecode) = LuaBytecode::from_with_options(&data, ParseOptions::hardened()) {
+                let _ = bytecode.write();
+            }
+        }
+    }
+}
+
+#[test]
+fn detect() {
+    use lua_bytecode::{Chunk, Error, Format, detect_format, parse_any};
+
+    let data = sample().write().unwrap();
+    assert_eq!(detect_format(&data), Some(Format::Luau));
+    assert_eq!(detect_format(&[6, 4]), None);
+    assert_eq!(detect_format(&[7, 3]), None);
+    assert_eq!(parse_any(&[7, 3]).err(), Some(Error::UnknownFormat));
+
+    // a failed compilation carries the compiler message after the 0 byte
+    assert_eq!(
+        detect_format(b"\0:1: Expected identifier"),
+        Some(Format::Luau)
+    );
+    assert_eq!(
+        parse_any(b"\0:1: Expected identifier").err(),
+        Some(Error::CompileError(":1: Expected identifier".into()))
+    );
+
+    match parse_any(&data).unwrap() {
+        Chunk::Luau(bytecode) => assert_eq!(bytecode.strings.len(), 2),
+        #[allow(unreachable_patterns)]
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn corpus() {
+    for entry in std::fs::read_dir("tests/corpus/luau").unwrap() {
+        let path = entry.unwrap().path();
+        let data = std::fs::read(&path).unwrap();
+
+        let bytecode = LuaBytecode::from(&data).unwrap();
+        assert_eq!(bytecode.write().unwrap(), data, "{}", path.display());
+    }
+
+    // a true stored as 2 keeps its byte
+    let data = std::fs::read("tests/corpus/luau/nonzero_bools.luau").unwrap();
+    let bytecode = LuaBytecode::from(&data).unwrap();
+    assert!(matches!(
+        bytecode.protos[0].constants[0],
+        lua_bytecode::constant::Constant::RawBool(2)
+    ));
+}
+
+#[test]
+fn tree() {
+    use lua_bytecode::ProtoTree;
+
+    let data = std::fs::read("tests/corpus/luau/types_v3.luau").unwrap();
+    let bytecode = LuaBytecode::from(&data).unwrap();
+
+    assert_eq!(bytecode.main().unwrap().bytecode_id, 1);
+    assert_eq!(bytecode.children(1), &[0]);
+    assert_eq!(bytecode.parent(0), Some(1));
+
+    let order = bytecode.depth_first().map(|(id, _)| id).collect::<Vec<_>>();
+    assert_eq!(order, vec![1, 0]);
+
+    let reparsed = LuaBytecode::from(&bytecode.write().unwrap()).unwrap();
+    assert_eq!(reparsed.main_proto_id, 1);
+    assert_eq!(reparsed.children(1), &[0]);
+}
+
+#[test]
+fn type_info() {
+    use lua_bytecode::luau::{LuauType, LuauTypeTag, LuauTypedLocal};
+
+    let tag = |kind, optional| LuauTypeTag { kind, optional };
+
+    let data = std::fs::read("tests/corpus/luau/types_v3.luau").unwrap();
+    let mut bytecode = LuaBytecode::from(&data).unwrap();
+
+    let type_info = bytecode.protos[0].type_info.clone().unwrap();
+    assert_eq!(
+        type_info.arguments,
+        Some(vec![
+            tag(LuauType::Number, false),
+            tag(LuauType::TaggedUserdata(0), true)
+        ])
+    );
+    assert_eq!(type_info.upvalues, vec![tag(LuauType::String, false)]);
+    assert_eq!(
+        type_info.locals,
+        vec![LuauTypedLocal {
+            kind: tag(LuauType::TaggedUserdata(0), false),
+            register: 0,
+            start_pc: 0,
+            end_pc: 2,
+        }]
+    );
+
+    let name = bytecode.userdata_type_name(type_info.locals[0].kind);
+    assert_eq!(name.map(|name| name.as_slice()), Some(&b"Vec"[..]));
+    assert_eq!(bytecode.userdata_type_name(type_info.upvalues[0]), None);
+    assert_eq!(bytecode.protos[1].type_info, None);
+
+    let type_info = bytecode.protos[0].type_info.as_mut().unwrap();
+    type_info.upvalues[0] = tag(LuauType::Vector, true);
+    type_info.locals.clear();
+
+    let reparsed = LuaBytecode::from(&bytecode.write().unwrap()).unwrap();
+    assert_eq!(reparsed.protos[0].type_info, bytecode.protos[0].type_info);
+}
+
+#[test]
+fn userdata_types() {
+    use lua_bytecode::Error;
+    use std::collections::BTreeMap;
+
+    let data = std::fs::read("tests/corpus/luau/types_v3.luau").unwrap();
+    let mut bytecode = LuaBytecode::from(&data).unwrap();
+    assert_eq!(bytecode.userdata_type_map, BTreeMap::from([(0, 5)]));
+
+    // sparse indices and those past the tagged range keep their place
+    bytecode.userdata_type_map = BTreeMap::from([(3, 5), (40, 1)]);
+    let data = bytecode.write().unwrap();
+    assert!(data.windows(5).any(|window| window == [4, 5, 41, 1, 0]));
+
+    let reparsed = LuaBytecode::from(&data).unwrap();
+    assert_eq!(reparsed.userdata_type_map, bytecode.userdata_type_map);
+    assert_eq!(reparsed.write().unwrap(), data);
+
+    bytecode.userdata_type_map.insert(255, 1);
+    assert_eq!(
+        bytecode.write().err(),
+        Some(Error::ValueOutOfRange {
+            proto: 1,
+            value: "userdata type index"
+        })
+    );
+}
+
+#[test]
+fn string_table() {
+    use lua_bytecode::{Error, constant::Constant, luau::StringTableMode};
+
+    let mut bytecode = sample();
+    bytecode.strings.clear();
+    bytecode.protos[0]
+        .constants
+        .push(Constant::String(b"main".to_vec()));
+
+    assert!(matches!(bytecode.write(), Err(Error::MissingString { .. })));
+
+    let rebuilt = bytecode
+        .write_with_options(StringTableMode::Rebuild)
+        .unwrap();
+    let reparsed = LuaBytecode::from(&rebuilt).unwrap();
+    assert_eq!(reparsed.strings, vec![b"print".to_vec(), b"main".to_vec()]);
+    assert_eq!(
+        format!("{:?}", reparsed.protos[0].constants),
+        format!("{:?}", bytecode.protos[0].constants)
+    );
+    assert_eq!(reparsed.protos[0].name, bytecode.protos[0].name);
+
+    let compacted = bytecode
+        .write_with_options(StringTableMode::Compact)
+        .unwrap();
+    let reparsed = LuaBytecode::from(&compacted).unwrap();
+    assert_eq!(reparsed.strings, vec![b"main".to_vec(), b"print".to_vec()]);
+    assert_eq!(
+        format!("{:?}", reparsed.protos[0].constants),
+        format!("{:?}", bytecode.protos[0].constants)
+    );
+
+    // the userdata type map follows its names into the new table
+    let data = std::fs::read("tests/corpus/luau/types_v3.luau").unwrap();
+    let bytecode = LuaBytecode::from(&data).unwrap();
+    let reparsed = LuaBytecode::from(
+        &bytecode
+            .write_with_options(StringTableMode::Compact)
+            .unwrap(),
+    )
+    .unwrap();
+    let tag = reparsed.protos[0].type_info.as_ref().unwrap().locals[0].kind;
+    assert_eq!(
+        reparsed.userdata_type_name(tag).map(|name| name.as_slice()),
+        Some(&b"Vec"[..])
+    );
+}
+
+#[test]
+fn import() {
+    use lua_bytecode::{constant::Constant, luau::LuauImport};
+
+    let constants = vec![
+        Constant::String(b"game".to_vec()),
+        Constant::String(b"Workspace".to_vec()),
+        Constant::Number(1.0),
+        Constant::String(b"Part".to_vec()),
+    ];
+
+    let import = LuauImport::new(&[0, 1, 3]).unwrap();
+    let raw = i32::from(import);
+    assert_eq!(raw as u32, 3 << 30 | 1 << 10 | 3);
+    assert_eq!(LuauImport::from(raw), import);
+    assert_eq!(
+        import.resolve(&constants),
+        Some(vec![
+            b"game".to_vec(),
+            b"Workspace".to_vec(),
+            b"Part".to_vec()
+        ])
+    );
+
+    let import = LuauImport::from(0x40000000);
+    assert_eq!(import.ids(), &[0]);
+    assert_eq!(import.resolve(&constants), Some(vec![b"game".to_vec()]));
+
+    assert_eq!(LuauImport::new(&[2]).unwrap().resolve(&constants), None);
+    assert_eq!(LuauImport::new(&[]), None);
+    assert_eq!(LuauImport::new(&[1024]), None);
+}
+
+#[test]
+fn signed_operands() {
+    let mut jump = LuauInstruction::from_ad(Opcode::LuauOpcode(LuauOpcode::JumpBack), 0, -3);
+    assert_eq!(jump.d(), -3);
+
+    jump.set_d(-32768);
+    assert_eq!(jump.d(), -32768);
+    assert_eq!(jump.opcode(), Opcode::LuauOpcode(LuauOpcode::JumpBack));
+
+    jump.set_a(7);
+    assert_eq!((jump.a(), jump.d()), (7, -32768));
+    assert_eq!(jump.set_a(1).set_d(-1).d(), -1);
+
+    let mut jump = LuauInstruction::from_e(Opcode::LuauOpcode(LuauOpcode::JumpX), -5);
+    assert_eq!(jump.e(), -5);
+    jump.set_e(0x7fffff);
+    assert_eq!(jump.e(), 0x7fffff);
+    assert_eq!(jump.opcode(), Opcode::LuauOpcode(LuauOpcode::JumpX));
+
+    let mut call = LuauInstruction::from_abc(Opcode::LuauOpcode(LuauOpcode::Call), 1, 2, 3);
+    call.set_b(0xff);
+    call.set_c(0);
+    assert_eq!((call.a(), call.b(), call.c()), (1, 0xff, 0));
+
+    let instructions = [
+        LuauInstruction::from_ad(Opcode::LuauOpcode(LuauOpcode::GetImport), 0, 1).into(),
+        Instruction(0x40000000),
+        LuauInstruction::from_abc(Opcode::LuauOpcode(LuauOpcode::Return), 0, 1, 0).into(),
+    ];
+    assert_eq!(LuauInstruction::aux(&instructions, 0), Some(0x40000000));
+    assert_eq!(LuauInstruction::aux(&instructions, 2), None);
+    assert_eq!(LuauInstruction::aux(&instructions, 3), None);
+}
+
+#[test]
+fn decode_instructions() {
+    use lua_bytecode::{
+        Error,
+        luau::{DecodedInstruction, Operands, decode_instructions},
+    };
+
+    let instructions = [
+        LuauInstruction::from_ad(Opcode::LuauOpcode(LuauOpcode::GetImport), 1, 0).into(),
+        Instruction(0x40000000),
+        LuauInstruction::from_ad(Opcode::LuauOpcode(LuauOpcode::JumpBack), 0, -3).into(),
+        LuauInstruction::from_abc(Opcode::LuauOpcode(LuauOpcode::Return), 0, 1, 0).into(),
+    ];
+
+    let decoded = decode_instructions(0, &instructions)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(
+        decoded,
+        vec![
+            (
+                0,
+                DecodedInstruction {
+                    opcode: LuauOpcode::GetImport,
+                    operands: Operands::AD { a: 1, d: 0 },
+                    aux: Some(0x40000000),
+                }
+            ),
+            (
+                2,
+                DecodedInstruction {
+                    opcode: LuauOpcode::JumpBack,
+                    operands: Operands::AD { a: 0, d: -3 },
+                    aux: None,
+                }
+            ),
+            (
+                3,
+                DecodedInstruction {
+                    opcode: LuauOpcode::Return,
+                    operands: Operands::ABC { a: 0, b: 1, c: 0 },
+                    aux: None,
+                }
+            ),
+        ]
+    );
+
+    let mut decoded = decode_instructions(4, &instructions[..1]);
+    assert_eq!(
+        decoded.next(),
+        Some(Err(Error::TruncatedInstruction { proto: 4, pc: 0 }))
+    );
+    assert_eq!(decoded.next(), None);
+}
+
+#[test]
+fn opcode_info() {
+    use lua_bytecode::opcode::{LuauOpMode, LuauOperand};
+
+    for op in 0..=u8::MAX {
+        let Ok(opcode) = LuauOpcode::try_from(op) else {
+            continue;
+        };
+
+        let info = opcode.info();
+        assert!(
+            info.name
+                .bytes()
+                .all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit() || byte == b'_')
+        );
+        assert!(
+            !info.ends_block || info.branches || info.returns,
+            "{}",
+            info.name
+        );
+    }
+
+    let info = LuauOpcode::GetImport.info();
+    assert_eq!(info.name, "GETIMPORT");
+    assert_eq!(info.mode, LuauOpMode::AD);
+    assert_eq!(
+        (info.a, info.d, info.aux),
+        (
+            LuauOperand::Register,
+            LuauOperand::Constant,
+            LuauOperand::Import
+        )
+    );
+
+    let info = LuauOpcode::ForGPrepInext.info();
+    assert_eq!(info.name, "FORGPREP_INEXT");
+    assert!(info.branches && info.ends_block);
+
+    let info = LuauOpcode::Return.info();
+    assert!(info.returns && info.ends_block && !info.calls);
+
+    let info = LuauOpcode::JumpX.info();
+    assert_eq!((info.mode, info.e), (LuauOpMode::E, LuauOperand::Jump));
+
+    assert_eq!(LuauOpcode::JumpXeqkNil.length(), 2);
+    assert!(LuauOpcode::Call.info().calls);
+    assert!(!LuauOpcode::JumpIfNot.info().ends_block);
+}
+
+#[test]
+fn disasm() {
+    use lua_bytecode::{Proto, constant::Constant, luau::disasm::disassemble};
+
+    let op = |op| Opcode::LuauOpcode(op);
+    let proto = Proto {
+        max_stack_size: 3,
+        instructions: vec![
+            LuauInstruction::from_ad(op(LuauOpcode::GetImport), 0, 2).into(),
+            Instruction(0x80000000 | 1 << 10),
+            LuauInstruction::from_ad(op(LuauOpcode::JumpIfNot), 0, 4).into(),
+            LuauInstruction::from_ad(op(LuauOpcode::LoadN), 1, -2).into(),
+            LuauInstruction::from_abc(op(LuauOpcode::FastCall1), 2, 1, 0).into(),
+            LuauInstruction::from_abc(op(LuauOpcode::Call), 0, 2, 2).into(),
+            LuauInstruction::from_ad(op(LuauOpcode::JumpBack), 0, -5).into(),
+            LuauInstruction::from_abc(op(LuauOpcode::Return), 0, 1, 0).into(),
+        ],
+        constants: vec![
+            Constant::String(b"math".to_vec()),
+            Constant::String(b"abs".to_vec()),
+            Constant::Import(0x80000000u32 as i32 | 1 << 10),
+        ],
+        linegaplog2: 24,
+        line_info: vec![0, 0, 1, 0, 0, 0, 1, 1],
+        absolute_line_info: vec![3],
+        ..Default::default()
+    };
+
+    let bytecode = LuaBytecode {
+        version: 6,
+        types_version: 1,
+        protos: vec![proto],
+        ..Default::default()
+    };
+
+    assert_eq!(
+        disassemble(&bytecode).unwrap(),
+        "Function 0 (??):
+3: GETIMPORT R0 2 [math.abs]
+4: L0: JUMPIFNOT R0 L2
+4: LOADN R1 -2
+4: FASTCALL1 math.abs R1 L1
+4: CALL R0 1 1
+5: L1: JUMPBACK L0
+6: L2: RETURN R0 0
+
+"
+    );
+
+    // a hash size past 2^63 and an anonymous closure
+    let proto = Proto {
+        instructions: vec![
+            LuauInstruction::from_abc(op(LuauOpcode::NewTable), 0, 70, 0).into(),
+            Instruction(0),
+            LuauInstruction::from_ad(op(LuauOpcode::DupClosure), 1, 0).into(),
+        ],
+        constants: vec![Constant::Closure(0)],
+        ..Default::default()
+    };
+    let bytecode = LuaBytecode {
+        version: 6,
+        protos: vec![proto],
+        ..Default::default()
+    };
+
+    let listing = disassemble(&bytecode).unwrap();
+    assert!(listing.contains("NEWTABLE R0 70 0\n"));
+    assert!(listing.contains("DUPCLOSURE R1 K0 []\n"));
+}
+
+#[test]
+fn asm() {
+    use lua_bytecode::{
+        Error, Proto,
+        constant::Constant,
+        luau::{
+            LuauImport, LuauType, StringTableMode, asm, disasm::disassemble, instruction_lines,
+            set_instruction_lines,
+        },
+    };
+
+    // the listing of the disasm test
+    let source = r#"
+.typesversion 1
+.stack 3
+.const math "math"
+.const abs "abs"
+.const path import math.abs
+
+3: GETIMPORT R0 2 [math.abs]
+4: L0: JUMPIFNOT R0 L2
+4: LOADN R1 -2
+4: FASTCALL1 math.abs R1 L1
+4: CALL R0 1 1
+5: L1: JUMPBACK L0
+6: L2: RETURN R0 0
+"#;
+    let bytecode = asm::assemble(source).unwrap();
+    let listing = disassemble(&bytecode).unwrap();
+    assert!(listing.starts_with("Function 0 (??):\n3: GETIMPORT R0 2 [math.abs]\n"));
+    assert!(listing.ends_with("5: L1: JUMPBACK L0\n6: L2: RETURN R0 0\n\n"));
+
+    let proto = &bytecode.protos[0];
+    assert_eq!(instruction_lines(proto), vec![3, 3, 4, 4, 4, 4, 5, 6]);
+    assert_eq!(proto.line_info, vec![0, 0, 1, 0, 0, 0, 1, 1]);
+    assert_eq!(proto.absolute_line_info, vec![3]);
+    assert_eq!(proto.instructions[1].0, 0x80000000 | 1 << 10);
+
+    // lines too far apart for one baseline split the intervals
+    let mut proto = proto.clone();
+    let lines = [10, 900, 11, 12, 2000, 2001, 5, 5];
+    set_instruction_lines(&mut proto, &lines);
+    assert_eq!(instruction_lines(&proto), lines);
+    assert!(proto.absolute_line_info.len() > 1);
+
+    let source = r#"
+.userdata Vec3
+
+.function scale
+    .params 2
+    .upvalue factor
+    .argtypes Vec3 number?
+    .upvaltypes number
+    .local v R0 0 done
+    .localtype R2 0 done number
+        GETUPVAL R2 U0
+        MUL R2 R1 R2
+        NAMECALL R3 R0 "scale"
+        CALL R3 2 1
+done:   RETURN R3 1
+.end
+
+.const f closure scale
+        .line 1
+        DUPCLOSURE R0 $f
+        NEWCLOSURE R1 scale
+        CAPTURE VAL R0
+        GETIMPORT R2 vector.create
+        JUMPXEQKS R2 "x" skip NOT
+        LOADB R3 1 skip
+        LOADNIL R3
+skip:   RETURN R0 0
+"#;
+    let data = asm::assemble_bytes(source).unwrap();
+    let bytecode = LuaBytecode::from(&data).unwrap();
+    assert_eq!(bytecode.protos.len(), 2);
+    assert_eq!(bytecode.main_proto_id, 1);
+    assert_eq!(
+        bytecode.strings,
+        [
+            &b"Vec3"[..],
+            b"scale",
+            b"v",
+            b"factor",
+            b"vector",
+            b"create",
+            b"x"
+        ]
+    );
+
+    let scale = &bytecode.protos[0];
+    assert_eq!(scale.name.as_deref(), Some(&b"scale"[..]));
+    assert_eq!(
+        (
+            scale.parameter_count,
+            scale.upvalue_count,
+            scale.max_stack_size
+        ),
+        (2, 1, 6)
+    );
+    assert_eq!(scale.instructions[3].luau().c(), 0);
+    assert_eq!(LuauInstruction::aux(&scale.instructions, 2), Some(0));
+    assert!(scale.line_info.is_empty());
+
+    let type_info = scale.type_info.as_ref().unwrap();
+    let arguments = type_info.arguments.as_ref().unwrap();
+    assert_eq!(arguments[0].kind, LuauType::TaggedUserdata(0));
+    assert!(arguments[1].optional);
+    assert_eq!(type_info.locals[0].end_pc, 5);
+    assert_eq!(
+        bytecode.userdata_type_name(arguments[0]).map(Vec::as_slice),
+        Some(&b"Vec3"[..])
+    );
+
+    let main: &Proto = &bytecode.protos[1];
+    assert_eq!(main.protos, vec![0]);
+    assert_eq!(
+        format!("{:?}", main.constants[0]),
+        format!("{:?}", Constant::Closure(0))
+    );
+    assert_eq!(
+        LuauImport::from(LuauInstruction::aux(&main.instructions, 3).unwrap() as i32).ids(),
+        [1, 2]
+    );
+
+    let listing = disassemble(&bytecode).unwrap();
+    assert!(listing.contains("1: GETIMPORT R2 3 [vector.create]\n"));
+    assert!(listing.contains("1: JUMPXEQKS R2 K4 ['x'] L0 NOT\n"));
+    assert!(listing.contains("1: LOADB R3 1 +1\n1: LOADNIL R3\n1: L0: RETURN R0 0\n"));
+
+    // the rebuilt table is the one the chunk already carries
+    assert_eq!(
+        bytecode
+            .write_with_options(StringTableMode::Preserve)
+            .unwrap(),
+        data
+    );
+
+    // both bit32.extract builtins keep their ids through a listing
+    let source = r#"
+.const width 8
+    FASTCALL2K builtin59 R1 $width L0
+    CALL R0 2 1
+L0: FASTCALL2K bit32.extract R1 $width L1
+    CALL R0 2 1
+L1: RETURN R0 0
+"#;
+    let bytecode = asm::assemble(source).unwrap();
+    let listing = disassemble(&bytecode).unwrap();
+    assert!(listing.contains("FASTCALL2K bit32.extractk R1 K0 [8] L0\n"));
+    assert!(listing.contains("FASTCALL2K bit32.extract R1 K0 [8] L1\n"));
+    let body = listing.lines().skip(1).collect::<Vec<_>>().join("\n");
+    let reassembled = asm::assemble(&format!(".const width 8\n{body}")).unwrap();
+    assert_eq!(
+        reassembled.protos[0].instructions,
+        bytecode.protos[0].instructions
+    );
+
+    let line = |source: &str| match asm::assemble(source) {
+        Err(Error::Assembly { line, .. }) => line,
+        result => panic!("expected an assembly error, got {result:?}"),
+    };
+    assert_eq!(line("MOVE R0 R1\nFOO R1"), 2);
+    assert_eq!(line("CALL R0 1"), 1);
+    assert_eq!(line("MOVE R0 R1 R2"), 1);
+    assert_eq!(line("\nJUMP nowhere"), 2);
+    assert_eq!(line("GETIMPORT R0 a.b.c.d"), 1);
+    assert_eq!(line("FASTCALL nothing L0"), 1);
+    assert_eq!(line("NEWCLOSURE R0 missing"), 1);
+    assert_eq!(line(".end"), 1);
+
+    // raw offsets have to fit the field they end up in
+    assert_eq!(line("MOVE R0 R1\nLOADB R0 1 +300"), 2);
+    assert_eq!(line("LOADB R0 1 -1"), 1);
+    assert_eq!(line("FASTCALL1 math.abs R1 256"), 1);
+    assert_eq!(line("JUMP 70000"), 1);
+    assert_eq!(line("JUMPX 8388608"), 1);
+    let bytecode = asm::assemble("JUMPX 70000").unwrap();
+    assert_eq!(bytecode.protos[0].instructions[0].luau().e(), 70000);
+}
+
+#[test]
+fn listing_round_trip() {
+    use lua_bytecode::luau::{asm, disasm::disassemble};
+
+    let constants = r#"
+.const quote "it's \\ here"
+.const long "it's a string past the preview limit"
+"#;
+    let source = format!(
+        "{constants}
+    GETIMPORT R0 string.len
+    LOADK R1 $quote
+    CALL R0 1 1
+    LOADK R1 $long
+    GETIMPORT R2 math.abs
+    RETURN R0 1
+"
+    );
+    let data = asm::assemble_bytes(&source).unwrap();
+    let bytecode = LuaBytecode::from(&data).unwrap();
+
+    let listing = disassemble(&bytecode).unwrap();
+    assert!(listing.contains(r"LOADK R1 K0 ['it\'s \\ here']"));
+    assert!(listing.contains(r"LOADK R1 K1 ['it\'s a string past the preview l'...]"));
+    assert!(listing.contains("GETIMPORT R2 7 [math.abs]\n"));
+
+    // the imports are rebuilt from their paths, only the strings are declared again
+    let body = listing.lines().skip(1).collect::<Vec<_>>().join("\n");
+    let reassembled = asm::assemble_bytes(&format!("{constants}{body}")).unwrap();
+    assert_eq!(reassembled, data);
+}
+
+#[test]
+fn builder() {
+    use lua_bytecode::{
+        Error,
+        constant::Constant,
+        luau::{builder::ProtoBuilder, instruction_lines},
+    };
+
+    let mut builder = ProtoBuilder::new();
+    builder.set_line(1);
+    let print = builder.constant(Constant::String(b"print".to_vec()));
+    assert_eq!(builder.constant(Constant::Number(1.0)), 1);
+    assert_eq!(builder.constant(Constant::String(b"print".to_vec())), print);
+
+    let top = builder.label();
+    let skip = builder.label();
+    let after = builder.label();
+    let done = builder.label();
+    builder.emit_ad(LuauOpcode::LoadN, 0, 0).unwrap();
+    builder.emit_abc(LuauOpcode::GetGlobal, 2, 0, 0).unwrap();
+    builder.emit_aux(print);
+    builder.bind(top).unwrap();
+    builder.set_line(2);
+    builder.emit_jump(LuauOpcode::JumpIfNot, 0, done).unwrap();
+    builder.emit_skip(LuauOpcode::LoadB, 1, 1, skip).unwrap();
+    builder.emit_abc(LuauOpcode::LoadB, 1, 0, 0).unwrap();
+    builder.bind(skip).unwrap();
+    builder
+        .emit_skip(LuauOpcode::FastCall1, 1, 1, after)
+        .unwrap();
+    builder.emit_abc(LuauOpcode::Move, 3, 1, 0).unwrap();
+    builder.emit_abc(LuauOpcode::Call, 2, 2, 1).unwrap();
+    builder.bind(after).unwrap();
+    builder.emit_jump(LuauOpcode::JumpBack, 0, top).unwrap();
+    builder.bind(done).unwrap();
+    builder.set_line(3);
+    builder.emit_abc(LuauOpcode::Return, 0, 1, 0).unwrap();
+
+    let proto = builder.finish().unwrap();
+    let instructions = &proto.instructions;
+    assert_eq!(instructions.len(), 11);
+    assert_eq!(LuauInstruction::aux(instructions, 1), Some(print));
+    assert_eq!(instructions[3].luau().d(), 6);
+    assert_eq!(instructions[4].luau().c(), 1);
+    // fast calls skip past their CALL
+    assert_eq!(instructions[6].luau().c(), 1);
+    assert_eq!(instructions[9].luau().d(), -7);
+    assert_eq!(proto.max_stack_size, 4);
+    assert_eq!(
+        instruction_lines(&proto),
+        vec![1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 3]
+    );
+
+    let mut builder = ProtoBuilder::new();
+    let nowhere = builder.label();
+    builder.emit_jump(LuauOpcode::JumpX, 0, nowhere).unwrap();
+    assert_eq!(
+        builder.finish().unwrap_err(),
+        Error::UnboundLabel { label: 0 }
+    );
+
+    // a label of another builder fails even when its index exists here
+    let foreign = ProtoBuilder::new().label();
+    let mut builder = ProtoBuilder::new();
+    builder.label();
+    assert_eq!(builder.bind(foreign), Err(Error::UnknownLabel { label: 0 }));
+
+    assert_eq!(
+        builder.emit_abc(LuauOpcode::Move, 0, 300, 0),
+        Err(Error::OperandOutOfRange {
+            pc: 0,
+            operand: "B"
+        })
+    );
+    assert_eq!(
+        builder.emit_ad(LuauOpcode::LoadN, 0, 1 << 15),
+        Err(Error::OperandOutOfRange {
+            pc: 0,
+            operand: "D"
+        })
+    );
+
+    // 254 results from R2 reach past the last register
+    builder.emit_abc(LuauOpcode::Call, 2, 1, 255).unwrap();
+    assert_eq!(
+        builder.finish().unwrap_err(),
+        Error::StackOverflow { size: 256 }
+    );
+
+    let mut builder = ProtoBuilder::new();
+    let far = builder.label();
+    builder.emit_skip(LuauOpcode::LoadB, 0, 1, far).unwrap();
+    for _ in 0..300 {
+        builder.emit_abc(LuauOpcode::Nop, 0, 0, 0).unwrap();
+    }
+    builder.bind(far).unwrap();
+    assert_eq!(
+        builder.finish().unwrap_err(),
+        Error::JumpOutOfRange { pc: 0 }
+    );
+}